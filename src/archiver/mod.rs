@@ -14,34 +14,59 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-mod processor;
+pub(crate) mod processor;
 pub mod tree_serializer;
 
-use std::{collections::BTreeSet, path::PathBuf, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::{Result, anyhow, bail};
 use chrono::Local;
-use tree_serializer::finalize_if_complete;
+use tree_serializer::{PendingTree, finalize_if_complete};
 
 use crate::{
-    global::ID,
+    global::{FileType, ID, defaults::SHORT_SNAPSHOT_ID_LEN},
     repository::{
         repo::Repository,
         snapshot::Snapshot,
         streamers::{
-            FSNodeStreamer, NodeDiff, NodeDiffStreamer, SerializedNodeStreamer, StreamNode,
+            ExcludeOptions, FSNodeStreamer, NodeDiff, NodeDiffStreamer, SerializedNodeStreamer,
+            StreamNode,
         },
+        tree::ChangeDetection,
     },
     ui::{self, snapshot_progress::SnapshotProgressReporter},
+    utils::{self, priority::ThreadPriority, rate_limit::RateLimiter},
 };
 
+use processor::SaveFileOptions;
+
 pub struct SnapshotOptions {
     pub absolute_source_paths: Vec<PathBuf>,
     pub snapshot_root_path: PathBuf,
     pub exclude_paths: Vec<PathBuf>,
+    pub exclude_options: ExcludeOptions,
     pub parent_snapshot: Option<(ID, Snapshot)>,
     pub tags: BTreeSet<String>,
     pub description: Option<String>,
+    /// Host name recorded in the snapshot.
+    pub hostname: String,
+    /// Time between checkpoint snapshots. No checkpoints are saved if None.
+    pub checkpoint_interval: Option<Duration>,
+    /// Number of times a file that changed while being read is read again.
+    pub changed_file_retries: usize,
+    /// Maximum rate at which the source files are read, in bytes per second.
+    pub read_limit: Option<u64>,
+    /// How the archiver decides whether an item changed since the parent snapshot.
+    pub change_detection: ChangeDetection,
+    /// Priority of the reader, encoder and pack saver threads.
+    pub priority: ThreadPriority,
+    /// Number of threads that hash, compress and encrypt the data. Defaults to the number of CPUs.
+    pub max_cpu_threads: Option<usize>,
 }
 
 pub struct Archiver {
@@ -88,6 +113,7 @@ impl Archiver {
         let fs_streamer = match FSNodeStreamer::from_paths(
             arch.snapshot_options.absolute_source_paths.clone(),
             arch.snapshot_options.exclude_paths.clone(),
+            arch.snapshot_options.exclude_options.clone(),
         ) {
             Ok(stream) => stream,
             Err(e) => bail!("Failed to create FSNodeStreamer: {:?}", e.to_string()),
@@ -100,7 +126,8 @@ impl Archiver {
             None,
         )?;
 
-        arch.repo.init_pack_saver(arch.write_concurrency);
+        arch.repo
+            .init_pack_saver(arch.write_concurrency, arch.snapshot_options.priority);
 
         // Channels
         let (diff_tx, diff_rx) = crossbeam_channel::bounded::<(
@@ -115,8 +142,10 @@ impl Archiver {
         // Diff thread. This thread iterates the NodeDiffStreamer and passes the
         // items to the item processor thread.
         let diff_progress_reporter_clone = arch.progress_reporter.clone();
+        let change_detection = arch.snapshot_options.change_detection;
         let diff_thread = std::thread::spawn(move || {
-            let diff_streamer = NodeDiffStreamer::new(previous_tree_streamer, fs_streamer);
+            let diff_streamer = NodeDiffStreamer::new(previous_tree_streamer, fs_streamer)
+                .with_change_detection(change_detection);
 
             for diff_result in diff_streamer {
                 if let Ok((path, prev, next, diff)) = diff_result {
@@ -142,9 +171,38 @@ impl Archiver {
         let repo_clone = arch.repo.clone();
         let processor_progress_reporter_clone = arch.progress_reporter.clone();
         let snapshot_root_path_clone = arch.snapshot_options.snapshot_root_path.clone();
+        let priority = arch.snapshot_options.priority;
+
+        // The reader threads hand the data over to a separate pool, so the CPU usage can be
+        // bounded independently of the number of files read in parallel.
+        let cpu_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(
+                arch.snapshot_options
+                    .max_cpu_threads
+                    .unwrap_or_else(num_cpus::get),
+            )
+            .start_handler(move |_| {
+                let _ = priority.apply();
+            })
+            .build()
+            .expect("Failed to build thread pool");
+
+        let save_file_options = Arc::new(SaveFileOptions {
+            changed_file_retries: arch.snapshot_options.changed_file_retries,
+            read_limiter: arch
+                .snapshot_options
+                .read_limit
+                .filter(|&limit| limit > 0)
+                .map(|limit| Arc::new(RateLimiter::new(limit))),
+            cpu_pool,
+        });
 
         let pool = rayon::ThreadPoolBuilder::new()
             .num_threads(arch.read_concurrency)
+            .start_handler(move |_| {
+                // The priority is validated before starting, so errors can be ignored.
+                let _ = priority.apply();
+            })
             .build()
             .expect("Failed to build thread pool");
 
@@ -155,6 +213,7 @@ impl Archiver {
                     let inner_repo_clone = repo_clone.clone();
                     let inner_progress_reporter_clone = processor_progress_reporter_clone.clone();
                     let inner_snapshot_root_path_clone = snapshot_root_path_clone.clone();
+                    let inner_save_file_options_clone = save_file_options.clone();
 
                     s.spawn(move |_| {
                         let stripped_path = path.strip_prefix(&inner_snapshot_root_path_clone).unwrap().to_path_buf();
@@ -165,6 +224,7 @@ impl Archiver {
                         let processed_item_result = processor::process_item(
                             (path, prev, next, diff),
                             inner_repo_clone,
+                            &inner_save_file_options_clone,
                             inner_progress_reporter_clone.clone(),
                         );
 
@@ -202,8 +262,10 @@ impl Archiver {
         let serializer_progress_reporter_clone = arch.progress_reporter.clone();
         let serializer_snapshot_root_path_clone = arch.snapshot_options.snapshot_root_path.clone();
         let arch_clone = arch.clone();
-        let tree_serializer_thread = std::thread::spawn(move || -> Option<ID> {
+        let tree_serializer_thread = std::thread::spawn(move || -> (Option<ID>, Option<ID>) {
             let mut final_root_tree_id: Option<ID> = None;
+            let mut checkpoint_id: Option<ID> = None;
+            let mut last_checkpoint = Instant::now();
             let mut pending_trees = tree_serializer::init_pending_trees(
                 &serializer_snapshot_root_path_clone,
                 &arch_clone.snapshot_options.absolute_source_paths,
@@ -235,6 +297,16 @@ impl Archiver {
                         );
                     }
                 }
+
+                if let Some(interval) = arch_clone.snapshot_options.checkpoint_interval
+                    && last_checkpoint.elapsed() >= interval
+                {
+                    match arch_clone.save_checkpoint(&pending_trees, checkpoint_id.as_ref()) {
+                        Ok(id) => checkpoint_id = Some(id),
+                        Err(e) => ui::cli::warning!("Could not save checkpoint: {}", e),
+                    }
+                    last_checkpoint = Instant::now();
+                }
             }
 
             // After the loop, if no error occurred, finalize the root tree.
@@ -252,13 +324,13 @@ impl Archiver {
                 );
             }
 
-            final_root_tree_id
+            (final_root_tree_id, checkpoint_id)
         });
 
         // Join threads
         let _ = diff_thread.join();
         let _ = processor_thread.join();
-        let root_tree_id = tree_serializer_thread.join().unwrap();
+        let (root_tree_id, checkpoint_id) = tree_serializer_thread.join().unwrap();
 
         // Unwrap the archiver Arc to avoid cloning the contents.
         // Archiver cannot implement Debug, so unwrap is not available.
@@ -277,26 +349,233 @@ impl Archiver {
             .written_meta_bytes(flushed_raw_meta_size, flushed_encode_meta_size);
         archiver.repo.finalize_pack_saver();
 
+        // The checkpoint is no longer needed once all the data has been saved.
+        if let Some(id) = checkpoint_id
+            && let Err(e) = archiver.repo.remove_snapshot(&id)
+        {
+            ui::cli::warning!("Could not remove checkpoint: {}", e);
+        }
+
         match root_tree_id {
-            Some(tree_id) => Ok(Snapshot {
-                timestamp: Local::now(),
-                parent: archiver
-                    .snapshot_options
-                    .parent_snapshot
-                    .map(|(id, _)| id.clone()),
-                tree: tree_id,
-                root: archiver.snapshot_options.snapshot_root_path,
-                paths: archiver.snapshot_options.absolute_source_paths,
-                tags: archiver.snapshot_options.tags,
-                description: archiver.snapshot_options.description,
-                summary: archiver.progress_reporter.get_summary(),
-            }),
+            Some(tree_id) => Ok(archiver.build_snapshot(tree_id, false)),
             None => Err(anyhow!(
                 "Failed to finalize snapshot: No root tree ID was generated."
             )),
         }
     }
+
+    fn build_snapshot(&self, tree_id: ID, checkpoint: bool) -> Snapshot {
+        let (uid, gid) = utils::host::user_ids();
+        Snapshot {
+            timestamp: Local::now(),
+            parent: self
+                .snapshot_options
+                .parent_snapshot
+                .as_ref()
+                .map(|(id, _)| id.clone()),
+            tree: tree_id,
+            root: self.snapshot_options.snapshot_root_path.clone(),
+            paths: self.snapshot_options.absolute_source_paths.clone(),
+            tags: self.snapshot_options.tags.clone(),
+            description: self.snapshot_options.description.clone(),
+            hostname: self.snapshot_options.hostname.clone(),
+            username: utils::host::username(),
+            uid,
+            gid,
+            program_version: utils::host::program_version(),
+            checkpoint,
+            summary: self.progress_reporter.get_summary(),
+        }
+    }
+
+    /// Saves a checkpoint snapshot with the items processed so far and removes the previous
+    /// checkpoint, if any. All data referenced by the checkpoint is written to the backend
+    /// before the checkpoint itself, so that a later snapshot can use it as a parent and
+    /// reuse the blobs already uploaded.
+    fn save_checkpoint(
+        &self,
+        pending_trees: &HashMap<PathBuf, PendingTree>,
+        previous_checkpoint_id: Option<&ID>,
+    ) -> Result<ID> {
+        let (tree_id, (raw_tree_size, encoded_tree_size)) = tree_serializer::save_partial_tree(
+            self.repo.as_ref(),
+            pending_trees,
+            &self.snapshot_options.snapshot_root_path,
+        )?;
+        self.progress_reporter
+            .written_meta_bytes(raw_tree_size, encoded_tree_size);
+
+        let (flushed_raw_meta_size, flushed_encoded_meta_size) = self.repo.flush()?;
+        self.progress_reporter
+            .written_meta_bytes(flushed_raw_meta_size, flushed_encoded_meta_size);
+        self.repo.wait_pack_saver();
+
+        let checkpoint = self.build_snapshot(tree_id, true);
+        let (checkpoint_id, _, _) = self.repo.save_file(
+            FileType::Snapshot,
+            serde_json::to_string(&checkpoint)?.as_bytes(),
+        )?;
+
+        if let Some(id) = previous_checkpoint_id {
+            self.repo.remove_snapshot(id)?;
+        }
+
+        ui::cli::verbose_1!(
+            "Saved checkpoint {}",
+            checkpoint_id.to_short_hex(SHORT_SNAPSHOT_ID_LEN)
+        );
+
+        Ok(checkpoint_id)
+    }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::{
+        backend::localfs::LocalFS,
+        global::{BlobType, SaveID},
+        repository::{
+            repo::RepoConfig,
+            storage::{Compression, CompressionMode},
+        },
+    };
+
+    use super::*;
+
+    /// Test that an interrupted snapshot resumes from a checkpoint with pending trees
+    #[test]
+    fn test_resume_from_partial_checkpoint() -> Result<()> {
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path().canonicalize()?;
+
+        let source_path = tmp_path.join("data");
+        std::fs::create_dir_all(source_path.join("a"))?;
+        std::fs::write(source_path.join("a").join("1.txt"), "mapache")?;
+        std::fs::write(source_path.join("a").join("2.txt"), "backup")?;
+        std::fs::write(source_path.join("b.txt"), "rust")?;
+
+        let password = Some(String::from("mapachito"));
+        let backend = Arc::new(LocalFS::new(tmp_path.join("repo")));
+        Repository::init(
+            password.clone(),
+            None,
+            backend.clone(),
+            Compression::default(),
+            CompressionMode::Auto,
+        )?;
+        let (repo, _) = Repository::try_open(password, None, backend, RepoConfig::default())?;
+
+        let snapshot_options = |parent_snapshot| SnapshotOptions {
+            absolute_source_paths: vec![source_path.clone()],
+            snapshot_root_path: tmp_path.clone(),
+            exclude_paths: Vec::new(),
+            exclude_options: ExcludeOptions::default(),
+            parent_snapshot,
+            tags: BTreeSet::new(),
+            description: None,
+            hostname: String::new(),
+            checkpoint_interval: None,
+            changed_file_retries: 0,
+            read_limit: None,
+            change_detection: ChangeDetection::default(),
+            priority: ThreadPriority::default(),
+            max_cpu_threads: None,
+        };
+
+        // Interrupt the snapshot after the first file: 'data' and 'data/a' are still pending
+        let archiver = Archiver::new(
+            repo.clone(),
+            snapshot_options(None),
+            (1, 1),
+            Arc::new(SnapshotProgressReporter::new(0, 0, 0)),
+        );
+        repo.init_pack_saver(1, ThreadPriority::default());
+        let mut pending_trees =
+            tree_serializer::init_pending_trees(&tmp_path, std::slice::from_ref(&source_path));
+        let mut root_tree_id = None;
+        let fs_streamer =
+            FSNodeStreamer::from_paths(vec![source_path.clone()], Vec::new(), Default::default())?;
+        for item in fs_streamer.take(3) {
+            let (path, mut stream_node) = item?;
+            if stream_node.node.is_file() {
+                let (blob_id, _, _) = repo.encode_and_save_blob(
+                    BlobType::Data,
+                    std::fs::read(&path)?,
+                    SaveID::CalculateID,
+                )?;
+                stream_node.node.blobs = Some(vec![blob_id]);
+            }
+            tree_serializer::handle_processed_item(
+                (path, stream_node),
+                repo.as_ref(),
+                &mut pending_trees,
+                &mut root_tree_id,
+                &tmp_path,
+            )?;
+        }
+        assert!(root_tree_id.is_none());
+
+        let checkpoint_id = archiver.save_checkpoint(&pending_trees, None)?;
+        repo.finalize_pack_saver();
+
+        let checkpoint = repo.load_snapshot(&checkpoint_id)?;
+        assert!(checkpoint.checkpoint);
+        let checkpoint_paths: Vec<PathBuf> = SerializedNodeStreamer::new(
+            repo.clone(),
+            Some(checkpoint.tree.clone()),
+            PathBuf::new(),
+            None,
+            None,
+        )?
+        .map(|item| item.map(|(path, _)| path))
+        .collect::<Result<_>>()?;
+        assert_eq!(
+            checkpoint_paths,
+            vec![
+                PathBuf::from("data"),
+                PathBuf::from("data/a"),
+                PathBuf::from("data/a/1.txt")
+            ]
+        );
+
+        // Resume from the checkpoint. The file it contains is not uploaded again.
+        let archiver = Archiver::new(
+            repo.clone(),
+            snapshot_options(Some((checkpoint_id.clone(), checkpoint))),
+            (1, 1),
+            Arc::new(SnapshotProgressReporter::new(0, 0, 0)),
+        );
+        let snapshot = archiver.snapshot()?;
+        assert_eq!(snapshot.parent, Some(checkpoint_id));
+        assert_eq!(snapshot.summary.diff_counts.unchanged_files, 1);
+        assert_eq!(snapshot.summary.diff_counts.new_files, 2);
+        assert_eq!(
+            snapshot.summary.raw_bytes,
+            ("backup".len() + "rust".len()) as u64
+        );
+
+        let snapshot_paths: Vec<PathBuf> = SerializedNodeStreamer::new(
+            repo.clone(),
+            Some(snapshot.tree),
+            PathBuf::new(),
+            None,
+            None,
+        )?
+        .map(|item| item.map(|(path, _)| path))
+        .collect::<Result<_>>()?;
+        assert_eq!(
+            snapshot_paths,
+            vec![
+                PathBuf::from("data"),
+                PathBuf::from("data/a"),
+                PathBuf::from("data/a/1.txt"),
+                PathBuf::from("data/a/2.txt"),
+                PathBuf::from("data/b.txt")
+            ]
+        );
+
+        Ok(())
+    }
+}
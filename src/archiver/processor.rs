@@ -15,25 +15,37 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use std::{
-    fs::File,
-    io::BufReader,
+    fs::{File, Metadata as FsMetadata},
+    io::{BufReader, Read},
     path::{Path, PathBuf},
     sync::Arc,
 };
 
 use anyhow::{Context, Result, bail};
-use fastcdc::v2020::{Normalization, StreamCDC};
+use fastcdc::v2020::{ChunkData, Normalization, StreamCDC};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 use crate::{
     global::{self, BlobType, ID, SaveID},
     repository::{
         repo::Repository,
         streamers::{NodeDiff, StreamNode},
-        tree::{Node, NodeType},
+        tree::{Metadata, Node, NodeType},
     },
-    ui::snapshot_progress::SnapshotProgressReporter,
+    ui::{self, snapshot_progress::SnapshotProgressReporter},
+    utils::rate_limit::{RateLimitedReader, RateLimiter},
 };
 
+/// Options and shared resources used to read and save files.
+pub(crate) struct SaveFileOptions {
+    /// Number of times a file that changed while being read is read again.
+    pub changed_file_retries: usize,
+    /// Limits the rate at which the files are read.
+    pub read_limiter: Option<Arc<RateLimiter>>,
+    /// Thread pool where the blobs are hashed, compressed and encrypted.
+    pub cpu_pool: rayon::ThreadPool,
+}
+
 pub(crate) fn process_item(
     (path, prev_node, next_node, diff_type): (
         PathBuf,
@@ -42,6 +54,7 @@ pub(crate) fn process_item(
         NodeDiff,
     ),
     repo: Arc<Repository>,
+    options: &SaveFileOptions,
     progress_reporter: Arc<SnapshotProgressReporter>,
 ) -> Result<Option<(PathBuf, StreamNode)>> {
     match diff_type {
@@ -94,12 +107,30 @@ pub(crate) fn process_item(
                 let blobs_ids = save_file(
                     repo, // `repo` is an Arc, so it can be moved here.
                     &path,
-                    &stream_node_info.node,
+                    &mut stream_node_info.node,
+                    options,
                     progress_reporter.clone(),
                 )?;
                 stream_node_info.node.blobs = Some(blobs_ids);
             }
 
+            // A file re-read only because of content-based change detection is still unchanged
+            // if both its contents and its metadata match the previous node.
+            let diff_type = match &prev_node {
+                Some(prev)
+                    if diff_type == NodeDiff::Changed
+                        && stream_node_info.node.is_file()
+                        && prev.node.blobs == stream_node_info.node.blobs
+                        && !prev
+                            .node
+                            .metadata
+                            .has_changed(&stream_node_info.node.metadata) =>
+                {
+                    NodeDiff::Unchanged
+                }
+                _ => diff_type,
+            };
+
             // Notify reporter based on diff type and node type.
             match stream_node_info.node.node_type {
                 NodeType::File
@@ -110,6 +141,8 @@ pub(crate) fn process_item(
                 | NodeType::Socket => {
                     if diff_type == NodeDiff::New {
                         progress_reporter.new_file();
+                    } else if diff_type == NodeDiff::Unchanged {
+                        progress_reporter.unchanged_file();
                     } else {
                         // NodeDiff::Changed
                         progress_reporter.changed_file();
@@ -135,67 +168,244 @@ pub(crate) fn process_item(
 /// This function will split the file into chunks for deduplication, which will be compressed,
 /// encrypted and stored in the repository. Files smaller than the minimum chunk size are stored
 /// directly as blobs.
+///
+/// The file is stat'ed again after reading it. If it changed while being read, the node metadata
+/// is refreshed and the file is read again, up to `changed_file_retries` times. After that, the
+/// last contents read are kept and the node is flagged as inconsistent.
 pub(crate) fn save_file(
     repo: Arc<Repository>,
     src_path: &Path,
-    node: &Node,
+    node: &mut Node,
+    options: &SaveFileOptions,
     progress_reporter: Arc<SnapshotProgressReporter>,
 ) -> Result<Vec<ID>> {
+    let changed_file_retries = options.changed_file_retries;
+    let mut attempt = 0;
+
+    loop {
+        let (blob_ids, read_bytes) = read_and_save_blobs(
+            repo.clone(),
+            src_path,
+            node.metadata.size,
+            options,
+            progress_reporter.clone(),
+        )?;
+
+        let meta_after = std::fs::symlink_metadata(src_path)
+            .with_context(|| format!("Cannot stat {}", src_path.display()))?;
+
+        if !has_changed_while_reading(&node.metadata, read_bytes, &meta_after) {
+            return Ok(blob_ids);
+        }
+
+        node.metadata = Metadata::from_fs(&meta_after);
+
+        if attempt >= changed_file_retries {
+            node.inconsistent = true;
+            progress_reporter.inconsistent_file();
+            ui::cli::warning!(
+                "\'{}\' changed while being read. The saved contents may be inconsistent.",
+                src_path.display()
+            );
+            return Ok(blob_ids);
+        }
+
+        attempt += 1;
+        ui::cli::verbose_1!(
+            "\'{}\' changed while being read. Reading again ({}/{})",
+            src_path.display(),
+            attempt,
+            changed_file_retries
+        );
+    }
+}
+
+/// Checks whether a file changed while it was being read, comparing the number of bytes read
+/// and the size, mtime and ctime after reading to the node metadata taken before reading.
+fn has_changed_while_reading(
+    node_metadata: &Metadata,
+    read_bytes: u64,
+    meta_after: &FsMetadata,
+) -> bool {
+    let meta_after = Metadata::from_fs(meta_after);
+
+    read_bytes != node_metadata.size
+        || meta_after.size != node_metadata.size
+        || meta_after.modified_time != node_metadata.modified_time
+        || meta_after.changed_time != node_metadata.changed_time
+}
+
+// Reads the file and saves its contents in the repository. Returns the blob IDs and the
+// number of bytes read.
+fn read_and_save_blobs(
+    repo: Arc<Repository>,
+    src_path: &Path,
+    size: u64,
+    options: &SaveFileOptions,
+    progress_reporter: Arc<SnapshotProgressReporter>,
+) -> Result<(Vec<ID>, u64)> {
     // Do not chunk if the file is smaller than the minimum chunk size
-    if node.metadata.size < global::defaults::MIN_CHUNK_SIZE {
-        let data = std::fs::read(src_path)?;
-        let (id, (raw_data_size, encoded_data_size), (raw_meta_size, encoded_meta_size)) =
-            repo.encode_and_save_blob(BlobType::Data, data, SaveID::CalculateID)?;
+    if size < global::defaults::MIN_CHUNK_SIZE {
+        let data = std::fs::read(src_path)
+            .with_context(|| format!("Could not read file \'{}\'", src_path.display()))?;
+        let read_bytes = data.len() as u64;
+        if let Some(limiter) = &options.read_limiter {
+            limiter.consume(read_bytes);
+        }
+        let (
+            id,
+            (raw_data_size, encoded_data_size, uncompressed_data_size),
+            (raw_meta_size, encoded_meta_size),
+        ) = options
+            .cpu_pool
+            .install(|| repo.encode_and_save_blob(BlobType::Data, data, SaveID::CalculateID))?;
         progress_reporter.written_data_bytes(raw_data_size, encoded_data_size);
+        progress_reporter.uncompressed_data_bytes(uncompressed_data_size);
         progress_reporter.written_meta_bytes(raw_meta_size, encoded_meta_size);
-        progress_reporter.processed_bytes(node.metadata.size);
+        progress_reporter.processed_bytes(read_bytes);
 
-        Ok(vec![id])
+        Ok((vec![id], read_bytes))
     } else {
-        chunk_and_save_blobs(repo, src_path, progress_reporter)
+        chunk_and_save_blobs(repo, src_path, options, progress_reporter)
     }
 }
 
 // Chunks the file and saves the blobs in the repository.
+//
+// The chunk boundaries are found sequentially, but the chunks are hashed, encoded and packed in
+// parallel in the CPU pool, in batches as large as the pool. The next batch is read while the
+// previous one is being saved, so a single large file can keep all threads busy. The order of the
+// chunks is preserved in the returned list of IDs.
 fn chunk_and_save_blobs(
     repo: Arc<Repository>,
     src_path: &Path,
+    options: &SaveFileOptions,
     progress_reporter: Arc<SnapshotProgressReporter>,
-) -> Result<Vec<ID>> {
+) -> Result<(Vec<ID>, u64)> {
     let source = File::open(src_path)
         .with_context(|| format!("Could not open file \'{}\'", src_path.display()))?;
+    let source: Box<dyn Read + Send> = match &options.read_limiter {
+        Some(limiter) => Box::new(RateLimitedReader::new(source, limiter.clone())),
+        None => Box::new(source),
+    };
     let reader = BufReader::new(source);
 
     let mut chunk_ids = Vec::new();
+    let mut read_bytes = 0;
+
+    let mut chunker = new_chunker(reader);
+
+    let batch_size = options.cpu_pool.current_num_threads();
+    let mut batch = read_chunk_batch(&mut chunker, batch_size)?;
 
+    while !batch.is_empty() {
+        let (next_batch, saved_batch) = rayon::join(
+            || read_chunk_batch(&mut chunker, batch_size),
+            || {
+                options
+                    .cpu_pool
+                    .install(|| save_chunk_batch(&repo, batch, &progress_reporter))
+            },
+        );
+
+        let (batch_ids, batch_bytes) = saved_batch?;
+        chunk_ids.extend(batch_ids);
+        read_bytes += batch_bytes;
+
+        batch = next_batch?;
+    }
+
+    Ok((chunk_ids, read_bytes))
+}
+
+/// Creates the content defined chunker used to split files into blobs.
+pub(crate) fn new_chunker<R: Read>(reader: R) -> StreamCDC<R> {
     // The chunker parameters must remain stable across versions, otherwise
     // same contents will no longer produce same chunks and IDs.
-    let chunker = StreamCDC::with_level(
+    StreamCDC::with_level(
         reader,
         global::defaults::MIN_CHUNK_SIZE as u32,
         global::defaults::AVG_CHUNK_SIZE as u32,
         global::defaults::MAX_CHUNK_SIZE as u32,
         Normalization::Level0,
-    );
+    )
+}
 
-    for result in chunker {
+// Reads up to `batch_size` chunks from the chunker.
+fn read_chunk_batch(
+    chunker: &mut impl Iterator<Item = Result<ChunkData, fastcdc::v2020::Error>>,
+    batch_size: usize,
+) -> Result<Vec<Vec<u8>>> {
+    let mut batch = Vec::with_capacity(batch_size);
+
+    for result in chunker.take(batch_size.max(1)) {
         let chunk = result.with_context(|| "Failed to chunk file")?;
+        batch.push(chunk.data);
+    }
+
+    Ok(batch)
+}
+
+// Saves a batch of chunks in parallel. Returns the blob IDs, in the same order as the chunks,
+// and the number of bytes saved.
+fn save_chunk_batch(
+    repo: &Repository,
+    batch: Vec<Vec<u8>>,
+    progress_reporter: &SnapshotProgressReporter,
+) -> Result<(Vec<ID>, u64)> {
+    let batch_bytes = batch.iter().map(|data| data.len() as u64).sum();
+
+    let ids = batch
+        .into_par_iter()
+        .map(|data| {
+            let chunk_length = data.len() as u64;
 
-        let repo_clone = repo.clone();
-        let pr = progress_reporter.clone();
-
-        let save_blob_res =
-            repo_clone.encode_and_save_blob(BlobType::Data, chunk.data, SaveID::CalculateID);
-        match save_blob_res {
-            Ok((id, (raw_data_size, encoded_data_size), (raw_meta_size, encoded_meta_size))) => {
-                chunk_ids.push(id.clone());
-                pr.written_data_bytes(raw_data_size, encoded_data_size);
-                pr.written_meta_bytes(raw_meta_size, encoded_meta_size);
-                pr.processed_bytes(raw_data_size);
+            match repo.encode_and_save_blob(BlobType::Data, data, SaveID::CalculateID) {
+                Ok((
+                    id,
+                    (raw_data_size, encoded_data_size, uncompressed_data_size),
+                    (raw_meta_size, encoded_meta_size),
+                )) => {
+                    progress_reporter.written_data_bytes(raw_data_size, encoded_data_size);
+                    progress_reporter.uncompressed_data_bytes(uncompressed_data_size);
+                    progress_reporter.written_meta_bytes(raw_meta_size, encoded_meta_size);
+                    progress_reporter.processed_bytes(chunk_length);
+                    Ok(id)
+                }
+                Err(e) => bail!("Failed to save blob to repository: {:?}", e),
             }
-            Err(e) => bail!("Failed to save blob to repository: {:?}", e),
-        }
-    }
+        })
+        .collect::<Result<Vec<ID>>>()?;
+
+    Ok((ids, batch_bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
 
-    Ok(chunk_ids)
+    #[test]
+    fn test_has_changed_while_reading() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("file");
+        std::fs::write(&file_path, b"mapache")?;
+
+        let node_metadata = Metadata::from_fs(&std::fs::symlink_metadata(&file_path)?);
+
+        // Nothing changed
+        let meta_after = std::fs::symlink_metadata(&file_path)?;
+        assert!(!has_changed_while_reading(&node_metadata, 7, &meta_after));
+
+        // Fewer bytes read than expected
+        assert!(has_changed_while_reading(&node_metadata, 5, &meta_after));
+
+        // The file grew while being read
+        std::fs::write(&file_path, b"mapache backup")?;
+        let meta_after = std::fs::symlink_metadata(&file_path)?;
+        assert!(has_changed_while_reading(&node_metadata, 7, &meta_after));
+
+        Ok(())
+    }
 }
@@ -205,6 +205,70 @@ pub(crate) fn finalize_if_complete(
     Ok((raw_tree_size, encoded_tree_size))
 }
 
+/// Serializes the current state of the pending trees without consuming them, returning the ID
+/// of the partial root tree. Pending directories are saved with the children received so far.
+/// Directories whose own node has not been received yet are left out.
+pub(crate) fn save_partial_tree(
+    repo: &Repository,
+    pending_trees: &HashMap<PathBuf, PendingTree>,
+    snapshot_root_path: &Path,
+) -> Result<(ID, (u64, u64))> {
+    // Map each pending directory to its pending subdirectories
+    let mut pending_subdirs: HashMap<&Path, Vec<&Path>> = HashMap::new();
+    for path in pending_trees.keys() {
+        if path == snapshot_root_path {
+            continue;
+        }
+
+        if let Some(parent_path) = path.parent() {
+            pending_subdirs.entry(parent_path).or_default().push(path);
+        }
+    }
+
+    save_partial_subtree(repo, pending_trees, &pending_subdirs, snapshot_root_path)
+}
+
+fn save_partial_subtree(
+    repo: &Repository,
+    pending_trees: &HashMap<PathBuf, PendingTree>,
+    pending_subdirs: &HashMap<&Path, Vec<&Path>>,
+    dir_path: &Path,
+) -> Result<(ID, (u64, u64))> {
+    let pending_tree = pending_trees.get(dir_path).with_context(|| {
+        format!(
+            "Pending tree for path '{}' not found in map.",
+            dir_path.display()
+        )
+    })?;
+
+    let mut raw_size = 0;
+    let mut encoded_size = 0;
+    let mut nodes: Vec<Node> = pending_tree.children.values().cloned().collect();
+
+    for subdir_path in pending_subdirs.get(dir_path).into_iter().flatten() {
+        let Some(subdir_node) = &pending_trees[*subdir_path].node else {
+            continue;
+        };
+
+        let (subtree_id, (subtree_raw_size, subtree_encoded_size)) =
+            save_partial_subtree(repo, pending_trees, pending_subdirs, subdir_path)?;
+        raw_size += subtree_raw_size;
+        encoded_size += subtree_encoded_size;
+
+        let mut subdir_node = subdir_node.clone();
+        subdir_node.tree = Some(subtree_id);
+        nodes.push(subdir_node);
+    }
+
+    let mut partial_tree = Tree { nodes };
+    let (tree_id, (tree_raw_size, tree_encoded_size)) = partial_tree.save_to_repo(repo)?;
+
+    Ok((
+        tree_id,
+        (raw_size + tree_raw_size, encoded_size + tree_encoded_size),
+    ))
+}
+
 #[inline]
 fn insert_finalized_node(
     pending_trees: &mut HashMap<PathBuf, PendingTree>,
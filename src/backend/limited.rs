@@ -0,0 +1,148 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+
+use super::{FileAttr, StorageBackend};
+use crate::utils::rate_limit::RateLimiter;
+
+/// A storage backend that sets itself before another backend, limiting the rate at which
+/// data is uploaded to and downloaded from it.
+pub struct LimitedBackend {
+    backend: Arc<dyn StorageBackend>,
+    upload_limiter: Option<RateLimiter>,
+    download_limiter: Option<RateLimiter>,
+}
+
+impl LimitedBackend {
+    /// Creates a new limited backend. The limits are given in bytes per second, and a limit of
+    /// 0 means no limit.
+    pub fn new(
+        backend: Arc<dyn StorageBackend>,
+        upload_limit: Option<u64>,
+        download_limit: Option<u64>,
+    ) -> Self {
+        Self {
+            backend,
+            upload_limiter: upload_limit.filter(|&l| l > 0).map(RateLimiter::new),
+            download_limiter: download_limit.filter(|&l| l > 0).map(RateLimiter::new),
+        }
+    }
+
+    #[inline]
+    fn limit_download(&self, data: Result<Vec<u8>>) -> Result<Vec<u8>> {
+        if let Ok(data) = &data
+            && let Some(limiter) = &self.download_limiter
+        {
+            limiter.consume(data.len() as u64);
+        }
+        data
+    }
+}
+
+impl StorageBackend for LimitedBackend {
+    #[inline]
+    fn create(&self) -> Result<()> {
+        self.backend.create()
+    }
+
+    #[inline]
+    fn root_exists(&self) -> bool {
+        self.backend.root_exists()
+    }
+
+    #[inline]
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.limit_download(self.backend.read(path))
+    }
+
+    #[inline]
+    fn seek_read(&self, path: &Path, offset: u64, length: u64) -> Result<Vec<u8>> {
+        self.limit_download(self.backend.seek_read(path, offset, length))
+    }
+
+    #[inline]
+    fn seek_read_from_end(&self, path: &Path, offset: i64, length: u64) -> Result<Vec<u8>> {
+        self.limit_download(self.backend.seek_read_from_end(path, offset, length))
+    }
+
+    #[inline]
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        if let Some(limiter) = &self.upload_limiter {
+            limiter.consume(contents.len() as u64);
+        }
+        self.backend.write(path, contents)
+    }
+
+    #[inline]
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.backend.rename(from, to)
+    }
+
+    #[inline]
+    fn remove_file(&self, file_path: &Path) -> Result<()> {
+        self.backend.remove_file(file_path)
+    }
+
+    #[inline]
+    fn create_dir(&self, path: &Path) -> Result<()> {
+        self.backend.create_dir(path)
+    }
+
+    #[inline]
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        self.backend.create_dir_all(path)
+    }
+
+    #[inline]
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        self.backend.read_dir(path)
+    }
+
+    #[inline]
+    fn remove_dir(&self, path: &Path) -> Result<()> {
+        self.backend.remove_dir(path)
+    }
+
+    #[inline]
+    fn remove_dir_all(&self, path: &Path) -> Result<()> {
+        self.backend.remove_dir_all(path)
+    }
+
+    #[inline]
+    fn exists(&self, path: &Path) -> bool {
+        self.backend.exists(path)
+    }
+
+    #[inline]
+    fn is_file(&self, path: &Path) -> bool {
+        self.backend.is_file(path)
+    }
+
+    #[inline]
+    fn is_dir(&self, path: &Path) -> bool {
+        self.backend.is_dir(path)
+    }
+
+    fn lstat(&self, path: &Path) -> Result<FileAttr> {
+        self.backend.lstat(path)
+    }
+}
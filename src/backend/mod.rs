@@ -15,6 +15,7 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 pub mod dry;
+pub mod limited;
 pub mod localfs;
 pub mod sftp;
 
@@ -28,6 +29,7 @@ use std::{
 use crate::{backend::sftp::SftpBackend, commands::GlobalArgs};
 use anyhow::{Result, anyhow, bail};
 use dry::DryBackend;
+use limited::LimitedBackend;
 use localfs::LocalFS;
 
 use crate::{ui, utils::url::Url};
@@ -129,6 +131,17 @@ pub fn new_backend_with_prompt(
         }
     };
 
+    let backend: Arc<dyn StorageBackend> =
+        if global_args.limit_upload.is_some() || global_args.limit_download.is_some() {
+            Arc::new(LimitedBackend::new(
+                backend,
+                global_args.limit_upload,
+                global_args.limit_download,
+            ))
+        } else {
+            backend
+        };
+
     let backend = match dry_backend {
         true => Arc::new(DryBackend::new(backend.clone())),
         false => backend,
@@ -275,10 +275,10 @@ impl SftpBackend {
             }
         }
 
-        if let Some(parent) = path.parent() {
-            if parent != Path::new("") {
-                self.create_dir_all_internal(parent, sftp)?;
-            }
+        if let Some(parent) = path.parent()
+            && parent != Path::new("")
+        {
+            self.create_dir_all_internal(parent, sftp)?;
         }
 
         sftp.mkdir(path, 0o755)
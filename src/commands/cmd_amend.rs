@@ -26,7 +26,7 @@ use crate::archiver::tree_serializer::init_pending_trees;
 use crate::commands::{EMPTY_TAG_MARK, parse_tags};
 use crate::repository::repo::{RepoConfig, Repository};
 use crate::repository::snapshot::SnapshotStreamer;
-use crate::utils::{format_size, size};
+use crate::utils::{format_size, priority::ThreadPriority, size};
 use crate::{
     archiver::tree_serializer,
     backend::new_backend_with_prompt,
@@ -81,6 +81,7 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
 
     let config = RepoConfig {
         pack_size: (global_args.pack_size_mib * size::MiB as f32) as u64,
+        compression: None,
     };
     let (repo, _) = Repository::try_open(pass, global_args.key.as_ref(), backend, config)?;
 
@@ -93,7 +94,7 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
         let mut all_snapshots: Vec<(ID, Snapshot)> = snapshot_streamer.collect();
         snapshots.append(&mut all_snapshots);
     } else {
-        match find_use_snapshot(repo.clone(), &args.snapshot) {
+        match find_use_snapshot(repo.clone(), &args.snapshot, None) {
             Ok(Some((id, snap))) => snapshots.push((id, snap)),
             Ok(None) | Err(_) => bail!("Snapshot not found"),
         }
@@ -234,7 +235,7 @@ fn rewrite_snapshot_tree(
     snapshot.summary.processed_items_count = 0;
     snapshot.summary.processed_bytes = 0;
 
-    repo.init_pack_saver(1);
+    repo.init_pack_saver(1, ThreadPriority::default());
     for (path, stream_node) in node_streamer.flatten() {
         snapshot.summary.processed_items_count += 1;
         if !stream_node.node.is_dir() {
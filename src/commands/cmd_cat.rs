@@ -14,6 +14,8 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
 
 use anyhow::{Context, Result, bail};
@@ -21,21 +23,32 @@ use clap::Args;
 
 use crate::global::{ID, ID_LENGTH};
 use crate::repository::repo::{RepoConfig, Repository};
+use crate::repository::snapshot::Snapshot;
+use crate::repository::streamers::{FileReader, find_serialized_node};
 use crate::repository::tree::Tree;
 use crate::ui;
 use crate::utils::{self, size};
 use crate::{backend::new_backend_with_prompt, global::FileType};
 
-use super::GlobalArgs;
+use super::{GlobalArgs, UseSnapshot, find_use_snapshot};
 
 #[derive(Args, Debug)]
 #[clap(about = "Print repository objects")]
 pub struct CmdArgs {
     /// Object to print:
-    /// [manifest|snapshot:ID|pack:ID|blob:ID|tree:ID|index:ID|key:ID].
-    /// Blob and tree types don't accept prefixes.
+    /// [manifest|snapshot:ID|pack:ID|blob:ID|tree:ID|index:ID|key:ID|file:SNAPSHOT:PATH].
+    /// Blob and tree types don't accept prefixes. The contents of files are written
+    /// to stdout as they are.
     #[arg(value_parser)]
     pub object: Object,
+
+    /// Skip this many bytes of a file (e.g. 500K, 20M)
+    #[clap(long, value_parser = utils::parse_size_string)]
+    pub offset: Option<u64>,
+
+    /// Print at most this many bytes of a file (e.g. 500K, 20M)
+    #[clap(long, value_parser = utils::parse_size_string)]
+    pub length: Option<u64>,
 }
 
 #[derive(Debug, Clone)]
@@ -47,6 +60,7 @@ pub enum Object {
     Index(String),
     Key(String),
     Snapshot(String),
+    File(UseSnapshot, PathBuf),
 }
 
 pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
@@ -55,9 +69,15 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
 
     let config = RepoConfig {
         pack_size: (global_args.pack_size_mib * size::MiB as f32) as u64,
+        compression: None,
     };
     let (repo, _) = Repository::try_open(pass, global_args.key.as_ref(), backend, config)?;
 
+    if !matches!(args.object, Object::File(..)) && (args.offset.is_some() || args.length.is_some())
+    {
+        bail!("--offset and --length can only be used with files");
+    }
+
     match &args.object {
         Object::Manifest => {
             let manifest = repo
@@ -116,13 +136,77 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
             ui::cli::log!("{}", serde_json::to_string_pretty(&snapshot)?);
             Ok(())
         }
+        Object::File(use_snapshot, path) => {
+            let (_, snapshot) = match find_use_snapshot(repo.clone(), use_snapshot, None) {
+                Ok(Some((id, snap))) => (id, snap),
+                Ok(None) | Err(_) => bail!("Snapshot not found"),
+            };
+            let mut stdout = io::stdout().lock();
+            write_file(
+                repo.as_ref(),
+                &snapshot,
+                path,
+                args.offset.unwrap_or(0),
+                args.length,
+                &mut stdout,
+            )?;
+            stdout.flush()?;
+            Ok(())
+        }
+    }
+}
+
+/// Writes the contents of a file in a snapshot, starting at `offset` and up to `length` bytes.
+pub fn write_file<W: Write>(
+    repo: &Repository,
+    snapshot: &Snapshot,
+    path: &Path,
+    offset: u64,
+    length: Option<u64>,
+    writer: &mut W,
+) -> Result<u64> {
+    // Paths in the snapshot are relative to its root
+    let relative_path: PathBuf = path
+        .components()
+        .filter(|c| !matches!(c, Component::RootDir | Component::Prefix(_)))
+        .collect();
+
+    let node = find_serialized_node(repo, &snapshot.tree, &relative_path)?
+        .with_context(|| format!("Path '{}' not found in snapshot", path.display()))?;
+    if !node.is_file() {
+        bail!("'{}' is not a file", path.display());
     }
+
+    let mut reader = FileReader::new(repo, &node);
+    reader.skip(offset)?;
+    let written = match length {
+        Some(length) => io::copy(&mut reader.take(length), writer)?,
+        None => io::copy(&mut reader, writer)?,
+    };
+
+    Ok(written)
 }
 
 impl FromStr for Object {
     type Err = String; // Or a more specific error type
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // File paths may contain ':'
+        if let Some(file) = s.strip_prefix("file:") {
+            return match file.split_once(':') {
+                Some((snapshot, path)) if !snapshot.is_empty() && !path.is_empty() => {
+                    Ok(Object::File(
+                        UseSnapshot::from_str(snapshot).map_err(|e| e.to_string())?,
+                        PathBuf::from(path),
+                    ))
+                }
+                _ => Err(
+                    "File object requires a snapshot and a path, e.g., 'file:latest:some/path'"
+                        .to_string(),
+                ),
+            };
+        }
+
         let parts: Vec<&str> = s.split(':').collect();
         match parts[0] {
             "manifest" => Ok(Object::Manifest),
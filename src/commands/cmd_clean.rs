@@ -64,6 +64,7 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
 
     let config = RepoConfig {
         pack_size: (global_args.pack_size_mib * size::MiB as f32) as u64,
+        compression: None,
     };
     let (repo, _) = Repository::try_open(pass, global_args.key.as_ref(), backend, config)?;
 
@@ -14,21 +14,29 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use anyhow::Result;
+use anyhow::{Result, anyhow, bail};
 use clap::Args;
 use colored::Colorize;
 
 use crate::{
     backend::new_backend_with_prompt,
     commands::GlobalArgs,
-    global::{FileType, defaults::SHORT_SNAPSHOT_ID_LEN},
+    global::{FileType, ID, defaults::SHORT_SNAPSHOT_ID_LEN},
     repository::{
         repo::{RepoConfig, Repository},
-        snapshot::DiffCounts,
-        streamers::{NodeDiff, NodeDiffStreamer, SerializedNodeStreamer},
+        snapshot::{DiffCounts, Snapshot},
+        streamers::{
+            DiffTuple, ExcludeOptions, FSNodeStreamer, NodeDiff, NodeDiffStreamer,
+            SerializedNodeStreamer,
+        },
+        tree::Node,
     },
+    restorer::existing_chunks,
     ui::{
         self,
         table::{Alignment, Table},
@@ -37,13 +45,27 @@ use crate::{
 };
 
 #[derive(Args, Debug)]
-#[clap(about = "Show differences between snapshots")]
+#[clap(
+    about = "Show differences between snapshots",
+    long_about = "Show differences between two snapshots, or between a snapshot and the \
+    current state of the filesystem with --against-fs."
+)]
 pub struct CmdArgs {
     #[arg(value_parser)]
     pub source_snapshot_id: String,
 
-    #[arg(value_parser)]
-    pub target_snapshot_id: String,
+    #[arg(value_parser, required_unless_present = "against_fs")]
+    pub target_snapshot_id: Option<String>,
+
+    /// Compare the snapshot with a path in the filesystem instead of another snapshot. The path
+    /// must be inside the snapshot root, and only the items under it are compared.
+    #[clap(long, conflicts_with = "target_snapshot_id")]
+    pub against_fs: Option<PathBuf>,
+
+    /// Read and chunk the files in the filesystem to compare their contents, instead of trusting
+    /// their size and modification time.
+    #[clap(long, default_value_t = false, requires = "against_fs")]
+    pub content: bool,
 
     /// A list of paths to include.
     #[clap(long)]
@@ -54,146 +76,112 @@ pub struct CmdArgs {
     pub exclude: Option<Vec<PathBuf>>,
 }
 
+/// How to tell whether the contents of an item present on both sides changed.
+enum ContentComparison {
+    /// Compare the blobs saved in both snapshots.
+    Blobs,
+    /// Items in the filesystem have no blobs. Files are considered modified if their size or
+    /// modification time differ.
+    FsMetadata,
+    /// Chunk the files in the filesystem, relative to this root, and compare their blobs.
+    FsContent(PathBuf),
+}
+
+impl ContentComparison {
+    fn content_changed(&self, path: &Path, source: &Node, target: &Node) -> Result<bool> {
+        match self {
+            ContentComparison::Blobs => Ok(target.blobs != source.blobs),
+            ContentComparison::FsMetadata => Ok(source.is_file()
+                && target.is_file()
+                && (source.metadata.size != target.metadata.size
+                    || source.metadata.modified_time != target.metadata.modified_time)),
+            ContentComparison::FsContent(root) => {
+                if !source.is_file() || !target.is_file() {
+                    return Ok(false);
+                }
+                let ids: Vec<ID> = existing_chunks(&root.join(path))?
+                    .into_iter()
+                    .map(|(_, id)| id)
+                    .collect();
+                Ok(source.blobs.as_deref().unwrap_or_default() != ids.as_slice())
+            }
+        }
+    }
+}
+
 pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
     let pass = utils::get_password_from_file(&global_args.password_file)?;
     let backend = new_backend_with_prompt(global_args, false)?;
 
     let config = RepoConfig {
         pack_size: (global_args.pack_size_mib * size::MiB as f32) as u64,
+        compression: None,
     };
     let (repo, _) = Repository::try_open(pass, global_args.key.as_ref(), backend, config)?;
 
     // Load snapshots
     let (source_id, _) = repo.find(FileType::Snapshot, &args.source_snapshot_id)?;
-    let (target_id, _) = repo.find(FileType::Snapshot, &args.target_snapshot_id)?;
     let source_snapshot = repo.load_snapshot(&source_id)?;
-    let target_snapshot = repo.load_snapshot(&target_id)?;
-
-    let source_node_streamer = SerializedNodeStreamer::new(
-        repo.clone(),
-        Some(source_snapshot.tree.clone()),
-        PathBuf::new(),
-        args.include.clone(),
-        args.exclude.clone(),
-    )?;
-    let target_node_streamer = SerializedNodeStreamer::new(
-        repo.clone(),
-        Some(target_snapshot.tree.clone()),
-        PathBuf::new(),
-        args.include.clone(),
-        args.exclude.clone(),
-    )?;
-    let diff_streamer = NodeDiffStreamer::new(source_node_streamer, target_node_streamer);
-
-    ui::cli::log!(
-        "Finding diffs {}..{}\n",
-        source_id
-            .to_short_hex(SHORT_SNAPSHOT_ID_LEN)
-            .bold()
-            .yellow(),
-        target_id.to_short_hex(SHORT_SNAPSHOT_ID_LEN).bold().green()
-    );
-
-    let mut counts = DiffCounts::default();
-    for (path, source, target, diff_type) in diff_streamer.flatten() {
-        match &diff_type {
-            NodeDiff::New => {
-                let target_node = &target
-                    .as_ref()
-                    .expect("Target node (new) should not be None")
-                    .node;
-
-                let new_symbol = "+".bold().green().to_string();
-
-                let path_str = if target_node.is_dir() {
-                    format!("{}", path.to_string_lossy().blue().bold())
-                } else {
-                    path.display().to_string()
-                };
-
-                ui::cli::log!("{}  {}", new_symbol, path_str);
-
-                counts.increment(target_node.is_dir(), &diff_type);
-            }
-            NodeDiff::Deleted => {
-                let source_node = &source
-                    .as_ref()
-                    .expect("Source node (deleted) should not be None")
-                    .node;
-
-                let deleted_symbol = "-".bold().red().to_string();
-
-                let path_str = if source_node.is_dir() {
-                    format!("{}", path.to_string_lossy().blue().bold())
-                } else {
-                    path.display().to_string()
-                };
-
-                ui::cli::log!("{}  {}", deleted_symbol, path_str);
-
-                counts.increment(source_node.is_dir(), &diff_type);
-            }
-            NodeDiff::Changed => {
-                let target_node = &target
-                    .as_ref()
-                    .expect("Target node (changed) should not be None")
-                    .node;
-                let source_node = &source
-                    .as_ref()
-                    .expect("Source node (changed) should not be None")
-                    .node;
-
-                let content_changed = target_node.blobs != source_node.blobs;
-                let node_type_changed = source_node.node_type != target_node.node_type;
-
-                let path_str = if source_node.is_dir() {
-                    format!("{}", path.to_string_lossy().blue().bold())
-                } else {
-                    path.display().to_string()
-                };
-
-                let symbol = if node_type_changed {
-                    "T".bold().purple().to_string()
-                } else if !content_changed {
-                    "m".bold().cyan().to_string()
-                } else {
-                    "M".bold().yellow().to_string()
-                };
-
-                ui::cli::log!("{}  {}", symbol, path_str);
-
-                counts.increment(target_node.is_dir(), &diff_type);
-            }
-            NodeDiff::Unchanged => {
-                let target_node = &target
-                    .as_ref()
-                    .expect("Target node (unchanged) should not be None")
-                    .node;
-                counts.increment(target_node.is_dir(), &diff_type);
-                let source_node = &source
-                    .as_ref()
-                    .expect("Source node (changed) should not be None")
-                    .node;
-
-                let content_changed = target_node.blobs != source_node.blobs;
-                let path_str = if target_node.is_dir() {
-                    format!("{}", path.to_string_lossy().blue().bold())
-                } else {
-                    path.display().to_string()
-                };
 
-                if content_changed {
-                    ui::cli::log!(
-                        "{}  {}",
-                        "?".bold().white().on_red().to_string(),
-                        path.display()
-                    );
-                } else {
-                    ui::cli::verbose_1!("{}  {}", "U".bold().to_string(), path_str);
-                }
-            }
+    let source_label = source_id
+        .to_short_hex(SHORT_SNAPSHOT_ID_LEN)
+        .bold()
+        .yellow()
+        .to_string();
+
+    let (counts, target_label, target_size) = match (&args.against_fs, &args.target_snapshot_id) {
+        (Some(fs_path), _) => {
+            ui::cli::log!(
+                "Finding diffs {}..{}\n",
+                source_label,
+                fs_path.display().to_string().bold().green()
+            );
+
+            let (counts, fs_size) = diff_against_fs(
+                repo,
+                &source_snapshot,
+                fs_path,
+                args.include.clone(),
+                args.exclude.clone(),
+                args.content,
+            )?;
+            (counts, fs_path.display().to_string(), fs_size)
         }
-    }
+        (None, Some(target_snapshot_id)) => {
+            let (target_id, _) = repo.find(FileType::Snapshot, target_snapshot_id)?;
+            let target_snapshot = repo.load_snapshot(&target_id)?;
+
+            ui::cli::log!(
+                "Finding diffs {}..{}\n",
+                source_label,
+                target_id.to_short_hex(SHORT_SNAPSHOT_ID_LEN).bold().green()
+            );
+
+            let source_node_streamer = SerializedNodeStreamer::new(
+                repo.clone(),
+                Some(source_snapshot.tree.clone()),
+                PathBuf::new(),
+                args.include.clone(),
+                args.exclude.clone(),
+            )?;
+            let target_node_streamer = SerializedNodeStreamer::new(
+                repo.clone(),
+                Some(target_snapshot.tree.clone()),
+                PathBuf::new(),
+                args.include.clone(),
+                args.exclude.clone(),
+            )?;
+            let diff_streamer = NodeDiffStreamer::new(source_node_streamer, target_node_streamer);
+
+            let (counts, _) = log_diffs(diff_streamer, &ContentComparison::Blobs)?;
+            (
+                counts,
+                target_id.to_short_hex(SHORT_SNAPSHOT_ID_LEN),
+                target_snapshot.size(),
+            )
+        }
+        (None, None) => bail!("Either a target snapshot or --against-fs must be specified"),
+    };
 
     ui::cli::log!();
 
@@ -232,24 +220,163 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
         Table::new_with_alignments(vec![Alignment::Left, Alignment::Right, Alignment::Right]);
     summary_table.set_headers(vec![
         String::new(),
-        source_id
-            .to_short_hex(SHORT_SNAPSHOT_ID_LEN)
-            .bold()
-            .yellow()
-            .to_string(),
-        target_id
-            .to_short_hex(SHORT_SNAPSHOT_ID_LEN)
-            .bold()
-            .green()
-            .to_string(),
+        source_label,
+        target_label.bold().green().to_string(),
     ]);
     summary_table.add_row(vec![
         "Size".to_string(),
         format_size(source_snapshot.size(), 3),
-        format_size(target_snapshot.size(), 3),
+        format_size(target_size, 3),
     ]);
 
     ui::cli::log!("{}", summary_table.render());
 
     Ok(())
 }
+
+/// Compares a snapshot with the items under `fs_path`, which must be inside the snapshot root.
+/// Each difference is logged. Returns the diff counts and the size of the files compared in the
+/// filesystem.
+pub fn diff_against_fs(
+    repo: Arc<Repository>,
+    snapshot: &Snapshot,
+    fs_path: &Path,
+    include: Option<Vec<PathBuf>>,
+    exclude: Option<Vec<PathBuf>>,
+    content: bool,
+) -> Result<(DiffCounts, u64)> {
+    let fs_path = std::fs::canonicalize(fs_path)
+        .map_err(|e| anyhow!("Cannot access '{}': {}", fs_path.display(), e))?;
+    let Ok(relative_path) = fs_path.strip_prefix(&snapshot.root) else {
+        bail!(
+            "'{}' is not inside the snapshot root '{}'",
+            fs_path.display(),
+            snapshot.root.display()
+        );
+    };
+    let relative_path = relative_path.to_path_buf();
+
+    // Only explore the part of the snapshot under the compared path. The ancestors of the path,
+    // which the snapshot streamer emits, are skipped.
+    let snapshot_include = match &include {
+        None if !relative_path.as_os_str().is_empty() => Some(vec![relative_path.clone()]),
+        _ => include.clone(),
+    };
+    let snapshot_streamer = SerializedNodeStreamer::new(
+        repo,
+        Some(snapshot.tree.clone()),
+        PathBuf::new(),
+        snapshot_include,
+        exclude.clone(),
+    )?
+    .filter(|node_res| {
+        node_res
+            .as_ref()
+            .map_or(true, |(path, _)| path.starts_with(&relative_path))
+    });
+
+    // Paths in the filesystem are made relative to the snapshot root
+    let fs_streamer =
+        FSNodeStreamer::from_paths(vec![fs_path.clone()], Vec::new(), ExcludeOptions::default())?
+            .filter_map(|node_res| {
+                node_res
+                    .and_then(|(path, stream_node)| {
+                        let path = path.strip_prefix(&snapshot.root)?.to_path_buf();
+                        if path.as_os_str().is_empty()
+                            || !utils::filter_path(&path, include.as_ref(), exclude.as_ref())
+                        {
+                            return Ok(None);
+                        }
+                        Ok(Some((path, stream_node)))
+                    })
+                    .transpose()
+            });
+
+    let comparison = if content {
+        ContentComparison::FsContent(snapshot.root.clone())
+    } else {
+        ContentComparison::FsMetadata
+    };
+    log_diffs(
+        NodeDiffStreamer::new(snapshot_streamer, fs_streamer),
+        &comparison,
+    )
+}
+
+/// Logs each difference with its symbol and counts them. Returns the counts and the size of the
+/// files on the target side.
+fn log_diffs(
+    diff_streamer: impl Iterator<Item = Result<DiffTuple>>,
+    comparison: &ContentComparison,
+) -> Result<(DiffCounts, u64)> {
+    let mut counts = DiffCounts::default();
+    let mut target_size = 0;
+
+    for (path, source, target, diff_type) in diff_streamer.flatten() {
+        if let Some(target) = &target
+            && target.node.is_file()
+        {
+            target_size += target.node.metadata.size;
+        }
+
+        match (&diff_type, &source, &target) {
+            (NodeDiff::New, _, Some(target)) => {
+                let target_node = &target.node;
+                let new_symbol = "+".bold().green().to_string();
+                ui::cli::log!("{}  {}", new_symbol, path_str(&path, target_node));
+                counts.increment(target_node.is_dir(), &diff_type);
+            }
+            (NodeDiff::Deleted, Some(source), _) => {
+                let source_node = &source.node;
+                let deleted_symbol = "-".bold().red().to_string();
+                ui::cli::log!("{}  {}", deleted_symbol, path_str(&path, source_node));
+                counts.increment(source_node.is_dir(), &diff_type);
+            }
+            (NodeDiff::Changed | NodeDiff::Unchanged, Some(source), Some(target)) => {
+                let source_node = &source.node;
+                let target_node = &target.node;
+                let content_changed =
+                    comparison.content_changed(&path, source_node, target_node)?;
+
+                if diff_type == NodeDiff::Unchanged && !content_changed {
+                    counts.increment(target_node.is_dir(), &diff_type);
+                    ui::cli::verbose_1!("{}  {}", "U".bold(), path_str(&path, target_node));
+                    continue;
+                }
+
+                // Snapshots with the same metadata but different blobs are inconsistent. Items in
+                // the filesystem whose contents changed without touching their metadata are
+                // simply modified.
+                if diff_type == NodeDiff::Unchanged
+                    && matches!(comparison, ContentComparison::Blobs)
+                {
+                    counts.increment(target_node.is_dir(), &diff_type);
+                    ui::cli::log!("{}  {}", "?".bold().white().on_red(), path.display());
+                    continue;
+                }
+
+                let symbol = if source_node.node_type != target_node.node_type {
+                    "T".bold().purple().to_string()
+                } else if !content_changed {
+                    "m".bold().cyan().to_string()
+                } else {
+                    "M".bold().yellow().to_string()
+                };
+
+                ui::cli::log!("{}  {}", symbol, path_str(&path, source_node));
+                counts.increment(target_node.is_dir(), &NodeDiff::Changed);
+            }
+            _ => bail!("Inconsistent diff for '{}'", path.display()),
+        }
+    }
+
+    Ok((counts, target_size))
+}
+
+fn path_str(path: &Path, node: &Node) -> String {
+    if node.is_dir() {
+        format!("{}", path.to_string_lossy().blue().bold())
+    } else {
+        path.display().to_string()
+    }
+}
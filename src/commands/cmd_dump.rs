@@ -0,0 +1,134 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+
+use crate::{
+    backend::new_backend_with_prompt,
+    commands::{GlobalArgs, UseSnapshot, find_use_snapshot},
+    global::{self, defaults::SHORT_SNAPSHOT_ID_LEN},
+    repository::repo::{RepoConfig, Repository},
+    restorer::archive::{self, ArchiveFormat},
+    ui,
+    utils::{self, size},
+};
+
+impl std::fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArchiveFormat::Tar => write!(f, "tar"),
+            ArchiveFormat::TarZst => write!(f, "tar.zst"),
+            ArchiveFormat::Zip => write!(f, "zip"),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+#[clap(
+    about = "Write a snapshot as a tar or zip archive",
+    long_about = "Write a snapshot, or a subset of it, as a tar or zip archive. The archive is \
+    streamed from the repository without writing to the local filesystem. Tar archives keep \
+    the mode, ownership, modification times, symlinks and device nodes. Zip archives don't \
+    store ownership nor special files."
+)]
+pub struct CmdArgs {
+    /// The ID of the snapshot to dump, or 'latest' to dump the most recent snapshot saved.
+    #[arg(value_parser = clap::value_parser!(UseSnapshot), default_value_t=UseSnapshot::Latest)]
+    pub snapshot: UseSnapshot,
+
+    /// Archive format.
+    #[clap(long, default_value_t = ArchiveFormat::Tar)]
+    pub format: ArchiveFormat,
+
+    /// File where the archive is written, or '-' for stdout.
+    #[clap(long, short = 'o', default_value = "-")]
+    pub output: PathBuf,
+
+    /// A list of paths to dump: path[,path,...]. Can be used multiple times.
+    #[clap(long, value_delimiter = ',')]
+    pub include: Option<Vec<PathBuf>>,
+
+    /// A list of paths to exclude: path[,path,...]. Can be used multiple times.
+    #[clap(long, value_delimiter = ',')]
+    pub exclude: Option<Vec<PathBuf>>,
+
+    /// Strip the longest common prefix from all archived routes.
+    #[clap(long, value_parser, default_value_t = false)]
+    pub strip_prefix: bool,
+}
+
+pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
+    // Keep stdout clean for the archive
+    let to_stdout = args.output == Path::new("-");
+    if to_stdout {
+        global::set_verbosity(0);
+    }
+
+    let pass = utils::get_password_from_file(&global_args.password_file)?;
+    let backend = new_backend_with_prompt(global_args, false)?;
+
+    let config = RepoConfig {
+        pack_size: (global_args.pack_size_mib * size::MiB as f32) as u64,
+        compression: None,
+    };
+    let (repo, _) = Repository::try_open(pass, global_args.key.as_ref(), backend, config)?;
+
+    let (snapshot_id, snapshot) = match find_use_snapshot(repo.clone(), &args.snapshot, None) {
+        Ok(Some((id, snap))) => (id, snap),
+        Ok(None) | Err(_) => bail!("Snapshot not found"),
+    };
+
+    let common_prefix: Option<PathBuf> = if args.strip_prefix {
+        args.include
+            .as_ref()
+            .map(|includes| utils::calculate_lcp(includes, false))
+    } else {
+        None
+    };
+
+    let writer: Box<dyn Write> = if to_stdout {
+        Box::new(io::stdout().lock())
+    } else {
+        let file = File::create(&args.output)
+            .with_context(|| format!("Could not create '{}'", args.output.display()))?;
+        Box::new(file)
+    };
+
+    archive::write_archive(
+        repo,
+        &snapshot,
+        args.include.clone(),
+        args.exclude.clone(),
+        common_prefix,
+        args.format,
+        BufWriter::new(writer),
+    )?;
+
+    ui::cli::log!(
+        "Snapshot {} written to '{}'",
+        snapshot_id.to_short_hex(SHORT_SNAPSHOT_ID_LEN),
+        args.output.display()
+    );
+
+    Ok(())
+}
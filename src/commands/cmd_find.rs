@@ -0,0 +1,361 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use clap::Args;
+use colored::Colorize;
+use glob::{MatchOptions, Pattern};
+
+use crate::{
+    backend::new_backend_with_prompt,
+    commands::{GlobalArgs, parse_tags},
+    global::{ID, defaults::SHORT_SNAPSHOT_ID_LEN},
+    repository::{
+        repo::{RepoConfig, Repository},
+        snapshot::{Snapshot, SnapshotStreamer},
+        tree::{Node, NodeType, Tree},
+    },
+    ui,
+    utils::{self, size},
+};
+
+#[derive(Args, Debug)]
+#[clap(
+    about = "Find files and directories across snapshots",
+    long_about = "Find files and directories across all snapshots. Items can be matched by \
+    name or path, type, size, modification time or contents. Each match is printed with the \
+    snapshot it was found in. Directories shared between snapshots are only scanned once."
+)]
+pub struct CmdArgs {
+    /// Glob patterns to match. Patterns containing a '/' are matched against the path of the
+    /// item relative to the snapshot root, and others against its name. An item matches if it
+    /// matches any pattern. All items match if no pattern is given.
+    #[arg(value_parser = clap::value_parser!(Pattern))]
+    pub patterns: Vec<Pattern>,
+
+    /// Only match items of this type
+    #[clap(long = "type", value_enum)]
+    pub node_type: Option<NodeType>,
+
+    /// Only match files with at least this size (e.g. '512K', '1.5G')
+    #[clap(long, value_parser = utils::parse_size_string)]
+    pub min_size: Option<u64>,
+
+    /// Only match files with at most this size (e.g. '512K', '1.5G')
+    #[clap(long, value_parser = utils::parse_size_string)]
+    pub max_size: Option<u64>,
+
+    /// Only match items modified after this time: a date (YYYY-MM-DD[ HH:MM:SS]) or a duration
+    /// before now (e.g. '2w')
+    #[clap(long, value_parser = utils::parse_time_string)]
+    pub modified_after: Option<DateTime<Local>>,
+
+    /// Only match items modified before this time: a date (YYYY-MM-DD[ HH:MM:SS]) or a duration
+    /// before now (e.g. '2w')
+    #[clap(long, value_parser = utils::parse_time_string)]
+    pub modified_before: Option<DateTime<Local>>,
+
+    /// Only match files containing the blob with this ID
+    #[clap(long, value_parser = ID::from_hex)]
+    pub blob: Option<ID>,
+
+    /// Only search snapshots with tags: tag[,tag,...]
+    #[arg(long = "tags", value_parser)]
+    pub tags_str: Option<String>,
+
+    /// Only search snapshots of this host
+    #[arg(long, value_parser)]
+    pub host: Option<String>,
+
+    /// Only search snapshots taken after this time: a date (YYYY-MM-DD[ HH:MM:SS]) or a duration
+    /// before now (e.g. '2w')
+    #[clap(long, value_parser = utils::parse_time_string)]
+    pub snapshots_after: Option<DateTime<Local>>,
+
+    /// Only search snapshots taken before this time: a date (YYYY-MM-DD[ HH:MM:SS]) or a
+    /// duration before now (e.g. '2w')
+    #[clap(long, value_parser = utils::parse_time_string)]
+    pub snapshots_before: Option<DateTime<Local>>,
+}
+
+/// The conditions an item must meet to match. Unset conditions match all items.
+#[derive(Debug, Clone, Default)]
+pub struct Criteria {
+    pub patterns: Vec<Pattern>,
+    pub node_type: Option<NodeType>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub modified_after: Option<DateTime<Local>>,
+    pub modified_before: Option<DateTime<Local>>,
+    pub blob: Option<ID>,
+}
+
+impl Criteria {
+    /// Returns true if patterns are matched against paths, which makes the matches in a subtree
+    /// depend on its location.
+    fn has_path_patterns(&self) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.as_str().contains('/'))
+    }
+
+    fn matches(&self, path: &Path, node: &Node) -> bool {
+        if !self.patterns.is_empty() {
+            let options = MatchOptions {
+                require_literal_separator: true,
+                ..MatchOptions::default()
+            };
+            let matches_pattern = self.patterns.iter().any(|pattern| {
+                if pattern.as_str().contains('/') {
+                    pattern.matches_path_with(path, options)
+                } else {
+                    pattern.matches_with(&node.name, options)
+                }
+            });
+            if !matches_pattern {
+                return false;
+            }
+        }
+
+        if self
+            .node_type
+            .is_some_and(|node_type| node_type != node.node_type)
+        {
+            return false;
+        }
+
+        if (self.min_size.is_some() || self.max_size.is_some())
+            && (!node.is_file()
+                || self.min_size.is_some_and(|min| node.metadata.size < min)
+                || self.max_size.is_some_and(|max| node.metadata.size > max))
+        {
+            return false;
+        }
+
+        if self.modified_after.is_some() || self.modified_before.is_some() {
+            let Some(mtime) = node.metadata.modified_time.map(DateTime::<Local>::from) else {
+                return false;
+            };
+            if self.modified_after.is_some_and(|after| mtime <= after)
+                || self.modified_before.is_some_and(|before| mtime >= before)
+            {
+                return false;
+            }
+        }
+
+        if let Some(blob) = &self.blob
+            && !node
+                .blobs
+                .as_ref()
+                .is_some_and(|blobs| blobs.contains(blob))
+        {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// The matches found in a tree, in lexicographical order. Paths are relative to the tree, so
+/// the matches in a subtree can be shared between all the snapshots that contain it.
+#[derive(Debug, Default)]
+pub struct TreeMatches {
+    entries: Vec<MatchEntry>,
+}
+
+#[derive(Debug)]
+enum MatchEntry {
+    Item(Box<Node>),
+    Subtree(String, Arc<TreeMatches>),
+}
+
+impl TreeMatches {
+    /// Returns the matched items with their paths relative to the snapshot root.
+    pub fn items(&self) -> Vec<(PathBuf, &Node)> {
+        let mut items = Vec::new();
+        self.collect_items(Path::new(""), &mut items);
+        items
+    }
+
+    fn collect_items<'a>(&'a self, base_path: &Path, items: &mut Vec<(PathBuf, &'a Node)>) {
+        for entry in &self.entries {
+            match entry {
+                MatchEntry::Item(node) => items.push((base_path.join(&node.name), node)),
+                MatchEntry::Subtree(name, subtree) => {
+                    subtree.collect_items(&base_path.join(name), items)
+                }
+            }
+        }
+    }
+}
+
+/// Searches snapshot trees for the items that meet some criteria. The matches of each tree are
+/// cached by tree ID, so trees shared between snapshots are only loaded and scanned once.
+pub struct Finder {
+    repo: Arc<Repository>,
+    criteria: Criteria,
+    path_dependent: bool,
+    cache: HashMap<(ID, PathBuf), Arc<TreeMatches>>,
+    num_scanned_trees: usize,
+}
+
+impl Finder {
+    pub fn new(repo: Arc<Repository>, criteria: Criteria) -> Self {
+        let path_dependent = criteria.has_path_patterns();
+        Self {
+            repo,
+            criteria,
+            path_dependent,
+            cache: HashMap::new(),
+            num_scanned_trees: 0,
+        }
+    }
+
+    /// Finds the items of a snapshot that meet the criteria.
+    pub fn find(&mut self, snapshot: &Snapshot) -> Result<Arc<TreeMatches>> {
+        self.find_in_tree(&snapshot.tree, PathBuf::new())
+    }
+
+    /// Returns the number of trees loaded and scanned so far.
+    pub fn num_scanned_trees(&self) -> usize {
+        self.num_scanned_trees
+    }
+
+    fn find_in_tree(&mut self, tree_id: &ID, path: PathBuf) -> Result<Arc<TreeMatches>> {
+        // Unless patterns depend on paths, the matches of a tree are the same wherever it is
+        let cache_key = match self.path_dependent {
+            true => (tree_id.clone(), path.clone()),
+            false => (tree_id.clone(), PathBuf::new()),
+        };
+        if let Some(matches) = self.cache.get(&cache_key) {
+            return Ok(matches.clone());
+        }
+
+        let mut tree = Tree::load_from_repo(self.repo.as_ref(), tree_id)
+            .with_context(|| format!("Failed to load tree with ID {tree_id}"))?;
+        self.num_scanned_trees += 1;
+        tree.nodes
+            .sort_by(|first, second| first.name.cmp(&second.name));
+
+        let mut matches = TreeMatches::default();
+        for node in tree.nodes {
+            let node_path = path.join(&node.name);
+            let subtree = node.tree.clone().map(|id| (node.name.clone(), id));
+
+            if self.criteria.matches(&node_path, &node) {
+                matches.entries.push(MatchEntry::Item(Box::new(node)));
+            }
+
+            if let Some((name, subtree_id)) = subtree {
+                let subtree_matches = self.find_in_tree(&subtree_id, node_path)?;
+                if !subtree_matches.entries.is_empty() {
+                    matches
+                        .entries
+                        .push(MatchEntry::Subtree(name, subtree_matches));
+                }
+            }
+        }
+
+        let matches = Arc::new(matches);
+        self.cache.insert(cache_key, matches.clone());
+        Ok(matches)
+    }
+}
+
+pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
+    let pass = utils::get_password_from_file(&global_args.password_file)?;
+    let backend = new_backend_with_prompt(global_args, false)?;
+
+    let config = RepoConfig {
+        pack_size: (global_args.pack_size_mib * size::MiB as f32) as u64,
+        compression: None,
+    };
+    let (repo, _) = Repository::try_open(pass, global_args.key.as_ref(), backend, config)?;
+
+    let mut snapshots: Vec<(ID, Snapshot)> = SnapshotStreamer::new(repo.clone())?.collect();
+    if let Some(tags_str) = &args.tags_str {
+        let tags = parse_tags(Some(tags_str));
+        snapshots.retain(|(_id, sn)| sn.has_tags(&tags));
+    }
+    if let Some(host) = &args.host {
+        snapshots.retain(|(_id, sn)| sn.is_from_host(host));
+    }
+    if let Some(after) = &args.snapshots_after {
+        snapshots.retain(|(_id, sn)| sn.timestamp > *after);
+    }
+    if let Some(before) = &args.snapshots_before {
+        snapshots.retain(|(_id, sn)| sn.timestamp < *before);
+    }
+    snapshots.sort_by_key(|(_id, snapshot)| snapshot.timestamp);
+
+    if snapshots.is_empty() {
+        ui::cli::log!("No snapshots found");
+        return Ok(());
+    }
+
+    let criteria = Criteria {
+        patterns: args.patterns.clone(),
+        node_type: args.node_type,
+        min_size: args.min_size,
+        max_size: args.max_size,
+        modified_after: args.modified_after,
+        modified_before: args.modified_before,
+        blob: args.blob.clone(),
+    };
+    let mut finder = Finder::new(repo, criteria);
+
+    let mut num_matches = 0;
+    let mut num_matching_snapshots = 0;
+    for (id, snapshot) in &snapshots {
+        let matches = finder.find(snapshot)?;
+        let items = matches.items();
+        if items.is_empty() {
+            continue;
+        }
+
+        num_matching_snapshots += 1;
+        num_matches += items.len();
+
+        let id_str = id.to_short_hex(SHORT_SNAPSHOT_ID_LEN).bold().yellow();
+        let timestamp_str = utils::pretty_print_timestamp(&snapshot.timestamp);
+        for (path, node) in items {
+            let path_str = if node.is_dir() {
+                path.to_string_lossy().bold().blue().to_string()
+            } else {
+                path.display().to_string()
+            };
+            ui::cli::log!("{}  {}  {}", id_str, timestamp_str, path_str);
+        }
+    }
+
+    ui::cli::log!();
+    ui::cli::log!(
+        "{} matches in {} of {} snapshots ({} trees scanned)",
+        num_matches,
+        num_matching_snapshots,
+        snapshots.len(),
+        finder.num_scanned_trees()
+    );
+
+    Ok(())
+}
@@ -15,9 +15,10 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::PathBuf;
 
-use anyhow::{Result, bail};
-use chrono::{DateTime, Datelike, Duration, Local};
+use anyhow::{Result, anyhow, bail};
+use chrono::{DateTime, Datelike, Duration, Local, Timelike};
 use clap::{ArgGroup, Parser};
 use colored::Colorize;
 
@@ -25,6 +26,7 @@ use crate::backend::new_backend_with_prompt;
 use crate::commands::parse_tags;
 use crate::global::defaults::DEFAULT_GC_TOLERANCE;
 use crate::global::{self, FileType, ID};
+use crate::repository::policy::RetentionPolicy;
 use crate::repository::repo::{RepoConfig, Repository};
 use crate::repository::snapshot::{Snapshot, SnapshotStreamer};
 use crate::ui::table::{Alignment, Table};
@@ -35,8 +37,7 @@ use super::GlobalArgs;
 
 // Define argument groups for mutual exclusivity and multiple selection
 #[derive(Parser, Debug)]
-#[clap(group = ArgGroup::new("policy").multiple(false))] // Either forget OR retention_rules, but not both
-#[clap(group = ArgGroup::new("retention_rules").multiple(true))] // Allow multiple --keep-* rules
+#[clap(group = ArgGroup::new("forget_mode").multiple(false))] // Either forget OR retention_rules, but not both
 #[clap(
     about = "Remove snapshots from the repository",
     long_about = "Remove snapshots from the repository and apply retention policies. \
@@ -45,40 +46,15 @@ use super::GlobalArgs;
 )]
 pub struct CmdArgs {
     /// Forget specific snapshots by their IDs.
-    #[arg(value_parser, value_delimiter = ' ', group = "policy")]
+    #[arg(value_parser, value_delimiter = ' ', group = "forget_mode")]
     pub forget: Vec<String>,
 
-    /// Only consider snapshots with any tag from the list: tag[,tag,...]
-    #[arg(long = "tags", value_parser)]
-    pub tags_str: Option<String>,
+    /// Apply a retention policy stored in the repository (see the `policy` command).
+    #[arg(long, conflicts_with_all = ["forget", "RetentionPolicy"])]
+    pub policy: Option<String>,
 
-    /// Keep the last N snapshots.
-    #[arg(long, group = "retention_rules")]
-    pub keep_last: Option<usize>,
-
-    /// Keep snapshots within a specified duration (e.g., '1d', '2w', '3m', '4y', '5h', '6s').
-    #[arg(long, value_parser = utils::parse_duration_string, group = "retention_rules")]
-    pub keep_within: Option<Duration>,
-
-    /// Keep N yearly snapshots. N must be greater than 1 or "all".
-    #[arg(long, value_parser = parse_retention_number, group = "retention_rules")]
-    pub keep_yearly: Option<usize>,
-
-    /// Keep N monthly snapshots. N must be greater than 1 or "all".
-    #[arg(long, value_parser = parse_retention_number, group = "retention_rules")]
-    pub keep_monthly: Option<usize>,
-
-    /// Keep N weekly snapshots. N must be greater than 1 or "all".
-    #[arg(long, value_parser = parse_retention_number, group = "retention_rules")]
-    pub keep_weekly: Option<usize>,
-
-    /// Keep N daily snapshots. N must be greater than 1 or "all".
-    #[arg(long, value_parser = parse_retention_number, group = "retention_rules")]
-    pub keep_daily: Option<usize>,
-
-    /// Keep all snapshots with tags
-    #[arg(long = "keep-tags", value_parser, group = "retention_rules")]
-    pub keep_tags_str: Option<String>,
+    #[command(flatten)]
+    pub rules: RetentionPolicy,
 
     /// Perform a dry run: show which snapshots would be removed without actually removing them.
     #[arg(long)]
@@ -118,154 +94,390 @@ pub enum RetentionRule {
     KeepWeekly(usize),
     /// Keep N daily snapshots.
     KeepDaily(usize),
+    /// Keep N hourly snapshots.
+    KeepHourly(usize),
+    /// Keep the latest snapshot of each hour within a specified duration from the present.
+    KeepWithinHourly(Duration),
+    /// Keep the latest snapshot of each day within a specified duration from the present.
+    KeepWithinDaily(Duration),
+    /// Keep the latest snapshot of each week within a specified duration from the present.
+    KeepWithinWeekly(Duration),
+    /// Keep the latest snapshot of each month within a specified duration from the present.
+    KeepWithinMonthly(Duration),
+    /// Keep the latest snapshot of each year within a specified duration from the present.
+    KeepWithinYearly(Duration),
     /// Keep snapshots with tag
     KeepTags(BTreeSet<String>),
 }
 
-pub fn parse_retention_number(s: &str) -> Result<usize> {
-    if s == "all" {
-        Ok(usize::MAX)
-    } else {
-        let n = s.parse::<isize>();
-        match n {
-            Ok(num) => {
-                if num > 0 {
-                    Ok(num as usize)
-                } else {
-                    bail!("N must be greater than 0")
-                }
+/// Snapshot attributes used to group snapshots before applying the retention rules.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GroupBy {
+    pub host: bool,
+    pub paths: bool,
+    pub tags: bool,
+}
+
+impl GroupBy {
+    pub fn is_empty(&self) -> bool {
+        !(self.host || self.paths || self.tags)
+    }
+}
+
+pub fn parse_group_by(s: &str) -> Result<GroupBy> {
+    let mut group_by = GroupBy::default();
+    for attribute in s.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+        match attribute {
+            "host" => group_by.host = true,
+            "paths" => group_by.paths = true,
+            "tags" => group_by.tags = true,
+            _ => bail!(
+                "Invalid group '{}': must be a combination of 'host', 'paths' and 'tags'",
+                attribute
+            ),
+        }
+    }
+    Ok(group_by)
+}
+
+/// The values shared by all snapshots in a group. Attributes not used for grouping are None.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GroupKey {
+    pub host: Option<String>,
+    pub paths: Option<Vec<PathBuf>>,
+    pub tags: Option<BTreeSet<String>>,
+}
+
+impl GroupKey {
+    fn new(snapshot: &Snapshot, group_by: GroupBy) -> Self {
+        Self {
+            host: group_by.host.then(|| snapshot.hostname.clone()),
+            paths: group_by.paths.then(|| snapshot.paths.clone()),
+            tags: group_by.tags.then(|| snapshot.tags.clone()),
+        }
+    }
+}
+
+impl std::fmt::Display for GroupKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(host) = &self.host {
+            parts.push(format!("host [{host}]"));
+        }
+        if let Some(paths) = &self.paths {
+            let paths: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+            parts.push(format!("paths [{}]", paths.join(", ")));
+        }
+        if let Some(tags) = &self.tags {
+            let tags: Vec<&str> = tags.iter().map(|t| t.as_str()).collect();
+            parts.push(format!("tags [{}]", tags.join(", ")));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Splits a sorted list of snapshots into groups. The order of the snapshots is preserved
+/// within each group.
+pub fn group_snapshots(
+    snapshots_sorted: Vec<(ID, Snapshot)>,
+    group_by: GroupBy,
+) -> BTreeMap<GroupKey, Vec<(ID, Snapshot)>> {
+    let mut groups: BTreeMap<GroupKey, Vec<(ID, Snapshot)>> = BTreeMap::new();
+    for (id, snapshot) in snapshots_sorted {
+        groups
+            .entry(GroupKey::new(&snapshot, group_by))
+            .or_default()
+            .push((id, snapshot));
+    }
+    groups
+}
+
+/// Snapshot filters, grouping and retention rules, either given as arguments or stored in a
+/// retention policy.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Retention {
+    pub tags: Option<BTreeSet<String>>,
+    pub host: Option<String>,
+    pub group_by: GroupBy,
+    pub rules: Vec<RetentionRule>,
+}
+
+impl Retention {
+    /// Parses the filters and rules of a retention policy. Use [`Retention::validate`] before
+    /// applying the rules.
+    pub(crate) fn from_policy(policy: &RetentionPolicy) -> Result<Self> {
+        let duration =
+            |d: &Option<String>| d.as_deref().map(utils::parse_duration_string).transpose();
+        let count = |n: &Option<usize>| -> Result<Option<usize>> {
+            match n {
+                Some(0) => bail!("N must be greater than 0"),
+                n => Ok(*n),
             }
-            Err(_) => bail!("{} is not a number", s),
+        };
+
+        let mut rules = Vec::new();
+
+        if let Some(n) = count(&policy.keep_last)? {
+            rules.push(RetentionRule::KeepLast(n));
+        }
+        if let Some(d) = duration(&policy.keep_within)? {
+            rules.push(RetentionRule::KeepWithin(d));
+        }
+        if let Some(n) = count(&policy.keep_yearly)? {
+            rules.push(RetentionRule::KeepYearly(n));
+        }
+        if let Some(n) = count(&policy.keep_monthly)? {
+            rules.push(RetentionRule::KeepMonthly(n));
+        }
+        if let Some(n) = count(&policy.keep_weekly)? {
+            rules.push(RetentionRule::KeepWeekly(n));
+        }
+        if let Some(n) = count(&policy.keep_daily)? {
+            rules.push(RetentionRule::KeepDaily(n));
+        }
+        if let Some(n) = count(&policy.keep_hourly)? {
+            rules.push(RetentionRule::KeepHourly(n));
+        }
+        if let Some(d) = duration(&policy.keep_within_hourly)? {
+            rules.push(RetentionRule::KeepWithinHourly(d));
+        }
+        if let Some(d) = duration(&policy.keep_within_daily)? {
+            rules.push(RetentionRule::KeepWithinDaily(d));
+        }
+        if let Some(d) = duration(&policy.keep_within_weekly)? {
+            rules.push(RetentionRule::KeepWithinWeekly(d));
+        }
+        if let Some(d) = duration(&policy.keep_within_monthly)? {
+            rules.push(RetentionRule::KeepWithinMonthly(d));
         }
+        if let Some(d) = duration(&policy.keep_within_yearly)? {
+            rules.push(RetentionRule::KeepWithinYearly(d));
+        }
+        if let Some(tags_str) = &policy.keep_tags {
+            rules.push(RetentionRule::KeepTags(parse_tags(Some(tags_str))));
+        }
+
+        Ok(Self {
+            tags: policy.tags.as_deref().map(|tags| parse_tags(Some(tags))),
+            host: policy.host.clone(),
+            group_by: parse_group_by(policy.group_by.as_deref().unwrap_or_default())?,
+            rules,
+        })
+    }
+
+    /// Checks that there is at least one rule that can keep a snapshot.
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.rules.is_empty() {
+            bail!("At least one retention rule must be used.");
+        }
+
+        let can_keep = self.rules.iter().any(|rule| match rule {
+            RetentionRule::KeepLast(n)
+            | RetentionRule::KeepYearly(n)
+            | RetentionRule::KeepMonthly(n)
+            | RetentionRule::KeepWeekly(n)
+            | RetentionRule::KeepDaily(n)
+            | RetentionRule::KeepHourly(n) => *n > 0,
+            RetentionRule::KeepWithin(d)
+            | RetentionRule::KeepWithinHourly(d)
+            | RetentionRule::KeepWithinDaily(d)
+            | RetentionRule::KeepWithinWeekly(d)
+            | RetentionRule::KeepWithinMonthly(d)
+            | RetentionRule::KeepWithinYearly(d) => *d > Duration::zero(),
+            RetentionRule::KeepTags(tags) => !tags.is_empty(),
+        });
+        if !can_keep {
+            bail!("The retention rules would keep zero snapshots");
+        }
+
+        Ok(())
+    }
+
+    /// Filters the snapshots by tags and host and groups them, sorted by timestamp.
+    pub(crate) fn select(
+        &self,
+        mut snapshots: Vec<(ID, Snapshot)>,
+    ) -> BTreeMap<GroupKey, Vec<(ID, Snapshot)>> {
+        if let Some(tags) = &self.tags {
+            snapshots.retain(|(_id, sn)| sn.has_tags(tags));
+        }
+        if let Some(host) = &self.host {
+            snapshots.retain(|(_id, sn)| sn.is_from_host(host));
+        }
+        snapshots.sort_by_key(|(_id, snapshot)| snapshot.timestamp);
+
+        group_snapshots(snapshots, self.group_by)
+    }
+
+    /// Applies the retention rules to each group and returns the IDs of the snapshots to keep.
+    pub(crate) fn ids_to_keep(
+        &self,
+        groups: &BTreeMap<GroupKey, Vec<(ID, Snapshot)>>,
+        now: DateTime<Local>,
+    ) -> HashSet<ID> {
+        let mut ids_to_keep = HashSet::new();
+        for snapshots_sorted in groups.values() {
+            // Checkpoints are not subject to the retention rules
+            let complete_snapshots: Vec<(ID, Snapshot)> = snapshots_sorted
+                .iter()
+                .filter(|(_id, snapshot)| !snapshot.checkpoint)
+                .cloned()
+                .collect();
+            ids_to_keep.extend(apply_retention_rules(&complete_snapshots, &self.rules, now));
+            ids_to_keep.extend(checkpoints_to_keep(snapshots_sorted));
+        }
+        ids_to_keep
     }
 }
 
+/// Returns true if there are complete snapshots, but none of them would be kept.
+pub(crate) fn keeps_no_snapshots(
+    groups: &BTreeMap<GroupKey, Vec<(ID, Snapshot)>>,
+    ids_to_keep: &HashSet<ID>,
+) -> bool {
+    let mut complete_snapshots = groups
+        .values()
+        .flatten()
+        .filter(|(_id, snapshot)| !snapshot.checkpoint)
+        .peekable();
+    complete_snapshots.peek().is_some()
+        && complete_snapshots.all(|(id, _snapshot)| !ids_to_keep.contains(id))
+}
+
 pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
     let pass = utils::get_password_from_file(&global_args.password_file)?;
     let backend = new_backend_with_prompt(global_args, args.dry_run)?;
 
     let config = RepoConfig {
         pack_size: (global_args.pack_size_mib * size::MiB as f32) as u64,
+        compression: None,
     };
     let (repo, _) = Repository::try_open(pass, global_args.key.as_ref(), backend, config)?;
 
-    // All sapshots, filter by tags and sorted by timestamp
-    let mut snapshots_sorted: Vec<(ID, Snapshot)> = SnapshotStreamer::new(repo.clone())?.collect();
-    if let Some(tags) = &args.tags_str {
-        let tags = parse_tags(Some(tags));
-        snapshots_sorted.retain(|(_id, sn)| sn.has_tags(&tags));
-    }
-    snapshots_sorted.sort_by_key(|(_id, snapshot)| snapshot.timestamp);
-
+    let snapshots: Vec<(ID, Snapshot)> = SnapshotStreamer::new(repo.clone())?.collect();
     let mut ids_to_keep: HashSet<ID> = HashSet::new();
 
-    if !args.forget.is_empty() {
+    let retention = match &args.policy {
+        Some(name) => {
+            let policies = repo.load_policies()?;
+            let Some(policy) = policies.policies.get(name) else {
+                bail!("Retention policy '{}' does not exist", name);
+            };
+            Retention::from_policy(policy)
+                .map_err(|e| anyhow!("Invalid retention policy '{}': {}", name, e))?
+        }
+        None => Retention::from_policy(&args.rules)?,
+    };
+
+    let groups = if !args.forget.is_empty() {
         let mut forget_ids = HashSet::new();
         for prefix in &args.forget {
             let (id, _) = repo.find(FileType::Snapshot, prefix)?;
             forget_ids.insert(id);
         }
 
-        for (id, _snapshot) in &snapshots_sorted {
+        let groups = retention.select(snapshots);
+        for (id, _snapshot) in groups.values().flatten() {
             if !forget_ids.contains(id) {
                 ids_to_keep.insert(id.clone());
             }
         }
+        groups
     } else {
-        let mut retention_rules = Vec::new();
-
-        if let Some(n) = args.keep_last {
-            retention_rules.push(RetentionRule::KeepLast(n));
-        }
-        if let Some(d) = args.keep_within {
-            retention_rules.push(RetentionRule::KeepWithin(d));
-        }
-        if let Some(n) = args.keep_yearly {
-            retention_rules.push(RetentionRule::KeepYearly(n));
-        }
-        if let Some(n) = args.keep_monthly {
-            retention_rules.push(RetentionRule::KeepMonthly(n));
-        }
-        if let Some(n) = args.keep_weekly {
-            retention_rules.push(RetentionRule::KeepWeekly(n));
-        }
-        if let Some(n) = args.keep_daily {
-            retention_rules.push(RetentionRule::KeepDaily(n));
-        }
-        if let Some(tags_str) = &args.keep_tags_str {
-            let keep_tags = parse_tags(Some(tags_str));
-            retention_rules.push(RetentionRule::KeepTags(keep_tags));
-        }
-
-        if retention_rules.is_empty() {
-            bail!("At least one retention rule must be used.");
+        if let Err(e) = retention.validate() {
+            match &args.policy {
+                Some(name) => bail!("Invalid retention policy '{}': {}", name, e),
+                None => return Err(e),
+            }
         }
 
-        ids_to_keep = apply_retention_rules(&snapshots_sorted, &retention_rules, Local::now());
-    }
+        let groups = retention.select(snapshots);
+        ids_to_keep = retention.ids_to_keep(&groups, Local::now());
 
-    let mut removed_ids_table =
-        Table::new_with_alignments(vec![Alignment::Left, Alignment::Center, Alignment::Right]);
-    removed_ids_table.set_headers(vec![
-        "ID".bold().to_string(),
-        "Date ▼".bold().to_string(),
-        "Size".bold().to_string(),
-        "Tags".bold().to_string(),
-    ]);
-
-    let mut kept_ids_table =
-        Table::new_with_alignments(vec![Alignment::Left, Alignment::Center, Alignment::Right]);
-    kept_ids_table.set_headers(vec![
-        "ID".bold().to_string(),
-        "Date Date ▼".bold().to_string(),
-        "Size".bold().to_string(),
-        "Tags".bold().to_string(),
-    ]);
+        // A stored policy is applied unattended. Refuse to wipe the history if it is wrong.
+        if let Some(name) = &args.policy
+            && keeps_no_snapshots(&groups, &ids_to_keep)
+        {
+            bail!("Retention policy '{}' would keep zero snapshots", name);
+        }
+        groups
+    };
 
     // Forget snapshots
     let mut removed_count = 0;
-    for (id, snapshot) in snapshots_sorted {
-        let table = if !ids_to_keep.contains(&id) {
-            repo.remove_snapshot(&id)?;
-            removed_count += 1;
-            &mut removed_ids_table
-        } else {
-            &mut kept_ids_table
-        };
+    for (group_key, snapshots_sorted) in groups {
+        let mut removed_ids_table =
+            Table::new_with_alignments(vec![Alignment::Left, Alignment::Center, Alignment::Right]);
+        removed_ids_table.set_headers(vec![
+            "ID".bold().to_string(),
+            "Date ▼".bold().to_string(),
+            "Size".bold().to_string(),
+            "Tags".bold().to_string(),
+        ]);
 
-        table.add_row(vec![
-            id.to_short_hex(global::defaults::SHORT_SNAPSHOT_ID_LEN)
+        let mut kept_ids_table =
+            Table::new_with_alignments(vec![Alignment::Left, Alignment::Center, Alignment::Right]);
+        kept_ids_table.set_headers(vec![
+            "ID".bold().to_string(),
+            "Date Date ▼".bold().to_string(),
+            "Size".bold().to_string(),
+            "Tags".bold().to_string(),
+        ]);
+
+        let mut group_removed_count = 0;
+        for (id, snapshot) in snapshots_sorted {
+            let table = if !ids_to_keep.contains(&id) {
+                repo.remove_snapshot(&id)?;
+                group_removed_count += 1;
+                &mut removed_ids_table
+            } else {
+                &mut kept_ids_table
+            };
+
+            let mut id_str = id
+                .to_short_hex(global::defaults::SHORT_SNAPSHOT_ID_LEN)
                 .bold()
                 .yellow()
-                .to_string(),
-            snapshot
-                .timestamp
-                .with_timezone(&Local)
-                .format("%Y-%m-%d %H:%M:%S %Z")
-                .to_string(),
-            utils::format_size(snapshot.size(), 3),
-            snapshot
-                .tags
-                .iter()
-                .map(|s| s.as_str())
-                .collect::<Vec<_>>()
-                .join(", "),
-        ]);
-    }
+                .to_string();
+            if snapshot.checkpoint {
+                id_str.push_str(" (checkpoint)");
+            }
 
-    ui::cli::log!();
-    ui::cli::log!(
-        "{}\n{}",
-        "Snapshots to keep:".bold(),
-        kept_ids_table.render()
-    );
+            table.add_row(vec![
+                id_str,
+                snapshot
+                    .timestamp
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S %Z")
+                    .to_string(),
+                utils::format_size(snapshot.size(), 3),
+                snapshot
+                    .tags
+                    .iter()
+                    .map(|s| s.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ]);
+        }
+        removed_count += group_removed_count;
 
-    if removed_count > 0 {
+        ui::cli::log!();
+        if !retention.group_by.is_empty() {
+            ui::cli::log!("{} {}", "Group:".bold(), group_key);
+        }
         ui::cli::log!(
             "{}\n{}",
-            "Snapshots to remove:".bold(),
-            removed_ids_table.render()
+            "Snapshots to keep:".bold(),
+            kept_ids_table.render()
         );
+
+        if group_removed_count > 0 {
+            ui::cli::log!(
+                "{}\n{}",
+                "Snapshots to remove:".bold(),
+                removed_ids_table.render()
+            );
+        }
     }
 
     if !args.dry_run {
@@ -395,6 +607,59 @@ pub fn apply_retention_rules(
                     snapshots_to_keep.insert(id.clone());
                 }
             }
+            RetentionRule::KeepHourly(n) => {
+                let mut kept_hours: BTreeMap<(i32, u32, u32, u32), ID> = BTreeMap::new(); // (Year, Month, Day, Hour) -> latest snapshot ID for that hour
+                for (id, snapshot) in snapshots_sorted.iter().rev() {
+                    kept_hours
+                        .entry(hour_bucket(&snapshot.timestamp))
+                        .or_insert(id.clone());
+                }
+                for (i, (_, id)) in kept_hours.iter().rev().enumerate() {
+                    if i >= *n {
+                        break;
+                    }
+
+                    snapshots_to_keep.insert(id.clone());
+                }
+            }
+            RetentionRule::KeepWithinHourly(duration) => {
+                snapshots_to_keep.extend(keep_within_buckets(
+                    snapshots_sorted,
+                    now - *duration,
+                    hour_bucket,
+                ));
+            }
+            RetentionRule::KeepWithinDaily(duration) => {
+                snapshots_to_keep.extend(keep_within_buckets(
+                    snapshots_sorted,
+                    now - *duration,
+                    |timestamp| (timestamp.year(), timestamp.month(), timestamp.day()),
+                ));
+            }
+            RetentionRule::KeepWithinWeekly(duration) => {
+                snapshots_to_keep.extend(keep_within_buckets(
+                    snapshots_sorted,
+                    now - *duration,
+                    |timestamp| {
+                        let iso_week = timestamp.iso_week();
+                        (iso_week.year(), iso_week.week())
+                    },
+                ));
+            }
+            RetentionRule::KeepWithinMonthly(duration) => {
+                snapshots_to_keep.extend(keep_within_buckets(
+                    snapshots_sorted,
+                    now - *duration,
+                    |timestamp| (timestamp.year(), timestamp.month()),
+                ));
+            }
+            RetentionRule::KeepWithinYearly(duration) => {
+                snapshots_to_keep.extend(keep_within_buckets(
+                    snapshots_sorted,
+                    now - *duration,
+                    |timestamp| timestamp.year(),
+                ));
+            }
             RetentionRule::KeepTags(tags) => {
                 for (id, snapshot) in snapshots_sorted.iter() {
                     if snapshot.has_tags(tags) {
@@ -408,6 +673,65 @@ pub fn apply_retention_rules(
     snapshots_to_keep
 }
 
+/// (Year, Month, Day, Hour) bucket of a timestamp.
+fn hour_bucket(timestamp: &DateTime<Local>) -> (i32, u32, u32, u32) {
+    (
+        timestamp.year(),
+        timestamp.month(),
+        timestamp.day(),
+        timestamp.hour(),
+    )
+}
+
+/// Returns the IDs of the latest snapshot of each bucket, considering only the snapshots newer
+/// than `cutoff_time`.
+///
+/// `snapshots_sorted`: A vector of (ID, Snapshot) tuples, sorted in ascending order by timestamp.
+fn keep_within_buckets<K, F>(
+    snapshots_sorted: &[(ID, Snapshot)],
+    cutoff_time: DateTime<Local>,
+    bucket: F,
+) -> HashSet<ID>
+where
+    K: Ord,
+    F: Fn(&DateTime<Local>) -> K,
+{
+    let mut kept_buckets: BTreeMap<K, ID> = BTreeMap::new();
+    for (id, snapshot) in snapshots_sorted.iter().rev() {
+        // Snapshots are sorted, so we can stop once we hit an older one
+        if snapshot.timestamp < cutoff_time {
+            break;
+        }
+        kept_buckets
+            .entry(bucket(&snapshot.timestamp))
+            .or_insert(id.clone());
+    }
+    kept_buckets.into_values().collect()
+}
+
+/// Returns the IDs of the checkpoints to keep.
+///
+/// Checkpoints are only kept while there is no newer complete snapshot, because they can still
+/// be resumed by the next snapshot. Otherwise, they are obsolete.
+///
+/// `snapshots_sorted`: A vector of (ID, Snapshot) tuples, sorted in ascending order by timestamp.
+pub fn checkpoints_to_keep(snapshots_sorted: &[(ID, Snapshot)]) -> HashSet<ID> {
+    let latest_complete_timestamp = snapshots_sorted
+        .iter()
+        .rev()
+        .find(|(_id, snapshot)| !snapshot.checkpoint)
+        .map(|(_id, snapshot)| snapshot.timestamp);
+
+    snapshots_sorted
+        .iter()
+        .filter(|(_id, snapshot)| {
+            snapshot.checkpoint
+                && latest_complete_timestamp.is_none_or(|timestamp| snapshot.timestamp > timestamp)
+        })
+        .map(|(id, _snapshot)| id.clone())
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use std::{collections::BTreeSet, path::PathBuf};
@@ -453,7 +777,8 @@ mod tests {
                         .map(|s| s.to_string())
                         .collect(),
                     description: None,
-                    summary: Default::default(),
+                    checkpoint: false,
+                    ..Default::default()
                 },
             ),
             (
@@ -481,7 +806,8 @@ mod tests {
                         .map(|s| s.to_string())
                         .collect(),
                     description: None,
-                    summary: Default::default(),
+                    checkpoint: false,
+                    ..Default::default()
                 },
             ),
             (
@@ -506,7 +832,8 @@ mod tests {
                     paths: Vec::new(),
                     tags: BTreeSet::new(),
                     description: None,
-                    summary: Default::default(),
+                    checkpoint: false,
+                    ..Default::default()
                 },
             ),
             (
@@ -531,7 +858,8 @@ mod tests {
                     paths: Vec::new(),
                     tags: BTreeSet::new(),
                     description: None,
-                    summary: Default::default(),
+                    checkpoint: false,
+                    ..Default::default()
                 },
             ),
             (
@@ -556,7 +884,8 @@ mod tests {
                     paths: Vec::new(),
                     tags: BTreeSet::new(),
                     description: None,
-                    summary: Default::default(),
+                    checkpoint: false,
+                    ..Default::default()
                 },
             ),
             // Weekly snapshots (e.g., one per week, starting from week 1, 2023)
@@ -583,7 +912,8 @@ mod tests {
                     paths: Vec::new(),
                     tags: BTreeSet::new(),
                     description: None,
-                    summary: Default::default(),
+                    checkpoint: false,
+                    ..Default::default()
                 },
             ),
             (
@@ -609,7 +939,8 @@ mod tests {
                     paths: Vec::new(),
                     tags: BTreeSet::new(),
                     description: None,
-                    summary: Default::default(),
+                    checkpoint: false,
+                    ..Default::default()
                 },
             ),
             (
@@ -635,7 +966,8 @@ mod tests {
                     paths: Vec::new(),
                     tags: BTreeSet::new(),
                     description: None,
-                    summary: Default::default(),
+                    checkpoint: false,
+                    ..Default::default()
                 },
             ),
             (
@@ -661,7 +993,8 @@ mod tests {
                     paths: Vec::new(),
                     tags: BTreeSet::new(),
                     description: None,
-                    summary: Default::default(),
+                    checkpoint: false,
+                    ..Default::default()
                 },
             ),
             (
@@ -687,7 +1020,8 @@ mod tests {
                     paths: Vec::new(),
                     tags: BTreeSet::new(),
                     description: None,
-                    summary: Default::default(),
+                    checkpoint: false,
+                    ..Default::default()
                 },
             ),
             // Monthly snapshots
@@ -713,7 +1047,8 @@ mod tests {
                     paths: Vec::new(),
                     tags: BTreeSet::new(),
                     description: None,
-                    summary: Default::default(),
+                    checkpoint: false,
+                    ..Default::default()
                 },
             ),
             (
@@ -738,7 +1073,8 @@ mod tests {
                     paths: Vec::new(),
                     tags: BTreeSet::new(),
                     description: None,
-                    summary: Default::default(),
+                    checkpoint: false,
+                    ..Default::default()
                 },
             ),
             // Yearly snapshots
@@ -764,7 +1100,8 @@ mod tests {
                     paths: Vec::new(),
                     tags: BTreeSet::new(),
                     description: None,
-                    summary: Default::default(),
+                    checkpoint: false,
+                    ..Default::default()
                 },
             ),
             (
@@ -789,7 +1126,8 @@ mod tests {
                     paths: Vec::new(),
                     tags: BTreeSet::new(),
                     description: None,
-                    summary: Default::default(),
+                    checkpoint: false,
+                    ..Default::default()
                 },
             ),
             (
@@ -814,7 +1152,8 @@ mod tests {
                     paths: Vec::new(),
                     tags: BTreeSet::new(),
                     description: None,
-                    summary: Default::default(),
+                    checkpoint: false,
+                    ..Default::default()
                 },
             ),
         ];
@@ -1035,4 +1374,279 @@ mod tests {
 
         assert_eq!(kept_ids, expected_ids);
     }
+
+    fn local_time(year: i32, month: u32, day: u32, hour: u32, min: u32) -> DateTime<Local> {
+        Local
+            .from_local_datetime(
+                &NaiveDate::from_ymd_opt(year, month, day)
+                    .unwrap()
+                    .and_hms_opt(hour, min, 0)
+                    .unwrap(),
+            )
+            .unwrap()
+    }
+
+    /// Creates sorted snapshots with the given timestamps. The ID of each snapshot is its index.
+    fn snapshots_at(timestamps: &[DateTime<Local>]) -> Vec<(ID, Snapshot)> {
+        timestamps
+            .iter()
+            .enumerate()
+            .map(|(i, timestamp)| {
+                (
+                    ID::from_hex(&format!("{i:064x}")).unwrap(),
+                    Snapshot {
+                        timestamp: *timestamp,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn ids(snapshots: &[(ID, Snapshot)], indices: &[usize]) -> HashSet<ID> {
+        indices.iter().map(|i| snapshots[*i].0.clone()).collect()
+    }
+
+    #[test]
+    fn test_keep_hourly() {
+        let snapshots = snapshots_at(&[
+            local_time(2025, 5, 25, 10, 0),
+            local_time(2025, 5, 25, 10, 30),
+            local_time(2025, 5, 25, 11, 15),
+            local_time(2025, 5, 25, 12, 45),
+            local_time(2025, 5, 25, 12, 50),
+        ]);
+        let rules = vec![RetentionRule::KeepHourly(2)];
+
+        let kept_ids = apply_retention_rules(&snapshots, &rules, local_time(2025, 5, 25, 13, 0));
+
+        assert_eq!(kept_ids, ids(&snapshots, &[2, 4]));
+    }
+
+    #[test]
+    fn test_keep_within_hourly() {
+        let snapshots = snapshots_at(&[
+            local_time(2025, 5, 24, 23, 10),
+            local_time(2025, 5, 25, 9, 5),
+            local_time(2025, 5, 25, 9, 55),
+            local_time(2025, 5, 25, 11, 20),
+        ]);
+        let rules = vec![RetentionRule::KeepWithinHourly(Duration::hours(6))];
+
+        let kept_ids = apply_retention_rules(&snapshots, &rules, local_time(2025, 5, 25, 13, 0));
+
+        assert_eq!(kept_ids, ids(&snapshots, &[2, 3]));
+    }
+
+    #[test]
+    fn test_keep_within_daily() {
+        let snapshots = snapshots_at(&[
+            local_time(2025, 5, 10, 12, 0),
+            local_time(2025, 5, 20, 8, 0),
+            local_time(2025, 5, 20, 20, 0),
+            local_time(2025, 5, 22, 9, 0),
+            local_time(2025, 5, 24, 7, 0),
+            local_time(2025, 5, 24, 18, 0),
+        ]);
+        let rules = vec![RetentionRule::KeepWithinDaily(Duration::weeks(1))];
+
+        let kept_ids = apply_retention_rules(&snapshots, &rules, local_time(2025, 5, 25, 12, 0));
+
+        assert_eq!(kept_ids, ids(&snapshots, &[2, 3, 5]));
+    }
+
+    #[test]
+    fn test_keep_within_weekly_monthly_yearly() {
+        let snapshots = snapshots_at(&[
+            local_time(2023, 6, 1, 12, 0),
+            local_time(2024, 11, 4, 12, 0),
+            local_time(2025, 3, 3, 12, 0),
+            local_time(2025, 3, 28, 12, 0),
+            local_time(2025, 5, 12, 12, 0),
+            local_time(2025, 5, 14, 12, 0),
+            local_time(2025, 5, 21, 12, 0),
+        ]);
+        let now = local_time(2025, 5, 25, 12, 0);
+
+        // Weeks of May 12th and May 19th
+        let rules = vec![RetentionRule::KeepWithinWeekly(Duration::days(20))];
+        let kept_ids = apply_retention_rules(&snapshots, &rules, now);
+        assert_eq!(kept_ids, ids(&snapshots, &[5, 6]));
+
+        // March and May 2025
+        let rules = vec![RetentionRule::KeepWithinMonthly(Duration::days(90))];
+        let kept_ids = apply_retention_rules(&snapshots, &rules, now);
+        assert_eq!(kept_ids, ids(&snapshots, &[3, 6]));
+
+        // 2024 and 2025, but not 2023
+        let rules = vec![RetentionRule::KeepWithinYearly(Duration::days(365))];
+        let kept_ids = apply_retention_rules(&snapshots, &rules, now);
+        assert_eq!(kept_ids, ids(&snapshots, &[1, 6]));
+    }
+
+    #[test]
+    fn test_checkpoints_to_keep() {
+        let mut snapshots = create_mock_snapshots();
+        snapshots.sort_by_key(|(_id, snapshot)| snapshot.timestamp);
+        let n = snapshots.len();
+
+        // No checkpoints
+        assert!(checkpoints_to_keep(&snapshots).is_empty());
+
+        // Checkpoints older than the latest complete snapshot are obsolete
+        snapshots[n - 2].1.checkpoint = true;
+        assert!(checkpoints_to_keep(&snapshots).is_empty());
+
+        // Checkpoints newer than the latest complete snapshot can still be resumed
+        snapshots[n - 1].1.checkpoint = true;
+        let expected_ids: HashSet<ID> = [snapshots[n - 2].0.clone(), snapshots[n - 1].0.clone()]
+            .into_iter()
+            .collect();
+        assert_eq!(checkpoints_to_keep(&snapshots), expected_ids);
+    }
+
+    #[test]
+    fn test_parse_group_by() {
+        assert!(parse_group_by("").unwrap().is_empty());
+        assert_eq!(
+            parse_group_by("host, tags").unwrap(),
+            GroupBy {
+                host: true,
+                paths: false,
+                tags: true
+            }
+        );
+        assert!(parse_group_by("host,user").is_err());
+    }
+
+    #[test]
+    fn test_keep_last_grouped_by_host() {
+        let mut snapshots = create_mock_snapshots();
+        snapshots.sort_by_key(|(_id, snapshot)| snapshot.timestamp);
+        for (i, (_id, snapshot)) in snapshots.iter_mut().enumerate() {
+            snapshot.hostname = if i % 2 == 0 { "alpha" } else { "beta" }.to_string();
+        }
+
+        let group_by = parse_group_by("host").unwrap();
+        let groups = group_snapshots(snapshots.clone(), group_by);
+        assert_eq!(groups.len(), 2);
+
+        let mut kept_ids = HashSet::new();
+        for group in groups.values() {
+            kept_ids.extend(apply_retention_rules(
+                group,
+                &[RetentionRule::KeepLast(1)],
+                test_now(),
+            ));
+        }
+
+        // The latest snapshot of each host is kept
+        let n = snapshots.len();
+        let expected_ids: HashSet<ID> = [snapshots[n - 2].0.clone(), snapshots[n - 1].0.clone()]
+            .into_iter()
+            .collect();
+        assert_eq!(kept_ids, expected_ids);
+    }
+
+    #[test]
+    fn test_retention_from_policy() {
+        let policy = RetentionPolicy {
+            group_by: Some("host".to_string()),
+            keep_daily: Some(7),
+            keep_within_weekly: Some("8w".to_string()),
+            ..Default::default()
+        };
+        let retention = Retention::from_policy(&policy).unwrap();
+        assert!(retention.group_by.host);
+        assert_eq!(
+            retention.rules,
+            vec![
+                RetentionRule::KeepDaily(7),
+                RetentionRule::KeepWithinWeekly(Duration::weeks(8))
+            ]
+        );
+
+        // Invalid values and policies that can't keep any snapshot are refused
+        let invalid_policies = [
+            RetentionPolicy::default(),
+            RetentionPolicy {
+                keep_last: Some(0),
+                keep_daily: Some(7),
+                ..Default::default()
+            },
+            RetentionPolicy {
+                keep_within: Some("0d".to_string()),
+                ..Default::default()
+            },
+            RetentionPolicy {
+                keep_within: Some("2 weeks".to_string()),
+                ..Default::default()
+            },
+            RetentionPolicy {
+                keep_last: Some(1),
+                group_by: Some("user".to_string()),
+                ..Default::default()
+            },
+        ];
+        for policy in invalid_policies {
+            let retention = Retention::from_policy(&policy);
+            assert!(
+                retention
+                    .and_then(|retention| retention.validate())
+                    .is_err(),
+                "{policy:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_retention_args() {
+        let args = CmdArgs::try_parse_from([
+            "forget",
+            "--host",
+            "laptop",
+            "--keep-last",
+            "3",
+            "--keep-daily",
+            "all",
+            "--keep-tags",
+            "important",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.rules,
+            RetentionPolicy {
+                host: Some("laptop".to_string()),
+                keep_last: Some(3),
+                keep_daily: Some(usize::MAX),
+                keep_tags: Some("important".to_string()),
+                ..Default::default()
+            }
+        );
+
+        assert!(CmdArgs::try_parse_from(["forget", "--keep-last", "0"]).is_err());
+        assert!(
+            CmdArgs::try_parse_from(["forget", "--policy", "daily", "--keep-last", "1"]).is_err()
+        );
+        assert!(CmdArgs::try_parse_from(["forget", "--policy", "daily", "--tags", "a"]).is_err());
+    }
+
+    #[test]
+    fn test_keeps_no_snapshots() {
+        let mut snapshots = create_mock_snapshots();
+        snapshots.sort_by_key(|(_id, snapshot)| snapshot.timestamp);
+        let groups = group_snapshots(snapshots.clone(), GroupBy::default());
+
+        assert!(keeps_no_snapshots(&groups, &HashSet::new()));
+        assert!(!keeps_no_snapshots(&groups, &ids(&snapshots, &[0])));
+
+        // Keeping only checkpoints doesn't count
+        let n = snapshots.len();
+        snapshots[n - 1].1.checkpoint = true;
+        let groups = group_snapshots(snapshots.clone(), GroupBy::default());
+        assert!(keeps_no_snapshots(&groups, &ids(&snapshots, &[n - 1])));
+
+        // Nothing to keep if there are no snapshots
+        assert!(!keeps_no_snapshots(&BTreeMap::new(), &HashSet::new()));
+    }
 }
@@ -20,6 +20,7 @@ use colored::Colorize;
 
 use crate::backend::new_backend_with_prompt;
 use crate::repository::repo::Repository;
+use crate::repository::storage::{Compression, CompressionMode};
 use crate::ui;
 use crate::utils;
 
@@ -27,14 +28,30 @@ use super::GlobalArgs;
 
 #[derive(Args, Debug)]
 #[clap(about = "Initialize a new repository")]
-pub struct CmdArgs {}
+pub struct CmdArgs {
+    /// Default compression: 'zstd[:LEVEL]', 'zstd-long[:LEVEL]', 'lz4' or 'none'. It can be
+    /// overridden for each snapshot.
+    #[clap(long, default_value_t = Compression::default())]
+    pub compression: Compression,
+
+    /// When to compress the data. 'auto' stores incompressible data (media, archives, ...)
+    /// without compression.
+    #[clap(long, value_enum, default_value_t = CompressionMode::Auto)]
+    pub compression_mode: CompressionMode,
+}
 
-pub fn run(global_args: &GlobalArgs, _args: &CmdArgs) -> Result<()> {
+pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
     let pass = utils::get_password_from_file(&global_args.password_file)?;
     let backend = new_backend_with_prompt(global_args, false)?;
 
     ui::cli::log!("Initializing a new repository in \'{}\'", &global_args.repo);
-    Repository::init(pass, global_args.key.as_ref(), backend)?;
+    Repository::init(
+        pass,
+        global_args.key.as_ref(),
+        backend,
+        args.compression,
+        args.compression_mode,
+    )?;
 
     ui::cli::warning!(
         "{}\n{}",
@@ -49,6 +49,10 @@ pub struct CmdArgs {
     /// Only consider snapshots with tags: tag[,tag,...]
     #[arg(long = "tags", value_parser)]
     pub tags_str: Option<String>,
+
+    /// Only consider snapshots of this host
+    #[arg(long, value_parser)]
+    pub host: Option<String>,
 }
 
 pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
@@ -57,6 +61,7 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
 
     let config = RepoConfig {
         pack_size: (global_args.pack_size_mib * size::MiB as f32) as u64,
+        compression: None,
     };
     let (repo, _) = Repository::try_open(pass, global_args.key.as_ref(), backend, config)?;
 
@@ -75,6 +80,9 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
         let tags = parse_tags(Some(tags_str));
         snapshots_sorted.retain(|(_id, sn)| sn.has_tags(&tags));
     }
+    if let Some(host) = &args.host {
+        snapshots_sorted.retain(|(_id, sn)| sn.is_from_host(host));
+    }
     snapshots_sorted.sort_by_key(|(_id, snapshot)| snapshot.timestamp);
 
     if snapshots_sorted.is_empty() {
@@ -97,7 +105,11 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
 fn log(snapshots: &[(ID, Snapshot)]) {
     let mut peekable_snapshots = snapshots.iter().peekable();
     while let Some((id, snapshot)) = peekable_snapshots.next() {
-        ui::cli::log!("{}", id.to_hex().bold().yellow());
+        if snapshot.checkpoint {
+            ui::cli::log!("{} {}", id.to_hex().bold().yellow(), "(checkpoint)".bold());
+        } else {
+            ui::cli::log!("{}", id.to_hex().bold().yellow());
+        }
         ui::cli::log!(
             "{} {}",
             "Date:".bold(),
@@ -109,6 +121,23 @@ fn log(snapshots: &[(ID, Snapshot)]) {
             utils::format_size(snapshot.summary.processed_bytes, 3)
         );
         ui::cli::log!("{} {}", "Root:".bold(), &snapshot.root.display());
+        if !snapshot.hostname.is_empty() {
+            ui::cli::log!("{} {}", "Host:".bold(), snapshot.hostname);
+        }
+        if !snapshot.username.is_empty() {
+            ui::cli::log!("{} {}", "User:".bold(), snapshot.username);
+        }
+        if !snapshot.program_version.is_empty() {
+            ui::cli::log!("{} {}", "Program:".bold(), snapshot.program_version);
+        }
+
+        if snapshot.summary.inconsistent_files_count > 0 {
+            ui::cli::log!(
+                "{} {}",
+                "Inconsistent files:".bold().yellow(),
+                snapshot.summary.inconsistent_files_count
+            );
+        }
 
         if !snapshot.tags.is_empty() {
             ui::cli::log!(
@@ -147,23 +176,40 @@ fn log(snapshots: &[(ID, Snapshot)]) {
 }
 
 fn log_compact(snapshots: &Vec<(ID, Snapshot)>) {
-    let mut table =
-        Table::new_with_alignments(vec![Alignment::Left, Alignment::Center, Alignment::Right]);
+    let mut table = Table::new_with_alignments(vec![
+        Alignment::Left,
+        Alignment::Center,
+        Alignment::Left,
+        Alignment::Right,
+    ]);
 
     table.set_headers(vec![
         "ID".bold().to_string(),
         "Date ▼".bold().to_string(),
+        "Host".bold().to_string(),
         "Size".bold().to_string(),
         "Tags".bold().to_string(),
     ]);
 
+    let mut has_inconsistent_snapshots = false;
     for (id, snapshot) in snapshots {
+        let mut id_str = id
+            .to_short_hex(global::defaults::SHORT_SNAPSHOT_ID_LEN)
+            .bold()
+            .yellow()
+            .to_string();
+        if snapshot.checkpoint {
+            id_str.push_str(" (checkpoint)");
+        }
+        if snapshot.summary.inconsistent_files_count > 0 {
+            id_str.push_str(&format!(" {}", "!".bold().yellow()));
+            has_inconsistent_snapshots = true;
+        }
+
         table.add_row(vec![
-            id.to_short_hex(global::defaults::SHORT_SNAPSHOT_ID_LEN)
-                .bold()
-                .yellow()
-                .to_string(),
+            id_str,
             utils::pretty_print_timestamp(&snapshot.timestamp),
+            snapshot.hostname.clone(),
             utils::format_size(snapshot.size(), 3),
             snapshot
                 .tags
@@ -175,4 +221,11 @@ fn log_compact(snapshots: &Vec<(ID, Snapshot)>) {
     }
 
     ui::cli::log!("{}", table.render());
+
+    if has_inconsistent_snapshots {
+        ui::cli::log!(
+            "{} Contains files that changed while being read\n",
+            "!".bold().yellow()
+        );
+    }
 }
@@ -39,6 +39,10 @@ pub struct CmdArgs {
     #[clap(value_parser, default_value_t = UseSnapshot::Latest)]
     pub snapshot: UseSnapshot,
 
+    /// Only consider snapshots of this host when selecting 'latest'
+    #[clap(long, value_parser)]
+    pub host: Option<String>,
+
     /// Path
     #[clap(long, value_parser)]
     pub path: Option<PathBuf>,
@@ -62,11 +66,12 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
 
     let config = RepoConfig {
         pack_size: (global_args.pack_size_mib * size::MiB as f32) as u64,
+        compression: None,
     };
     let (repo, _) = Repository::try_open(pass, global_args.key.as_ref(), backend, config)?;
 
     let (_snapshot_id, snapshot) = {
-        match find_use_snapshot(repo.clone(), &args.snapshot) {
+        match find_use_snapshot(repo.clone(), &args.snapshot, args.host.as_deref()) {
             Ok(Some((id, snap))) => (id, snap),
             Ok(None) | Err(_) => bail!("Snapshot not found"),
         }
@@ -85,6 +90,7 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
             blobs: None,
             tree: Some(snapshot.tree.clone()),
             symlink_info: None,
+            inconsistent: false,
         },
     };
 
@@ -174,6 +180,18 @@ fn node_to_string(node: &Node, long: bool, human_readable: bool) -> String {
 
         const NA: &str = "_";
 
+        // Prefer the names saved in the snapshot
+        let owner_str = node.metadata.owner_user.clone().unwrap_or_else(|| {
+            node.metadata
+                .owner_uid
+                .map_or(NA.to_string(), |uid| uid.to_string())
+        });
+        let group_str = node.metadata.owner_group.clone().unwrap_or_else(|| {
+            node.metadata
+                .owner_gid
+                .map_or(NA.to_string(), |gid| gid.to_string())
+        });
+
         format!(
             "{:10} {:3} {:7}  {:7}  {:>14}  {:12}  {}",
             node.metadata.mode.map_or(NA.to_string(), |mode| {
@@ -182,12 +200,8 @@ fn node_to_string(node: &Node, long: bool, human_readable: bool) -> String {
             node.metadata
                 .nlink
                 .map_or(NA.to_string(), |nlink| nlink.to_string()),
-            node.metadata
-                .owner_uid
-                .map_or(NA.to_string(), |uid| uid.to_string()),
-            node.metadata
-                .owner_gid
-                .map_or(NA.to_string(), |gid| gid.to_string()),
+            owner_str,
+            group_str,
             size_str,
             node.metadata.modified_time.map_or(NA.to_string(), |mtime| {
                 utils::pretty_print_system_time(mtime, None).unwrap_or(String::from("Error"))
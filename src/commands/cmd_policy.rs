@@ -0,0 +1,194 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use anyhow::{Result, anyhow, bail};
+use chrono::Local;
+use clap::{Args, Subcommand};
+use colored::Colorize;
+
+use crate::{
+    backend::new_backend_with_prompt,
+    commands::{
+        GlobalArgs,
+        cmd_forget::{Retention, keeps_no_snapshots},
+    },
+    repository::{
+        policy::RetentionPolicy,
+        repo::{RepoConfig, Repository},
+        snapshot::SnapshotStreamer,
+    },
+    ui::{
+        self,
+        table::{Alignment, Table},
+    },
+    utils::{self, size},
+};
+
+#[derive(Args, Debug)]
+#[clap(
+    about = "Manage the retention policies stored in the repository",
+    long_about = "Manage the retention policies stored in the repository. A policy is a named \
+                  set of retention rules and filters that can be applied with \
+                  `forget --policy <NAME>`."
+)]
+pub struct CmdArgs {
+    #[command(subcommand)]
+    pub action: PolicyAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PolicyAction {
+    /// List all retention policies
+    List,
+
+    /// Show a retention policy
+    Show {
+        /// Name of the policy
+        name: String,
+    },
+
+    /// Create or replace a retention policy
+    Set(Box<SetArgs>),
+
+    /// Remove a retention policy
+    Remove {
+        /// Name of the policy
+        name: String,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct SetArgs {
+    /// Name of the policy
+    pub name: String,
+
+    #[command(flatten)]
+    pub policy: RetentionPolicy,
+}
+
+pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
+    let pass = utils::get_password_from_file(&global_args.password_file)?;
+    let backend = new_backend_with_prompt(global_args, false)?;
+
+    let config = RepoConfig {
+        pack_size: (global_args.pack_size_mib * size::MiB as f32) as u64,
+        compression: None,
+    };
+    let (repo, _) = Repository::try_open(pass, global_args.key.as_ref(), backend, config)?;
+
+    let mut policies = repo.load_policies()?;
+
+    match &args.action {
+        PolicyAction::List => {
+            if policies.policies.is_empty() {
+                ui::cli::log!("No retention policies found");
+                return Ok(());
+            }
+
+            let mut table = Table::new_with_alignments(vec![Alignment::Left, Alignment::Left]);
+            table.set_headers(vec!["Name".bold().to_string(), "Rules".bold().to_string()]);
+            for (name, policy) in &policies.policies {
+                table.add_row(vec![
+                    name.bold().to_string(),
+                    policy_flags(policy).join(" "),
+                ]);
+            }
+            ui::cli::log!("{}", table.render());
+        }
+        PolicyAction::Show { name } => {
+            let Some(policy) = policies.policies.get(name) else {
+                bail!("Retention policy '{}' does not exist", name);
+            };
+
+            ui::cli::log!("{}", name.bold());
+            for flag in policy_flags(policy) {
+                ui::cli::log!("  {}", flag);
+            }
+        }
+        PolicyAction::Set(set_args) => {
+            let policy = set_args.policy.clone();
+            let retention = Retention::from_policy(&policy)
+                .and_then(|retention| retention.validate().map(|_| retention))
+                .map_err(|e| anyhow!("Invalid retention policy '{}': {}", set_args.name, e))?;
+
+            // Check the policy against the snapshots currently in the repository
+            let groups = retention.select(SnapshotStreamer::new(repo.clone())?.collect());
+            let ids_to_keep = retention.ids_to_keep(&groups, Local::now());
+            if keeps_no_snapshots(&groups, &ids_to_keep) {
+                bail!(
+                    "Retention policy '{}' would keep zero snapshots",
+                    set_args.name
+                );
+            }
+
+            let replaced = policies
+                .policies
+                .insert(set_args.name.clone(), policy)
+                .is_some();
+            repo.save_policies(&policies)?;
+
+            if replaced {
+                ui::cli::log!("Updated retention policy '{}'", set_args.name);
+            } else {
+                ui::cli::log!("Created retention policy '{}'", set_args.name);
+            }
+        }
+        PolicyAction::Remove { name } => {
+            if policies.policies.remove(name).is_none() {
+                bail!("Retention policy '{}' does not exist", name);
+            }
+            repo.save_policies(&policies)?;
+            ui::cli::log!("Removed retention policy '{}'", name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the `forget` arguments equivalent to a policy.
+fn policy_flags(policy: &RetentionPolicy) -> Vec<String> {
+    let mut flags = Vec::new();
+    let count = |n: Option<usize>| {
+        n.map(|n| match n {
+            usize::MAX => "all".to_string(),
+            n => n.to_string(),
+        })
+    };
+    let mut push = |flag: &str, value: Option<String>| {
+        if let Some(value) = value {
+            flags.push(format!("--{flag} {value}"));
+        }
+    };
+
+    push("tags", policy.tags.clone());
+    push("host", policy.host.clone());
+    push("group-by", policy.group_by.clone());
+    push("keep-last", count(policy.keep_last));
+    push("keep-within", policy.keep_within.clone());
+    push("keep-within-hourly", policy.keep_within_hourly.clone());
+    push("keep-within-daily", policy.keep_within_daily.clone());
+    push("keep-within-weekly", policy.keep_within_weekly.clone());
+    push("keep-within-monthly", policy.keep_within_monthly.clone());
+    push("keep-within-yearly", policy.keep_within_yearly.clone());
+    push("keep-yearly", count(policy.keep_yearly));
+    push("keep-monthly", count(policy.keep_monthly));
+    push("keep-weekly", count(policy.keep_weekly));
+    push("keep-daily", count(policy.keep_daily));
+    push("keep-hourly", count(policy.keep_hourly));
+    push("keep-tags", policy.keep_tags.clone());
+
+    flags
+}
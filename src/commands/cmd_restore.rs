@@ -28,12 +28,18 @@ use indicatif::{ProgressBar, ProgressStyle};
 use crate::{
     backend::new_backend_with_prompt,
     commands::{GlobalArgs, UseSnapshot, find_use_snapshot},
-    global::defaults::SHORT_SNAPSHOT_ID_LEN,
+    global::{
+        ID,
+        defaults::{DEFAULT_RESTORE_CONCURRENCY, SHORT_SNAPSHOT_ID_LEN},
+    },
     repository::{
-        repo::RepoConfig, repo::Repository, streamers::SerializedNodeStreamer,
+        repo::RepoConfig, repo::Repository, snapshot::Snapshot, streamers::SerializedNodeStreamer,
         verify::verify_snapshot_links,
     },
-    restorer::{self, Resolution, Restorer},
+    restorer::{
+        self, Resolution, Restorer,
+        owners::{OwnerMapping, OwnerOptions},
+    },
     ui::{
         self, PROGRESS_REFRESH_RATE_HZ, SPINNER_TICK_CHARS, cli, default_bar_draw_target,
         restore_progress::RestoreProgressReporter,
@@ -47,6 +53,9 @@ impl std::fmt::Display for Resolution {
             Resolution::Skip => write!(f, "skip"),
             Resolution::Overwrite => write!(f, "overwrite"),
             Resolution::Fail => write!(f, "fail"),
+            Resolution::IfNewer => write!(f, "if-newer"),
+            Resolution::IfChanged => write!(f, "if-changed"),
+            Resolution::Rename => write!(f, "rename"),
         }
     }
 }
@@ -84,9 +93,66 @@ pub struct CmdArgs {
     /// skip: Skips restoring the conflicting item.
     /// overwrite: Overwrites the item in the target location.
     /// fail: Terminates the command with an error.
+    /// if-newer: Overwrites the item only if it is newer in the snapshot.
+    /// if-changed: Overwrites the item only if its size, modification time or contents differ.
+    /// rename: Restores the item alongside the existing one, as `name.mapache-<snapshot id>`.
     #[clap(long, default_value_t=Resolution::Fail)]
     pub resolution: Resolution,
 
+    /// Update existing files in place, only downloading the chunks that differ. Existing files are
+    /// read and chunked. Files with the same size, modification time and contents as in the
+    /// snapshot are skipped, and only their metadata is restored. Other existing items are
+    /// overwritten.
+    #[clap(long, default_value_t = false, conflicts_with = "resolution")]
+    pub delta: bool,
+
+    /// Resume an interrupted restore of the same snapshot into the same target. Files recorded as
+    /// completed in the restore journal are skipped. Other existing files are validated by size
+    /// and contents, and only their missing chunks are restored.
+    #[clap(long, default_value_t = false, conflicts_with_all = ["resolution", "delta"])]
+    pub resume: bool,
+
+    /// Delete items in the target that are not present in the snapshot. Only directories whose
+    /// contents are fully restored are cleaned up, and excluded paths are never deleted.
+    #[clap(long, default_value_t = false)]
+    pub delete: bool,
+
+    /// Restore the numeric user and group IDs saved in the snapshot. By default, owners are
+    /// matched by user and group name, and the numeric IDs are only used for names unknown in
+    /// this host.
+    #[clap(long, default_value_t = false)]
+    pub numeric_owner: bool,
+
+    /// Don't restore owners. Restored items belong to the user running the command.
+    #[clap(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["numeric_owner", "map_user", "map_group", "same_owner_as_target"]
+    )]
+    pub no_owner: bool,
+
+    /// Map a user of the snapshot to a user in this host: old=new. Users can be given as names or
+    /// numeric IDs. Can be used multiple times.
+    #[clap(long, value_parser = clap::value_parser!(OwnerMapping))]
+    pub map_user: Vec<OwnerMapping>,
+
+    /// Map a group of the snapshot to a group in this host: old=new. Groups can be given as names
+    /// or numeric IDs. Can be used multiple times.
+    #[clap(long, value_parser = clap::value_parser!(OwnerMapping))]
+    pub map_group: Vec<OwnerMapping>,
+
+    /// Give all restored items the owner and group of the target directory.
+    #[clap(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["numeric_owner", "map_user", "map_group"]
+    )]
+    pub same_owner_as_target: bool,
+
+    /// Number of packs read and restored in parallel
+    #[clap(long, default_value_t = DEFAULT_RESTORE_CONCURRENCY)]
+    pub restore_concurrency: usize,
+
     /// Skip verification of data
     #[clap(long = "no-verify", value_parser, default_value_t = false)]
     pub no_verify: bool,
@@ -94,6 +160,15 @@ pub struct CmdArgs {
     /// Dry run
     #[clap(long, default_value_t = false)]
     pub dry_run: bool,
+
+    /// Don't restore anything. Instead, compare the target with the snapshot, reading every file
+    /// to compare its contents, and report missing, extra and differing items.
+    #[clap(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["dry_run", "delta", "resume", "delete", "resolution"]
+    )]
+    pub verify_only: bool,
 }
 
 pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
@@ -102,10 +177,11 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
 
     let config = RepoConfig {
         pack_size: (global_args.pack_size_mib * size::MiB as f32) as u64,
+        compression: None,
     };
     let (repo, _) = Repository::try_open(pass, global_args.key.as_ref(), backend.clone(), config)?;
 
-    let (snapshot_id, snapshot) = match find_use_snapshot(repo.clone(), &args.snapshot) {
+    let (snapshot_id, snapshot) = match find_use_snapshot(repo.clone(), &args.snapshot, None) {
         Ok(Some((id, snap))) => (id, snap),
         Ok(None) | Err(_) => bail!("Snapshot not found"),
     };
@@ -124,6 +200,18 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
         ui::cli::log!("{}\n", "[OK]".bold().green());
     }
 
+    let owners = OwnerOptions {
+        numeric: args.numeric_owner,
+        no_owner: args.no_owner,
+        same_as_target: args.same_owner_as_target,
+        user_map: args.map_user.clone(),
+        group_map: args.map_group.clone(),
+    };
+
+    if args.verify_only {
+        return verify_target(repo, &snapshot_id, &snapshot, args, common_prefix, &owners);
+    }
+
     ui::cli::log!(
         "Restoring snapshot {}",
         snapshot_id
@@ -194,6 +282,7 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
 
     Restorer::restore(
         repo.clone(),
+        &snapshot_id,
         &snapshot,
         &args.target,
         args.include.clone(),
@@ -202,6 +291,11 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
             dry_run: args.dry_run,
             resolution: args.resolution.clone(),
             strip_prefix: common_prefix,
+            concurrency: args.restore_concurrency,
+            delta: args.delta,
+            delete: args.delete,
+            resume: args.resume,
+            owners,
         },
         progress_reporter.clone(),
     )?;
@@ -215,3 +309,52 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
 
     Ok(())
 }
+
+fn verify_target(
+    repo: Arc<Repository>,
+    snapshot_id: &ID,
+    snapshot: &Snapshot,
+    args: &CmdArgs,
+    common_prefix: Option<PathBuf>,
+    owners: &OwnerOptions,
+) -> Result<()> {
+    ui::cli::log!(
+        "Comparing {} with snapshot {}",
+        args.target.display(),
+        snapshot_id
+            .to_short_hex(SHORT_SNAPSHOT_ID_LEN)
+            .bold()
+            .yellow()
+    );
+
+    let start = Instant::now();
+    let check = Restorer::verify(
+        repo,
+        snapshot,
+        &args.target,
+        args.include.clone(),
+        args.exclude.clone(),
+        common_prefix,
+        owners,
+    )?;
+
+    ui::cli::log!();
+    ui::cli::log!(
+        "{} matching, {} missing, {} extra, {} differing",
+        check.matching,
+        check.missing,
+        check.extra,
+        check.differing
+    );
+    cli::log!(
+        "Finished in {}",
+        utils::pretty_print_duration(start.elapsed())
+    );
+
+    if !check.is_ok() {
+        bail!("The target does not match the snapshot");
+    }
+
+    ui::cli::log!("{}", "[OK]".bold().green());
+    Ok(())
+}
@@ -16,12 +16,13 @@
 
 use std::{
     collections::BTreeSet,
-    path::PathBuf,
+    io::Read,
+    path::{Path, PathBuf},
     sync::Arc,
     time::{Duration, Instant},
 };
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
 use clap::{ArgGroup, Args};
 use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
@@ -34,15 +35,21 @@ use crate::{
     repository::{
         repo::RepoConfig,
         repo::Repository,
-        snapshot::{SnapshotSummary, SnapshotTuple},
-        streamers::FSNodeStreamer,
+        snapshot::{SnapshotStreamer, SnapshotSummary, SnapshotTuple},
+        storage::Compression,
+        streamers::{ExcludeOptions, FSNodeStreamer},
+        tree::ChangeDetection,
     },
     ui::{
         self, PROGRESS_REFRESH_RATE_HZ, SPINNER_TICK_CHARS, default_bar_draw_target,
         snapshot_progress::SnapshotProgressReporter,
         table::{Alignment, Table},
     },
-    utils::{self, format_size, size},
+    utils::{
+        self, format_size,
+        priority::{IoniceClass, ThreadPriority},
+        size,
+    },
 };
 
 use super::{GlobalArgs, UseSnapshot};
@@ -52,9 +59,27 @@ use super::{GlobalArgs, UseSnapshot};
 #[clap(about = "Create a new snapshot")]
 pub struct CmdArgs {
     /// List of paths to backup
-    #[clap(value_parser, required = true)]
+    #[clap(
+        value_parser,
+        required_unless_present_any = ["files_from", "files_from_verbatim", "files_from_raw"]
+    )]
     pub paths: Vec<PathBuf>,
 
+    /// Read the paths to backup from a file ('-' for stdin), one per line. Empty lines and
+    /// lines starting with '#' are ignored. Can be used multiple times.
+    #[clap(long, value_parser)]
+    pub files_from: Vec<PathBuf>,
+
+    /// Read the paths to backup from a file ('-' for stdin), one per line, without
+    /// interpreting comments or trimming whitespace. Can be used multiple times.
+    #[clap(long, value_parser)]
+    pub files_from_verbatim: Vec<PathBuf>,
+
+    /// Read the paths to backup from a file ('-' for stdin) separated by NUL bytes,
+    /// e.g. from `find -print0`. Can be used multiple times.
+    #[clap(long, value_parser)]
+    pub files_from_raw: Vec<PathBuf>,
+
     /// Use a single directory path as the snapshot root
     #[clap(long = "as-root", value_parser, default_value_t = false)]
     pub as_root: bool,
@@ -63,6 +88,22 @@ pub struct CmdArgs {
     #[clap(long, value_parser, value_delimiter = ',', required = false)]
     pub exclude: Option<Vec<PathBuf>>,
 
+    /// Exclude directories containing a CACHEDIR.TAG file
+    #[clap(long, default_value_t = false)]
+    pub exclude_caches: bool,
+
+    /// Exclude directories containing a file with this name. Can be used multiple times.
+    #[clap(long, value_parser, required = false)]
+    pub exclude_if_present: Vec<String>,
+
+    /// Do not cross filesystem boundaries. Directories in a different filesystem are skipped.
+    #[clap(long, default_value_t = false)]
+    pub one_file_system: bool,
+
+    /// Exclude files larger than this size (e.g. 500K, 20M, 1G)
+    #[clap(long, value_parser = utils::parse_size_string)]
+    pub exclude_larger_than: Option<u64>,
+
     /// Tags
     #[clap(long = "tags", value_parser, default_value_t = EMPTY_TAG_MARK.to_string())]
     pub tags_str: String,
@@ -71,15 +112,60 @@ pub struct CmdArgs {
     #[clap(long, value_parser)]
     pub description: Option<String>,
 
+    /// Host name recorded in the snapshot. Defaults to the name of this machine.
+    #[clap(long, value_parser)]
+    pub host: Option<String>,
+
     /// Force a complete analysis of all files and directories
     #[clap(long = "no-parent", group = "scan_mode")]
     pub rescan: bool,
 
     /// Use a snapshot as parent (ID or 'latest'). This snapshot will be the base when analyzing differences.
+    /// 'latest' selects the latest snapshot of the same host with the same paths.
     #[clap(long, group = "scan_mode", value_parser = clap::value_parser!(UseSnapshot),
            default_value_t = UseSnapshot::Latest )]
     pub parent: UseSnapshot,
 
+    /// Time between checkpoints (e.g. '15m', '1h'). Checkpoints allow resuming an interrupted
+    /// snapshot without uploading the same data again. Use '0s' to disable them. Dry runs
+    /// never save checkpoints.
+    #[clap(long, value_parser = utils::parse_duration_string,
+           default_value = global::defaults::DEFAULT_CHECKPOINT_INTERVAL)]
+    pub checkpoint_interval: chrono::Duration,
+
+    /// Number of times a file that changed while being read is read again. If it keeps
+    /// changing, the last contents read are saved and the file is flagged as inconsistent.
+    #[clap(long, default_value_t = global::defaults::DEFAULT_CHANGED_FILE_RETRIES)]
+    pub changed_file_retries: usize,
+
+    /// How to detect files that changed since the parent snapshot. 'ignore-inode' is useful
+    /// on network filesystems with unstable inodes, and 'content' reads every file again.
+    #[clap(long, default_value_t = ChangeDetection::Default)]
+    pub change_detection: ChangeDetection,
+
+    /// Compression for this snapshot: 'zstd[:LEVEL]', 'zstd-long[:LEVEL]', 'lz4' or 'none'.
+    /// Defaults to the compression of the repository.
+    #[clap(long, value_parser = clap::value_parser!(Compression))]
+    pub compression: Option<Compression>,
+
+    /// Limit the rate at which the source files are read in bytes per second (e.g. 500K, 20M)
+    #[clap(long, value_parser = utils::parse_size_string)]
+    pub limit_read: Option<u64>,
+
+    /// Run the reader, encoder and writer threads with this niceness, from -20 (highest
+    /// priority) to 19 (lowest priority). Linux only.
+    #[clap(long, value_parser = clap::value_parser!(i32).range(-20..=19))]
+    pub nice: Option<i32>,
+
+    /// Run the reader, encoder and writer threads with this I/O scheduling class. Linux only.
+    #[clap(long, value_enum)]
+    pub ionice_class: Option<IoniceClass>,
+
+    /// Maximum number of threads hashing, compressing and encrypting data, independently of the
+    /// number of files read in parallel [default: number of CPUs]
+    #[clap(long, value_parser = clap::value_parser!(usize))]
+    pub max_cpu_threads: Option<usize>,
+
     /// Number of files to process in parallel.
     #[clap(long, default_value_t = global::defaults::DEFAULT_READ_CONCURRENCY)]
     pub read_concurrency: usize,
@@ -94,25 +180,48 @@ pub struct CmdArgs {
 }
 
 pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
+    let priority = ThreadPriority {
+        nice: args.nice,
+        ionice_class: args.ionice_class,
+    };
+    if !priority.is_default() {
+        priority
+            .validate()
+            .with_context(|| "Could not change the priority of the snapshot")?;
+    }
+
     let pass = utils::get_password_from_file(&global_args.password_file)?;
     let backend = new_backend_with_prompt(global_args, args.dry_run)?;
 
     let config = RepoConfig {
         pack_size: (global_args.pack_size_mib * size::MiB as f32) as u64,
+        compression: args.compression,
     };
     let (repo, _) = Repository::try_open(pass, global_args.key.as_ref(), backend, config)?;
 
     let start = Instant::now();
 
+    // Merge the positional paths with the paths read from files
+    let mut input_paths = args.paths.clone();
+    for file in &args.files_from {
+        input_paths.extend(read_files_from(file, FilesFromFormat::Lines)?);
+    }
+    for file in &args.files_from_verbatim {
+        input_paths.extend(read_files_from(file, FilesFromFormat::Verbatim)?);
+    }
+    for file in &args.files_from_raw {
+        input_paths.extend(read_files_from(file, FilesFromFormat::Raw)?);
+    }
+
     // Get source paths from arguments or readdir root path
     let source_paths = if !args.as_root {
-        args.paths.clone()
+        input_paths
     } else {
         // Use path as root and readdir
-        if args.paths.len() != 1 {
+        if input_paths.len() != 1 {
             bail!("Only one path can be the snapshot root");
         } else {
-            let root = args.paths.last().unwrap();
+            let root = input_paths.last().unwrap();
             if !root.is_dir() {
                 bail!("The snapshot root must be a directory");
             }
@@ -154,7 +263,20 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
     };
 
     absolute_source_paths.retain(|p| utils::filter_path(p, None, cannonical_excludes.as_ref()));
-    let absolute_source_paths: Vec<PathBuf> = absolute_source_paths.into_iter().collect();
+
+    // Drop paths nested in other source paths. They are already included by their ancestor and
+    // would otherwise make the common prefix collapse into one of the source paths.
+    // The set is ordered by components, so descendants always follow their ancestors.
+    let mut deduplicated_source_paths: Vec<PathBuf> = Vec::new();
+    for path in absolute_source_paths {
+        if deduplicated_source_paths
+            .last()
+            .is_none_or(|ancestor| !path.starts_with(ancestor))
+        {
+            deduplicated_source_paths.push(path);
+        }
+    }
+    let absolute_source_paths = deduplicated_source_paths;
 
     // Extract the snapshot root path
     if absolute_source_paths.is_empty() {
@@ -162,18 +284,32 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
     };
     let snapshot_root_path = utils::calculate_lcp(&absolute_source_paths, false);
 
+    let hostname = args.host.clone().unwrap_or_else(utils::host::hostname);
+
     ui::cli::log!();
     let parent_snapshot_tuple: Option<SnapshotTuple> = match args.rescan {
         true => {
             ui::cli::log!("Full scan");
             None
         }
-        false => match find_use_snapshot(repo.clone(), &args.parent) {
+        false => match find_parent_snapshot(
+            repo.clone(),
+            &args.parent,
+            &hostname,
+            &absolute_source_paths,
+        ) {
             Ok(Some((id, snap))) => {
-                ui::cli::log!(
-                    "Using snapshot {} as parent",
-                    id.to_short_hex(SHORT_SNAPSHOT_ID_LEN).bold().yellow()
-                );
+                if snap.checkpoint {
+                    ui::cli::log!(
+                        "Resuming from checkpoint {}",
+                        id.to_short_hex(SHORT_SNAPSHOT_ID_LEN).bold().yellow()
+                    );
+                } else {
+                    ui::cli::log!(
+                        "Using snapshot {} as parent",
+                        id.to_short_hex(SHORT_SNAPSHOT_ID_LEN).bold().yellow()
+                    );
+                }
                 Some((id, snap))
             }
             Ok(None) => {
@@ -184,6 +320,18 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
         },
     };
 
+    let resumed_checkpoint: Option<(ID, Option<ID>)> = parent_snapshot_tuple
+        .as_ref()
+        .filter(|(_, snapshot)| snapshot.checkpoint)
+        .map(|(id, snapshot)| (id.clone(), snapshot.parent.clone()));
+
+    let exclude_options = ExcludeOptions {
+        exclude_caches: args.exclude_caches,
+        exclude_if_present: args.exclude_if_present.clone(),
+        one_file_system: args.one_file_system,
+        exclude_larger_than: args.exclude_larger_than,
+    };
+
     // Scan filesystem
     let spinner = ProgressBar::new_spinner();
     spinner.set_draw_target(default_bar_draw_target());
@@ -201,11 +349,12 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
     let mut num_files = 0;
     let mut num_dirs = 0;
     let mut total_bytes = 0;
-    let scan_streamer = FSNodeStreamer::from_paths(
+    let mut scan_streamer = FSNodeStreamer::from_paths(
         absolute_source_paths.clone(),
         cannonical_excludes.clone().unwrap_or_default(),
+        exclude_options.clone(),
     )?;
-    for (_path, stream_node) in scan_streamer.flatten() {
+    for (_path, stream_node) in scan_streamer.by_ref().flatten() {
         let node = stream_node.node;
 
         if node.is_dir() {
@@ -239,6 +388,7 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
         total_bytes,
         args.read_concurrency,
     ));
+    progress_reporter.skipped_items(scan_streamer.skipped_items());
 
     // Process and save new snapshot
     let archiver = Archiver::new(
@@ -247,20 +397,43 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
             absolute_source_paths,
             snapshot_root_path,
             exclude_paths: cannonical_excludes.unwrap_or_default(),
+            exclude_options,
             parent_snapshot: parent_snapshot_tuple,
             tags,
             description: args.description.clone(),
+            hostname,
+            checkpoint_interval: args
+                .checkpoint_interval
+                .to_std()
+                .ok()
+                .filter(|interval| !interval.is_zero() && !args.dry_run),
+            changed_file_retries: args.changed_file_retries,
+            read_limit: args.limit_read,
+            priority,
+            max_cpu_threads: args.max_cpu_threads,
+            change_detection: args.change_detection,
         },
         (args.read_concurrency, args.write_concurrency),
         progress_reporter.clone(),
     );
-    let new_snapshot = archiver.snapshot()?;
+    let mut new_snapshot = archiver.snapshot()?;
+
+    // A resumed checkpoint is superseded by the new snapshot, which inherits its parent.
+    if let Some((_, parent)) = &resumed_checkpoint {
+        new_snapshot.parent = parent.clone();
+    }
 
     let (snapshot_id, snapshot_raw_size, snapshot_encoded_size) = repo.save_file(
         global::FileType::Snapshot,
         serde_json::to_string(&new_snapshot)?.as_bytes(),
     )?;
 
+    if let Some((checkpoint_id, _)) = &resumed_checkpoint
+        && let Err(e) = repo.remove_snapshot(checkpoint_id)
+    {
+        ui::cli::warning!("Could not remove resumed checkpoint: {}", e);
+    }
+
     progress_reporter.written_meta_bytes(snapshot_raw_size, snapshot_encoded_size);
 
     // Finalize reporter. This removes the progress bars.
@@ -277,6 +450,88 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
     Ok(())
 }
 
+/// Finds the parent snapshot. The latest snapshot is searched among those of the same host and
+/// with the same paths. Snapshots without a recorded host are accepted, so that repositories
+/// created by older versions keep their parents. Unlike other commands, the latest checkpoint is
+/// a valid parent, so that the work done by an interrupted snapshot can be resumed.
+fn find_parent_snapshot(
+    repo: Arc<Repository>,
+    use_snapshot: &UseSnapshot,
+    hostname: &str,
+    paths: &[PathBuf],
+) -> Result<Option<SnapshotTuple>> {
+    match use_snapshot {
+        UseSnapshot::Latest => Ok(SnapshotStreamer::new(repo)?.latest_matching(|snapshot| {
+            (snapshot.hostname.is_empty() || snapshot.is_from_host(hostname))
+                && snapshot.paths == paths
+        })),
+        UseSnapshot::SnapshotId(_) => find_use_snapshot(repo, use_snapshot, None),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FilesFromFormat {
+    /// Newline separated, ignoring empty lines and comments.
+    Lines,
+    /// Newline separated, taken literally.
+    Verbatim,
+    /// NUL separated, taken literally.
+    Raw,
+}
+
+/// Reads a list of paths from a file, or from stdin if the file is '-'.
+fn read_files_from(file: &Path, format: FilesFromFormat) -> Result<Vec<PathBuf>> {
+    let contents = if file == Path::new("-") {
+        let mut buf = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut buf)
+            .with_context(|| "Could not read paths from stdin")?;
+        buf
+    } else {
+        std::fs::read(file)
+            .with_context(|| format!("Could not read paths from \'{}\'", file.display()))?
+    };
+
+    let separator = match format {
+        FilesFromFormat::Lines | FilesFromFormat::Verbatim => b'\n',
+        FilesFromFormat::Raw => b'\0',
+    };
+
+    let mut paths = Vec::new();
+    for entry in contents.split(|byte| *byte == separator) {
+        let entry = match format {
+            FilesFromFormat::Lines => {
+                let entry = entry.trim_ascii();
+                if entry.starts_with(b"#") {
+                    continue;
+                }
+                entry
+            }
+            FilesFromFormat::Verbatim | FilesFromFormat::Raw => entry,
+        };
+
+        if entry.is_empty() {
+            continue;
+        }
+
+        paths.push(bytes_to_path(entry)?);
+    }
+
+    Ok(paths)
+}
+
+#[cfg(unix)]
+fn bytes_to_path(bytes: &[u8]) -> Result<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+#[cfg(not(unix))]
+fn bytes_to_path(bytes: &[u8]) -> Result<PathBuf> {
+    let s = std::str::from_utf8(bytes).with_context(|| "Path is not valid UTF-8")?;
+    Ok(PathBuf::from(s))
+}
+
 fn show_final_report(snapshot_id: &ID, summary: &SnapshotSummary, args: &CmdArgs) {
     ui::cli::log!("{}", "Changes since parent snapshot".bold());
     ui::cli::log!();
@@ -312,6 +567,20 @@ fn show_final_report(snapshot_id: &ID, summary: &SnapshotSummary, args: &CmdArgs
     ]);
     ui::cli::log!("{}", table.render());
 
+    if summary.inconsistent_files_count > 0 {
+        ui::cli::warning!(
+            "{} changed while being read and may be inconsistent\n",
+            utils::format_count(summary.inconsistent_files_count, "file", "files")
+        );
+    }
+
+    if summary.skipped_items_count > 0 {
+        ui::cli::log!(
+            "Skipped {} by exclusion rules\n",
+            utils::format_count(summary.skipped_items_count, "item", "items")
+        );
+    }
+
     if !args.dry_run {
         ui::cli::log!(
             "New snapshot created: {}",
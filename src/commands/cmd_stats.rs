@@ -62,6 +62,7 @@ pub fn run(global_args: &GlobalArgs, args: &CmdArgs) -> Result<()> {
 
     let config = RepoConfig {
         pack_size: (global_args.pack_size_mib * size::MiB as f32) as u64,
+        compression: None,
     };
     let (repo, _) = Repository::try_open(pass, global_args.key.as_ref(), backend.clone(), config)?;
 
@@ -188,6 +189,7 @@ fn stats_snapshots(repo: Arc<Repository>) -> Result<()> {
     let mut num_referenced_blobs = 0;
     let mut total_raw_data_size: u64 = 0;
     let mut total_encoded_data_size: u64 = 0;
+    let mut total_uncompressed_data_size: u64 = 0;
     let mut visited_blobs = BTreeSet::new();
     for (_id, snapshot) in snapshot_streamer {
         total_restore_size += snapshot.size();
@@ -203,10 +205,14 @@ fn stats_snapshots(repo: Arc<Repository>) -> Result<()> {
                     if let Some(blobs) = node.blobs {
                         for blob_id in blobs {
                             if !visited_blobs.contains(&blob_id) {
-                                match index.read().get(&blob_id) {
+                                let index_rlock = index.read();
+                                match index_rlock.get(&blob_id) {
                                     Some((_pack_id, _blob_type, _offset, encoded_len, raw_len)) => {
                                         total_raw_data_size += raw_len as u64;
                                         total_encoded_data_size += encoded_len as u64;
+                                        if index_rlock.is_uncompressed(&blob_id) {
+                                            total_uncompressed_data_size += raw_len as u64;
+                                        }
                                         num_referenced_blobs += 1;
                                     }
                                     None => {
@@ -248,6 +254,10 @@ fn stats_snapshots(repo: Arc<Repository>) -> Result<()> {
         "\tTotal encoded size: {:>12}",
         utils::format_size(total_encoded_data_size, 3)
     );
+    ui::cli::log!(
+        "\tStored uncompressed: {:>12}",
+        utils::format_size(total_uncompressed_data_size, 3)
+    );
     ui::cli::log!(
         "\tCompression ratio: {:.2}x",
         total_raw_data_size as f32 / total_encoded_data_size as f32
@@ -28,16 +28,20 @@ use crate::{
         repo::Repository,
         snapshot::{Snapshot, SnapshotStreamer},
     },
+    utils,
 };
 
 pub mod cmd_amend;
 pub mod cmd_cat;
 pub mod cmd_clean;
 pub mod cmd_diff;
+pub mod cmd_dump;
+pub mod cmd_find;
 pub mod cmd_forget;
 pub mod cmd_init;
 pub mod cmd_log;
 pub mod cmd_ls;
+pub mod cmd_policy;
 pub mod cmd_restore;
 pub mod cmd_snapshot;
 pub mod cmd_stats;
@@ -68,12 +72,15 @@ pub enum Command {
     Init(cmd_init::CmdArgs),
     Snapshot(cmd_snapshot::CmdArgs),
     Restore(cmd_restore::CmdArgs),
+    Dump(cmd_dump::CmdArgs),
     Log(cmd_log::CmdArgs),
     Forget(cmd_forget::CmdArgs),
+    Policy(cmd_policy::CmdArgs),
     Clean(cmd_clean::CmdArgs),
     Amend(cmd_amend::CmdArgs),
     Ls(cmd_ls::CmdArgs),
     Diff(cmd_diff::CmdArgs),
+    Find(cmd_find::CmdArgs),
     #[cfg(unix)]
     Mount(cmd_mount::CmdArgs),
     Cat(cmd_cat::CmdArgs),
@@ -116,6 +123,14 @@ pub struct GlobalArgs {
     #[clap(long = "pack-size", value_parser = pack_size_parser, default_value_t = DEFAULT_DEFAULT_PACK_SIZE_MIB)]
     pub pack_size_mib: f32,
 
+    /// Limit the upload rate to the repository in bytes per second (e.g. 500K, 2M)
+    #[clap(long, value_parser = utils::parse_size_string)]
+    pub limit_upload: Option<u64>,
+
+    /// Limit the download rate from the repository in bytes per second (e.g. 500K, 2M)
+    #[clap(long, value_parser = utils::parse_size_string)]
+    pub limit_download: Option<u64>,
+
     /// Path to a KeyFile
     #[clap(short = 'k', long = "key-file", value_parser)]
     pub key: Option<PathBuf>,
@@ -158,14 +173,18 @@ impl std::fmt::Display for UseSnapshot {
     }
 }
 
+/// Finds the snapshot to use. If a host is given, 'latest' only considers snapshots of that host.
 pub(crate) fn find_use_snapshot(
     repo: Arc<Repository>,
     use_snapshot: &UseSnapshot,
+    host: Option<&str>,
 ) -> Result<Option<(ID, Snapshot)>> {
     match use_snapshot {
         UseSnapshot::Latest => {
             let mut snapshots = SnapshotStreamer::new(repo.clone())?;
-            Ok(snapshots.latest())
+            Ok(snapshots.latest_matching(|snapshot| {
+                !snapshot.checkpoint && host.is_none_or(|host| snapshot.is_from_host(host))
+            }))
         }
         UseSnapshot::SnapshotId(prefix) => {
             let (id, _path) = repo.find(FileType::Snapshot, prefix)?;
@@ -202,12 +221,15 @@ pub fn run(args: &Cli) -> Result<()> {
         Command::Init(cmd_args) => cmd_init::run(&args.global_args, cmd_args),
         Command::Snapshot(cmd_args) => cmd_snapshot::run(&args.global_args, cmd_args),
         Command::Restore(cmd_args) => cmd_restore::run(&args.global_args, cmd_args),
+        Command::Dump(cmd_args) => cmd_dump::run(&args.global_args, cmd_args),
         Command::Forget(cmd_args) => cmd_forget::run(&args.global_args, cmd_args),
+        Command::Policy(cmd_args) => cmd_policy::run(&args.global_args, cmd_args),
         Command::Amend(cmd_args) => cmd_amend::run(&args.global_args, cmd_args),
         Command::Clean(cmd_args) => cmd_clean::run(&args.global_args, cmd_args),
         Command::Log(cmd_args) => cmd_log::run(&args.global_args, cmd_args),
         Command::Ls(cmd_args) => cmd_ls::run(&args.global_args, cmd_args),
         Command::Diff(cmd_args) => cmd_diff::run(&args.global_args, cmd_args),
+        Command::Find(cmd_args) => cmd_find::run(&args.global_args, cmd_args),
         Command::Cat(cmd_args) => cmd_cat::run(&args.global_args, cmd_args),
 
         #[cfg(unix)]
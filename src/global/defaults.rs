@@ -21,11 +21,28 @@ use crate::utils::size;
 // -- Concurrency --
 pub(crate) const DEFAULT_READ_CONCURRENCY: usize = 4;
 pub(crate) const DEFAULT_WRITE_CONCURRENCY: usize = 5;
+pub(crate) const DEFAULT_RESTORE_CONCURRENCY: usize = 8;
+
+// -- Restoring --
+/// Maximum gap between two blobs of a pack that are read with a single request
+pub(crate) const RESTORE_MAX_READ_GAP: u64 = 256 * size::KiB;
+/// Maximum size of a single read from a pack
+pub(crate) const RESTORE_MAX_READ_SIZE: u64 = 32 * size::MiB;
+/// Name of the file in the target directory that tracks the progress of a restore
+pub(crate) const RESTORE_JOURNAL_FILENAME: &str = ".mapache-restore-journal";
 
 // -- Index --
 pub(crate) const INDEX_FLUSH_TIMEOUT: Duration = Duration::from_secs(10 * 60);
 pub(crate) const BLOBS_PER_INDEX_FILE: usize = 65535;
 
+// -- Reading --
+/// Default number of times a file that changed while being read is read again
+pub(crate) const DEFAULT_CHANGED_FILE_RETRIES: usize = 2;
+
+// -- Checkpoints --
+/// Default time between checkpoint snapshots
+pub(crate) const DEFAULT_CHECKPOINT_INTERVAL: &str = "30m";
+
 // -- Packing --
 /// Minimum pack size before flushing to the backend.
 pub const DEFAULT_DEFAULT_PACK_SIZE_MIB: f32 = 16.0;
@@ -59,6 +59,12 @@ pub fn set_global_opts_with_args(global_args: &GlobalArgs) {
     *opts_guard = Some(new_opts);
 }
 
+/// Overrides the verbosity, e.g. to keep logs out of stdout when it's used for data.
+pub fn set_verbosity(verbosity: u32) {
+    let mut opts_guard = GLOBAL_OPTS.write();
+    *opts_guard = Some(GlobalOpts { verbosity });
+}
+
 pub fn global_opts() -> RwLockReadGuard<'static, Option<GlobalOpts>> {
     GLOBAL_OPTS.read()
 }
@@ -122,7 +128,7 @@ impl ID {
             ));
         }
 
-        if hex_len % 2 != 0 {
+        if !hex_len.is_multiple_of(2) {
             bail!("Hex string has an odd length");
         }
 
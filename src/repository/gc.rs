@@ -36,6 +36,7 @@ use crate::{
     },
     repository::{repo::Repository, snapshot::SnapshotStreamer, streamers::SerializedNodeStreamer},
     ui::{self, PROGRESS_REFRESH_RATE_HZ, SPINNER_TICK_CHARS, default_bar_draw_target},
+    utils::priority::ThreadPriority,
 };
 
 /// The cleanup plan. This struct contains lists of items that are valid, unused or need some work.
@@ -170,8 +171,10 @@ impl Plan {
 
         // No need to repack and rewrite the indices if there are no obsolete packs
         if !self.obsolete_packs.is_empty() {
-            self.repo
-                .init_pack_saver(global::defaults::DEFAULT_WRITE_CONCURRENCY);
+            self.repo.init_pack_saver(
+                global::defaults::DEFAULT_WRITE_CONCURRENCY,
+                ThreadPriority::default(),
+            );
 
             added_size += self.repack()?;
             let (_, encoded) = self.repo.flush()?;
@@ -228,13 +231,12 @@ impl Plan {
         for referenced_blob_id in &self.referenced_blobs {
             if let Some((pack_id, blob_type, offset, length, raw_length)) =
                 self.repo.index().read().get(referenced_blob_id)
+                && self.obsolete_packs.contains(&pack_id)
             {
-                if self.obsolete_packs.contains(&pack_id) {
-                    repack_blob_info.insert(
-                        referenced_blob_id,
-                        (pack_id, blob_type, offset, raw_length, length),
-                    );
-                }
+                repack_blob_info.insert(
+                    referenced_blob_id,
+                    (pack_id, blob_type, offset, raw_length, length),
+                );
             }
             repack_bar.inc(1);
         }
@@ -279,7 +281,7 @@ impl Plan {
                         offset as u64,
                         length as u64,
                     )?;
-                    let (_id, (_raw_length, _encoded_length), (_raw_meta, encoded_meta)) = self
+                    let (_id, (_raw_length, _encoded_length, _), (_raw_meta, encoded_meta)) = self
                         .repo
                         .encode_and_save_blob(blob_type, data, SaveID::WithID(blob_id.clone()))?;
                     added_size.fetch_add(length as u64 + encoded_meta, Ordering::AcqRel);
@@ -41,6 +41,8 @@ struct BlobLocation {
     pub length: u32,
     /// The raw sized (uncompressed, unencrypted) of the blob
     pub raw_length: u32,
+    /// Whether the blob was stored without compression
+    pub uncompressed: bool,
 }
 
 /// Represents the location and size of a blob within a pack file.
@@ -155,6 +157,7 @@ impl Index {
                         offset: blob.offset,
                         length: blob.length,
                         raw_length: blob.raw_length,
+                        uncompressed: blob.uncompressed,
                     },
                 );
             }
@@ -203,6 +206,14 @@ impl Index {
             })
     }
 
+    /// Returns true if the blob exists and was stored without compression.
+    pub fn is_uncompressed(&self, id: &ID) -> bool {
+        self.data_ids
+            .get(id)
+            .or_else(|| self.tree_ids.get(id))
+            .is_some_and(|location| location.uncompressed)
+    }
+
     /// Adds all blob descriptors from a specific pack to the index.
     /// This method is optimized for adding multiple blobs from the same pack,
     /// as it only needs to look up the pack ID once.
@@ -222,6 +233,7 @@ impl Index {
                     offset: blob.offset,
                     length: blob.length,
                     raw_length: blob.raw_length,
+                    uncompressed: blob.uncompressed,
                 },
             );
         }
@@ -248,6 +260,7 @@ impl Index {
                 offset: location.offset,
                 length: location.length,
                 raw_length: location.raw_length,
+                uncompressed: location.uncompressed,
             });
         }
         for (blob_id, location) in &self.tree_ids {
@@ -260,6 +273,7 @@ impl Index {
                 offset: location.offset,
                 length: location.length,
                 raw_length: location.raw_length,
+                uncompressed: location.uncompressed,
             });
         }
 
@@ -329,10 +343,9 @@ impl Index {
             if let Some(pack_id) = self
                 .pack_ids
                 .get_value(blob_location.pack_array_index as usize)
+                && target_pack_id == pack_id
             {
-                if target_pack_id == pack_id {
-                    blobs_to_remove.push(blob_id.clone());
-                }
+                blobs_to_remove.push(blob_id.clone());
             }
         }
 
@@ -389,6 +402,13 @@ impl MasterIndex {
             .find_map(|idx| if !idx.is_pending { idx.get(id) } else { None })
     }
 
+    /// Returns true if the blob exists and was stored without compression.
+    pub fn is_uncompressed(&self, id: &ID) -> bool {
+        self.indices
+            .iter()
+            .any(|idx| !idx.is_pending && idx.is_uncompressed(id))
+    }
+
     /// Adds a fully constructed `Index` to the master index.
     /// This is typically used for adding loaded, finalized indices.
     pub fn add_index(&mut self, index: Index) {
@@ -521,7 +541,7 @@ impl MasterIndex {
 
                 let mut process_blobs =
                     |blob_map: &HashMap<ID, BlobLocation>, blob_type: BlobType| {
-                        for (blob_id, _) in blob_map.iter() {
+                        for (blob_id, location) in blob_map.iter() {
                             let (blob_pack_id, _, offset, length, raw_length) =
                                 idx.get(blob_id).unwrap();
                             if blob_pack_id == *pack_id {
@@ -531,6 +551,7 @@ impl MasterIndex {
                                     offset,
                                     length,
                                     raw_length,
+                                    uncompressed: location.uncompressed,
                                 };
                                 packed_blob_descriptors.push(blob_descriptor);
                             }
@@ -580,4 +601,51 @@ pub struct IndexFileBlob {
     pub offset: u32,
     pub length: u32,
     pub raw_length: u32,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub uncompressed: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that the compression of blobs is kept in index files written by older versions
+    #[test]
+    fn test_uncompressed_blobs() -> Result<()> {
+        let pack_id = ID::new_random();
+        let compressed_id = ID::new_random();
+        let uncompressed_id = ID::new_random();
+        let descriptor = |id: &ID, offset, uncompressed| PackedBlobDescriptor {
+            id: id.clone(),
+            blob_type: BlobType::Data,
+            offset,
+            length: 10,
+            raw_length: 10,
+            uncompressed,
+        };
+
+        let mut index = Index::new();
+        index.add_pack(
+            &pack_id,
+            &[
+                descriptor(&compressed_id, 0, false),
+                descriptor(&uncompressed_id, 10, true),
+            ],
+        );
+        assert!(!index.is_uncompressed(&compressed_id));
+        assert!(index.is_uncompressed(&uncompressed_id));
+        assert!(!index.is_uncompressed(&ID::new_random()));
+
+        // Index files without the flag describe compressed blobs
+        let index_file: IndexFile = serde_json::from_str(&format!(
+            r#"{{"packs":[{{"id":"{}","blobs":[{{"id":"{}","type":"Data","offset":0,"length":10,"raw_length":10}}]}}]}}"#,
+            pack_id.to_hex(),
+            uncompressed_id.to_hex()
+        ))?;
+        let index = Index::from_index_file(index_file);
+        assert!(index.contains(&uncompressed_id));
+        assert!(!index.is_uncompressed(&uncompressed_id));
+
+        Ok(())
+    }
 }
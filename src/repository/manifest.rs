@@ -17,7 +17,10 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::global::ID;
+use crate::{
+    global::ID,
+    repository::storage::{Compression, CompressionMode},
+};
 
 /// Repository manifest. This struct contains metadata about the repository itself.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,4 +28,10 @@ pub struct Manifest {
     pub version: u32,
     pub id: ID,
     pub created_time: DateTime<Utc>,
+    /// Default compression algorithm
+    #[serde(default)]
+    pub compression: Compression,
+    /// When blobs are compressed
+    #[serde(default)]
+    pub compression_mode: CompressionMode,
 }
@@ -19,6 +19,7 @@ pub mod index;
 pub mod keys;
 pub mod manifest;
 pub mod packer;
+pub mod policy;
 pub mod repo;
 pub mod snapshot;
 pub mod storage;
@@ -18,13 +18,14 @@ use std::{sync::Arc, thread::JoinHandle};
 
 use anyhow::{Context, Result, bail};
 use crossbeam_channel::Sender;
+use parking_lot::{Condvar, Mutex};
 use rand::Rng;
 
 use crate::{
     backend::StorageBackend,
     global::{BlobType, FileType, ID, SaveID, defaults::HEADER_BLOB_MULTIPLE},
     repository::{repo::Repository, storage::SecureStorage},
-    utils,
+    utils::{self, priority::ThreadPriority},
 };
 
 /// Size of a header blob entry
@@ -45,6 +46,8 @@ pub struct PackedBlobDescriptor {
     pub offset: u32,
     pub length: u32,
     pub raw_length: u32,
+    /// Whether the blob was stored without compression. Only recorded in the index.
+    pub uncompressed: bool,
 }
 
 /// A tuple representing the flushed contents of a `Packer`:
@@ -65,7 +68,7 @@ pub struct FlushedPack {
 /// This design helps minimize memory reallocations by consolidating all blob
 /// data into a single `Vec<u8>` and tracking individual blob locations.
 pub struct Packer {
-    blobs: Vec<(ID, BlobType, Vec<u8>, u64)>, // (ID, type, encoded_data, raw_length)
+    blobs: Vec<(ID, BlobType, Vec<u8>, u64, bool)>, // (ID, type, encoded_data, raw_length, uncompressed)
     size: u64,
 }
 
@@ -113,9 +116,11 @@ impl Packer {
         blob_data: Vec<u8>,
         raw_size: u64,
         encoded_size: u64,
+        uncompressed: bool,
     ) {
         self.size += encoded_size;
-        self.blobs.push((id, blob_type, blob_data, raw_size));
+        self.blobs
+            .push((id, blob_type, blob_data, raw_size, uncompressed));
     }
 
     /// Flushes the contents of the packer, returning the accumulated raw data
@@ -145,6 +150,7 @@ impl Packer {
                 offset,
                 length,
                 raw_length: blob.3 as u32,
+                uncompressed: blob.4,
             });
             data.append(&mut blob_data);
             offset += length;
@@ -172,7 +178,7 @@ impl Packer {
         // blob[id (256 bits), lenght (u32), type (u8)] + header length (u32);
         let mut pack_header = Vec::<u8>::with_capacity(HEADER_BLOB_LEN * descriptors.len());
 
-        if descriptors.len() % HEADER_BLOB_MULTIPLE > 0 {
+        if !descriptors.len().is_multiple_of(HEADER_BLOB_MULTIPLE) {
             let num_padding_blobs =
                 HEADER_BLOB_MULTIPLE - (descriptors.len() % HEADER_BLOB_MULTIPLE);
             for _ in 0..num_padding_blobs {
@@ -183,6 +189,7 @@ impl Packer {
                     offset: rand::rng().random(),
                     length: rand::rng().random(),
                     raw_length: rand::rng().random(),
+                    uncompressed: false,
                 });
             }
         }
@@ -305,6 +312,7 @@ impl Packer {
                 offset,
                 length,
                 raw_length,
+                uncompressed: false,
             };
             blob_descriptors.push(blob_descriptor);
 
@@ -320,17 +328,24 @@ pub type QueueFn = Arc<dyn Fn(Vec<u8>, ID) + Send + Sync + 'static>;
 pub struct PackSaver {
     tx: Sender<(Vec<u8>, ID)>,
     join_handle: JoinHandle<()>,
+    pending_packs: Arc<(Mutex<usize>, Condvar)>,
 }
 
 impl PackSaver {
-    pub fn new(concurrency: usize, queue_fn: QueueFn) -> Self {
+    pub fn new(concurrency: usize, priority: ThreadPriority, queue_fn: QueueFn) -> Self {
         let (tx, rx) = crossbeam_channel::bounded(concurrency);
 
         let worker_queue_fn = Arc::clone(&queue_fn);
+        let pending_packs = Arc::new((Mutex::new(0), Condvar::new()));
+        let worker_pending_packs = pending_packs.clone();
 
         let join_handle = std::thread::spawn(move || {
             let pool = rayon::ThreadPoolBuilder::new()
                 .num_threads(concurrency)
+                .start_handler(move |_| {
+                    // The priority is validated before starting, so errors can be ignored.
+                    let _ = priority.apply();
+                })
                 .build()
                 .expect("Failed to build thread pool");
 
@@ -338,12 +353,20 @@ impl PackSaver {
                 pool.scope(|s| {
                     s.spawn(|_| {
                         worker_queue_fn(data, id);
+
+                        let (count, cvar) = &*worker_pending_packs;
+                        *count.lock() -= 1;
+                        cvar.notify_all();
                     });
                 });
             }
         });
 
-        PackSaver { tx, join_handle }
+        PackSaver {
+            tx,
+            join_handle,
+            pending_packs,
+        }
     }
 
     pub fn save_pack(&self, packer_data: Vec<u8>, save_id: SaveID) -> Result<ID> {
@@ -352,13 +375,24 @@ impl PackSaver {
             SaveID::WithID(id) => id,
         };
 
-        self.tx
-            .send((packer_data, pack_id.clone()))
-            .with_context(|| "Failed to send pack data to PackSaver channel")?;
+        *self.pending_packs.0.lock() += 1;
+        if let Err(e) = self.tx.send((packer_data, pack_id.clone())) {
+            *self.pending_packs.0.lock() -= 1;
+            return Err(e).with_context(|| "Failed to send pack data to PackSaver channel");
+        }
 
         Ok(pack_id)
     }
 
+    /// Blocks until all the packs queued so far have been saved.
+    pub fn wait(&self) {
+        let (count, cvar) = &*self.pending_packs;
+        let mut count = count.lock();
+        while *count > 0 {
+            cvar.wait(&mut count);
+        }
+    }
+
     pub fn finish(self) {
         drop(self.tx);
         self.join_handle
@@ -376,13 +410,13 @@ mod tests {
         let mut packer = Packer::new();
 
         let blob1: Vec<u8> = b"mapache".to_vec(); // 7 bytes
-        packer.add_blob(ID::from_content(&blob1), BlobType::Data, blob1, 7, 7);
+        packer.add_blob(ID::from_content(&blob1), BlobType::Data, blob1, 7, 7, false);
 
         let blob2: Vec<u8> = b"backup".to_vec(); // 6 bytes
-        packer.add_blob(ID::from_content(&blob2), BlobType::Data, blob2, 6, 6);
+        packer.add_blob(ID::from_content(&blob2), BlobType::Data, blob2, 6, 6, false);
 
         let blob3: Vec<u8> = b"rust".to_vec(); // 4 bytes
-        packer.add_blob(ID::from_content(&blob3), BlobType::Data, blob3, 4, 4);
+        packer.add_blob(ID::from_content(&blob3), BlobType::Data, blob3, 4, 4, false);
 
         assert_eq!(packer.size(), (7 + 6 + 4));
         assert!(!packer.is_empty());
@@ -395,7 +429,9 @@ mod tests {
             .expect("Failed to flush packer")
             .expect("Flushed pack data must be Some");
 
-        assert_eq!(flushed_pack.data.len(), 2654);
+        // The padded header does not compress, so it is stored uncompressed:
+        // 17 bytes of data + (4 bytes of framing + 64 * 41 bytes of header) + 4 bytes of length
+        assert_eq!(flushed_pack.data.len(), 2649);
         // Due to obfuscation we cannot make assumptions about the hash
 
         let header_descriptors = Packer::parse_header(&secure_storage, &flushed_pack.data)?;
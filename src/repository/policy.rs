@@ -0,0 +1,134 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Result, bail};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+/// Retention rules and filters, given as `forget` arguments or stored as a named policy and
+/// applied by `forget --policy`. Durations are kept as written by the user (e.g. '2w') and
+/// parsed when the rules are applied.
+#[derive(Args, Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Only consider snapshots with any tag from the list: tag[,tag,...]
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<String>,
+
+    /// Only consider snapshots of this host
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+
+    /// Apply the retention rules independently to each group of snapshots: [host][,paths][,tags]
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_by: Option<String>,
+
+    /// Keep the last N snapshots. N must be greater than 0 or "all".
+    #[arg(long, value_parser = parse_retention_number)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_last: Option<usize>,
+
+    /// Keep snapshots within a specified duration (e.g., '1d', '2w', '3m', '4y', '5h', '6s').
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_within: Option<String>,
+
+    /// Keep the latest hourly snapshot within a duration (e.g. '2d').
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_within_hourly: Option<String>,
+
+    /// Keep the latest daily snapshot within a duration (e.g. '2w').
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_within_daily: Option<String>,
+
+    /// Keep the latest weekly snapshot within a duration (e.g. '3m').
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_within_weekly: Option<String>,
+
+    /// Keep the latest monthly snapshot within a duration (e.g. '1y').
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_within_monthly: Option<String>,
+
+    /// Keep the latest yearly snapshot within a duration (e.g. '10y').
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_within_yearly: Option<String>,
+
+    /// Keep N yearly snapshots. N must be greater than 0 or "all".
+    #[arg(long, value_parser = parse_retention_number)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_yearly: Option<usize>,
+
+    /// Keep N monthly snapshots. N must be greater than 0 or "all".
+    #[arg(long, value_parser = parse_retention_number)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_monthly: Option<usize>,
+
+    /// Keep N weekly snapshots. N must be greater than 0 or "all".
+    #[arg(long, value_parser = parse_retention_number)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_weekly: Option<usize>,
+
+    /// Keep N daily snapshots. N must be greater than 0 or "all".
+    #[arg(long, value_parser = parse_retention_number)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_daily: Option<usize>,
+
+    /// Keep N hourly snapshots. N must be greater than 0 or "all".
+    #[arg(long, value_parser = parse_retention_number)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_hourly: Option<usize>,
+
+    /// Keep all snapshots with any of these tags: tag[,tag,...]
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_tags: Option<String>,
+}
+
+/// All retention policies of a repository, by name.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicies {
+    pub policies: BTreeMap<String, RetentionPolicy>,
+}
+
+/// Parses the N of a `--keep-*` rule. "all" keeps every snapshot.
+pub fn parse_retention_number(s: &str) -> Result<usize> {
+    if s == "all" {
+        Ok(usize::MAX)
+    } else {
+        let n = s.parse::<isize>();
+        match n {
+            Ok(num) => {
+                if num > 0 {
+                    Ok(num as usize)
+                } else {
+                    bail!("N must be greater than 0")
+                }
+            }
+            Err(_) => bail!("{} is not a number", s),
+        }
+    }
+}
@@ -22,8 +22,7 @@ use std::{
 
 use anyhow::{Context, Result, bail};
 use chrono::Utc;
-use parking_lot::RwLock;
-use zstd::DEFAULT_COMPRESSION_LEVEL;
+use parking_lot::{Mutex, RwLock};
 
 use crate::{
     backend::StorageBackend,
@@ -34,24 +33,29 @@ use crate::{
     repository::{
         keys::{generate_key_file, generate_new_master_key, retrieve_master_key},
         packer::{PackSaver, Packer},
-        storage::SecureStorage,
+        storage::{Compression, CompressionMode, SecureStorage},
     },
     ui::{self, cli},
+    utils::priority::ThreadPriority,
 };
 
 use super::{
     index::{Index, IndexFile, MasterIndex},
     keys,
     manifest::Manifest,
+    policy::RetentionPolicies,
     snapshot::Snapshot,
 };
 
-pub const THIS_REPOSITORY_VERSION: u32 = 1;
+/// Version 2 introduced the tagged blob framing (uncompressed and LZ4 blobs), which older
+/// binaries cannot decode. Version 1 repositories are upgraded before the first write.
+pub const THIS_REPOSITORY_VERSION: u32 = 2;
 
 const OBJECTS_DIR: &str = "objects";
 const SNAPSHOTS_DIR: &str = "snapshots";
 const INDEX_DIR: &str = "index";
 pub(crate) const MANIFEST_PATH: &str = "manifest";
+const POLICIES_PATH: &str = "policies";
 pub(crate) const KEYS_DIR: &str = "keys";
 
 const OBJECTS_DIR_FANOUT: usize = 2;
@@ -59,12 +63,15 @@ const OBJECTS_DIR_FANOUT: usize = 2;
 #[derive(Debug)]
 pub struct RepoConfig {
     pub pack_size: u64,
+    /// Overrides the compression algorithm of the repository
+    pub compression: Option<Compression>,
 }
 
 impl Default for RepoConfig {
     fn default() -> Self {
         Self {
             pack_size: DEFAULT_PACK_SIZE,
+            compression: None,
         }
     }
 }
@@ -88,6 +95,10 @@ pub struct Repository {
     pack_saver: Arc<RwLock<Option<PackSaver>>>,
 
     index: Arc<RwLock<MasterIndex>>,
+
+    // Encoded manifest of the current version, saved before the first write to a repository
+    // of an older version.
+    manifest_upgrade: Mutex<Option<Vec<u8>>>,
 }
 
 impl Repository {
@@ -96,6 +107,8 @@ impl Repository {
         password: Option<String>,
         keyfile_path: Option<&PathBuf>,
         backend: Arc<dyn StorageBackend>,
+        compression: Compression,
+        compression_mode: CompressionMode,
     ) -> Result<()> {
         let timestamp = Utc::now();
 
@@ -124,15 +137,10 @@ impl Repository {
         let master_key = generate_new_master_key();
         let keyfile = generate_key_file(&pass, master_key.clone())
             .with_context(|| "Could not generate key")?;
-        let secure_storage = Arc::new(
-            SecureStorage::build()
-                .with_compression(DEFAULT_COMPRESSION_LEVEL)
-                .with_key(master_key),
-        );
 
         let keyfile_json = serde_json::to_string_pretty(&keyfile)?;
         let keyfile_json =
-            SecureStorage::compress(keyfile_json.as_bytes(), DEFAULT_COMPRESSION_LEVEL)?;
+            SecureStorage::compress(keyfile_json.as_bytes(), zstd::DEFAULT_COMPRESSION_LEVEL)?;
         let keyfile_id = ID::from_content(&keyfile_json);
         match keyfile_path {
             Some(p) => {
@@ -157,12 +165,12 @@ impl Repository {
             version: THIS_REPOSITORY_VERSION,
             id: repo_id.clone(),
             created_time: timestamp,
+            compression,
+            compression_mode,
         };
 
-        let manifest_path = Path::new(MANIFEST_PATH);
-        let manifest = serde_json::to_string_pretty(&manifest)?;
-        let manifest = secure_storage.encode(manifest.as_bytes())?;
-        backend.write(manifest_path, &manifest)?;
+        let manifest = encode_manifest(&manifest, &manifest_storage(master_key))?;
+        backend.write(Path::new(MANIFEST_PATH), &manifest)?;
 
         backend.create_dir(&objects_path)?;
         let num_folders: usize = 1 << (4 * OBJECTS_DIR_FANOUT);
@@ -221,30 +229,43 @@ impl Repository {
             }
         };
 
-        let secure_storage = Arc::new(
-            SecureStorage::build()
-                .with_compression(DEFAULT_COMPRESSION_LEVEL)
-                .with_key(master_key),
-        );
-
-        let manifest_path = Path::new(MANIFEST_PATH);
+        let manifest_storage = manifest_storage(master_key.clone());
 
         let manifest = backend
-            .read(manifest_path)
+            .read(Path::new(MANIFEST_PATH))
             .with_context(|| "Could not load manifest file")?;
-        let manifest = secure_storage
+        let manifest = manifest_storage
             .decode(&manifest)
             .with_context(|| "Could not decode the manifest file")?;
         let manifest: Manifest = serde_json::from_slice(&manifest)?;
 
-        let version = manifest.version;
+        // Version 1 repositories can be read as they are. They are only upgraded before the
+        // first write, which may use the framing older binaries cannot decode.
+        let manifest_upgrade = match manifest.version {
+            THIS_REPOSITORY_VERSION => None,
+            1 => {
+                let upgraded = Manifest {
+                    version: THIS_REPOSITORY_VERSION,
+                    ..manifest.clone()
+                };
+                Some(encode_manifest(&upgraded, &manifest_storage)?)
+            }
+            version => bail!(
+                "Unsupported repository version \'{}\'. This version of mapache supports version {}.",
+                version,
+                THIS_REPOSITORY_VERSION
+            ),
+        };
 
-        if version == 1 {
-            let repo = Repository::open(backend, secure_storage.clone(), config)?;
-            Ok((repo, secure_storage))
-        } else {
-            bail!("Invalid repository version \'{}\'", version);
-        }
+        let secure_storage = Arc::new(
+            SecureStorage::build()
+                .with_key(master_key)
+                .with_compression(config.compression.unwrap_or(manifest.compression))
+                .with_compression_mode(manifest.compression_mode),
+        );
+
+        let repo = Repository::open(backend, secure_storage.clone(), config, manifest_upgrade)?;
+        Ok((repo, secure_storage))
     }
 
     /// Open an existing repository from a directory
@@ -252,6 +273,7 @@ impl Repository {
         backend: Arc<dyn StorageBackend>,
         secure_storage: Arc<SecureStorage>,
         config: RepoConfig,
+        manifest_upgrade: Option<Vec<u8>>,
     ) -> Result<Arc<Self>> {
         let objects_path = PathBuf::from(OBJECTS_DIR);
         let snapshot_path = PathBuf::from(SNAPSHOTS_DIR);
@@ -274,6 +296,7 @@ impl Repository {
             tree_packer,
             pack_saver: Arc::new(RwLock::new(None)),
             index,
+            manifest_upgrade: Mutex::new(manifest_upgrade),
         };
 
         repo.load_master_index()?;
@@ -282,14 +305,16 @@ impl Repository {
     }
 
     /// Encodes and saves a blob in the repository. This blob can be packed with other blobs in an pack file.
-    /// Returns a tuple (`ID`, (raw_data_size, encoded_data_size), (raw_meta_size, encoded_meta_size))
+    /// Returns a tuple (`ID`, (raw_data_size, encoded_data_size, uncompressed_data_size),
+    /// (raw_meta_size, encoded_meta_size)). `uncompressed_data_size` is the raw size of the blob
+    /// if it was stored without compression, and 0 otherwise.
     #[allow(clippy::type_complexity)]
     pub fn encode_and_save_blob(
         &self,
         blob_type: BlobType,
         data: Vec<u8>,
         save_id: SaveID,
-    ) -> Result<(ID, (u64, u64), (u64, u64))> {
+    ) -> Result<(ID, (u64, u64, u64), (u64, u64))> {
         let packer = match blob_type {
             BlobType::Data => &self.data_packer,
             BlobType::Tree => &self.tree_packer,
@@ -310,16 +335,24 @@ impl Repository {
 
         // If the blob was already pending, return early, as we are finished here.
         if blob_exists {
-            return Ok((id, (0, 0), (0, 0)));
+            return Ok((id, (0, 0, 0), (0, 0)));
         }
 
+        self.upgrade_version()?;
+
         let raw_length = data.len() as u64;
-        let data = self.secure_storage.encode(&data)?;
+        let (data, compressed) = self.secure_storage.encode_with_info(&data)?;
         let encoded_length = data.len() as u64;
-
-        packer
-            .write()
-            .add_blob(id.clone(), blob_type, data, raw_length, encoded_length);
+        let uncompressed_length = if compressed { 0 } else { raw_length };
+
+        packer.write().add_blob(
+            id.clone(),
+            blob_type,
+            data,
+            raw_length,
+            encoded_length,
+            !compressed,
+        );
 
         // Flush if the packer is considered full
         let packer_meta_size = if packer.read().size() > self.max_packer_size {
@@ -328,7 +361,11 @@ impl Repository {
             (0, 0)
         };
 
-        Ok((id, (raw_length, encoded_length), packer_meta_size))
+        Ok((
+            id,
+            (raw_length, encoded_length, uncompressed_length),
+            packer_meta_size,
+        ))
     }
 
     /// Loads a blob from the repository.
@@ -347,6 +384,8 @@ impl Repository {
         assert_ne!(file_type, FileType::Key);
         assert_ne!(file_type, FileType::Manifest);
 
+        self.upgrade_version()?;
+
         let raw_size = data.len() as u64;
         let data = self.secure_storage.encode(data)?;
         let encoded_size = data.len() as u64;
@@ -416,10 +455,10 @@ impl Repository {
             .with_context(|| "Could not read snapshots")?;
 
         for path in paths {
-            if self.backend.is_file(&path) {
-                if let Some(file_name) = path.file_name().and_then(|s| s.to_str()) {
-                    ids.push(ID::from_hex(file_name)?);
-                }
+            if self.backend.is_file(&path)
+                && let Some(file_name) = path.file_name().and_then(|s| s.to_str())
+            {
+                ids.push(ID::from_hex(file_name)?);
             }
         }
 
@@ -462,6 +501,33 @@ impl Repository {
         Ok(manifest)
     }
 
+    /// Loads the retention policies. A repository without policies returns an empty set.
+    pub fn load_policies(&self) -> Result<RetentionPolicies> {
+        let policies_path = Path::new(POLICIES_PATH);
+        if !self.backend.exists(policies_path) {
+            return Ok(RetentionPolicies::default());
+        }
+
+        let policies = self
+            .backend
+            .read(policies_path)
+            .with_context(|| "Could not read the retention policies")?;
+        let policies = self.secure_storage.decode(&policies)?;
+        let policies = serde_json::from_slice(&policies)?;
+        Ok(policies)
+    }
+
+    /// Saves the retention policies, replacing the existing ones.
+    pub fn save_policies(&self, policies: &RetentionPolicies) -> Result<()> {
+        self.upgrade_version()?;
+
+        let policies = serde_json::to_string_pretty(policies)?;
+        let policies = self.secure_storage.encode(policies.as_bytes())?;
+        self.save_with_rename(Path::new(POLICIES_PATH), &policies)
+            .with_context(|| "Could not save the retention policies")?;
+        Ok(())
+    }
+
     /// Loads a KeyFile.
     pub fn load_key(&self, id: &ID) -> Result<keys::KeyFile> {
         let key_path = self.keys_path.join(id.to_hex());
@@ -520,12 +586,15 @@ impl Repository {
         Ok((id, filepath))
     }
 
-    pub fn init_pack_saver(&self, concurrency: usize) {
+    /// Starts the PackSaver, which writes packs to the backend with `concurrency` threads running
+    /// at the given priority.
+    pub fn init_pack_saver(&self, concurrency: usize, priority: ThreadPriority) {
         let backend = self.backend.clone();
         let objects_path = self.objects_path.clone();
 
         let pack_saver = PackSaver::new(
             concurrency,
+            priority,
             Arc::new(move |data, id| {
                 let path = Self::get_object_path(&objects_path, &id);
                 if let Err(e) = backend.write(&path, &data) {
@@ -536,6 +605,13 @@ impl Repository {
         self.pack_saver.write().replace(pack_saver);
     }
 
+    /// Blocks until all the packs queued in the PackSaver have been written to the backend.
+    pub fn wait_pack_saver(&self) {
+        if let Some(pack_saver) = self.pack_saver.read().as_ref() {
+            pack_saver.wait();
+        }
+    }
+
     pub fn finalize_pack_saver(&self) {
         if let Some(pack_saver) = self.pack_saver.write().take() {
             pack_saver.finish();
@@ -575,6 +651,11 @@ impl Repository {
         self.secure_storage.decode(&data)
     }
 
+    /// Decodes a blob read from a pack.
+    pub fn decode_blob(&self, data: &[u8]) -> Result<Vec<u8>> {
+        self.secure_storage.decode(data)
+    }
+
     /// Lists all packs in the repository.
     pub fn list_objects(&self) -> Result<BTreeSet<ID>> {
         let mut list = BTreeSet::new();
@@ -641,6 +722,20 @@ impl Repository {
         }
     }
 
+    /// Upgrades the manifest of an older repository to the current version. The repository
+    /// is no longer readable by older binaries afterwards.
+    fn upgrade_version(&self) -> Result<()> {
+        let mut manifest_upgrade = self.manifest_upgrade.lock();
+        if let Some(manifest) = manifest_upgrade.as_ref() {
+            self.backend
+                .write(Path::new(MANIFEST_PATH), manifest)
+                .with_context(|| "Could not upgrade the repository manifest")?;
+            *manifest_upgrade = None;
+            ui::cli::log_stderr!("Upgraded repository to version {}", THIS_REPOSITORY_VERSION);
+        }
+        Ok(())
+    }
+
     fn save_with_rename(&self, path: &Path, data: &[u8]) -> Result<usize> {
         let tmp_path = path.with_extension("tmp");
         self.backend.write(&tmp_path, data)?;
@@ -718,6 +813,21 @@ impl Drop for Repository {
     }
 }
 
+/// Storage used for the manifest. The manifest is always zstd compressed, the only framing
+/// every repository version can decode, so older binaries can read it and report an
+/// unsupported version instead of failing to decode it.
+fn manifest_storage(master_key: Vec<u8>) -> SecureStorage {
+    SecureStorage::build()
+        .with_key(master_key)
+        .with_compression(Compression::default())
+        .with_compression_mode(CompressionMode::Always)
+}
+
+fn encode_manifest(manifest: &Manifest, storage: &SecureStorage) -> Result<Vec<u8>> {
+    let manifest = serde_json::to_string_pretty(manifest)?;
+    storage.encode(manifest.as_bytes())
+}
+
 #[cfg(test)]
 mod tests {
     use base64::{Engine, engine::general_purpose};
@@ -736,7 +846,13 @@ mod tests {
         let password = Some(String::from("mapachito"));
         let backend = Arc::new(LocalFS::new(temp_repo_path.to_owned()));
 
-        Repository::init(password.clone(), None, backend.to_owned())?;
+        Repository::init(
+            password.clone(),
+            None,
+            backend.to_owned(),
+            Compression::default(),
+            CompressionMode::Auto,
+        )?;
         Repository::try_open(password, None, backend, RepoConfig::default())?;
 
         Ok(())
@@ -756,12 +872,81 @@ mod tests {
         let password = utils::get_password_from_file(&Some(password_file_path))?;
         let backend = Arc::new(LocalFS::new(temp_repo_path.to_owned()));
 
-        Repository::init(password.clone(), None, backend.to_owned())?;
+        Repository::init(
+            password.clone(),
+            None,
+            backend.to_owned(),
+            Compression::default(),
+            CompressionMode::Auto,
+        )?;
         Repository::try_open(password, None, backend, RepoConfig::default())?;
 
         Ok(())
     }
 
+    /// Test that version 1 repositories are upgraded and unknown versions are rejected
+    #[test]
+    fn test_open_repository_versions() -> Result<()> {
+        let temp_repo_dir = tempdir()?;
+        let temp_repo_path = temp_repo_dir.path().join("repo");
+
+        let password = Some(String::from("mapachito"));
+        let backend = Arc::new(LocalFS::new(temp_repo_path.to_owned()));
+
+        Repository::init(
+            password.clone(),
+            None,
+            backend.to_owned(),
+            Compression::Lz4,
+            CompressionMode::Auto,
+        )?;
+
+        let (repo, secure_storage) = Repository::try_open(
+            password.clone(),
+            None,
+            backend.clone(),
+            RepoConfig::default(),
+        )?;
+        let mut manifest = repo.load_manifest()?;
+        assert_eq!(manifest.version, THIS_REPOSITORY_VERSION);
+
+        // The manifest stays readable by binaries that only know the zstd framing
+        let encoded = backend.read(Path::new(MANIFEST_PATH))?;
+        let decrypted = secure_storage.decrypt(&encoded)?;
+        SecureStorage::decompress(&decrypted)?;
+        drop(repo);
+
+        // Version 1 repositories are opened as they are and upgraded on the first write
+        manifest.version = 1;
+        backend.write(
+            Path::new(MANIFEST_PATH),
+            &encode_manifest(&manifest, &secure_storage)?,
+        )?;
+        let (repo, _) = Repository::try_open(
+            password.clone(),
+            None,
+            backend.clone(),
+            RepoConfig::default(),
+        )?;
+        repo.load_policies()?;
+        assert_eq!(repo.load_manifest()?.version, 1);
+        repo.save_policies(&RetentionPolicies::default())?;
+        assert_eq!(repo.load_manifest()?.version, THIS_REPOSITORY_VERSION);
+        drop(repo);
+
+        manifest.version = THIS_REPOSITORY_VERSION + 1;
+        backend.write(
+            Path::new(MANIFEST_PATH),
+            &encode_manifest(&manifest, &secure_storage)?,
+        )?;
+        let err = Repository::try_open(password, None, backend, RepoConfig::default())
+            .err()
+            .expect("Opening an unknown repository version should fail");
+        assert!(err.to_string().contains("Unsupported repository version"));
+
+        Ok(())
+    }
+
     /// Test generation of master keys
     #[test]
     fn test_generate_key_file() -> Result<()> {
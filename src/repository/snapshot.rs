@@ -57,6 +57,31 @@ pub struct Snapshot {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
 
+    /// Name of the host where the snapshot was created
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hostname: String,
+
+    /// Name of the user that created the snapshot
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub username: String,
+
+    /// User ID of the process that created the snapshot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+
+    /// Group ID of the process that created the snapshot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+
+    /// Name and version of the program that created the snapshot
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub program_version: String,
+
+    /// Checkpoints are saved periodically while a snapshot is being created, so that an
+    /// interrupted snapshot can be resumed. They may not contain all the source items.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub checkpoint: bool,
+
     /// Summary of the Snapshot.
     pub summary: SnapshotSummary,
 }
@@ -79,6 +104,12 @@ impl Snapshot {
         }
         false
     }
+
+    /// Returns true if the snapshot was created on the given host. Hosts are compared
+    /// case-insensitively.
+    pub fn is_from_host(&self, host: &str) -> bool {
+        self.hostname.eq_ignore_ascii_case(host)
+    }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -132,11 +163,14 @@ impl DiffCounts {
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct SnapshotSummary {
-    pub processed_items_count: u64, // Number of files processed
-    pub processed_bytes: u64,       // Bytes processed (only data)
+    pub processed_items_count: u64,    // Number of files processed
+    pub processed_bytes: u64,          // Bytes processed (only data)
+    pub skipped_items_count: u64,      // Number of items skipped by the exclusion rules
+    pub inconsistent_files_count: u64, // Number of files that changed while being read
 
     pub raw_bytes: u64,           // Bytes 'written' before encoding
     pub encoded_bytes: u64,       // Bytes written after encoding
+    pub uncompressed_bytes: u64,  // Bytes written without compression (raw size)
     pub meta_raw_bytes: u64,      // Metadata bytes 'written' before encoding
     pub meta_encoded_bytes: u64,  // Metadata bytes written after encoding
     pub total_raw_bytes: u64,     // Total raw bytes
@@ -176,19 +210,29 @@ impl SnapshotStreamer {
         self.snapshot_ids.len()
     }
 
-    /// Consumes the iterator and returns the Snapshot with the latest ID.
+    /// Consumes the iterator and returns the latest Snapshot. Checkpoints are ignored.
     pub fn latest(&mut self) -> Option<(ID, Snapshot)> {
-        let (mut latest_id, mut latest_sn) = self.next()?;
+        self.latest_matching(|snapshot| !snapshot.checkpoint)
+    }
 
-        for (mut id, mut snapshot) in self.by_ref() {
-            if snapshot.timestamp > latest_sn.timestamp {
-                std::mem::swap(&mut id, &mut latest_id);
-                std::mem::swap(&mut snapshot, &mut latest_sn);
-            }
-        }
+    /// Consumes the iterator and returns the latest Snapshot that satisfies the filter.
+    pub fn latest_matching<F>(&mut self, filter: F) -> Option<(ID, Snapshot)>
+    where
+        F: Fn(&Snapshot) -> bool,
+    {
+        let latest = self
+            .by_ref()
+            .filter(|(_id, snapshot)| filter(snapshot))
+            .reduce(|latest, candidate| {
+                if candidate.1.timestamp > latest.1.timestamp {
+                    candidate
+                } else {
+                    latest
+                }
+            });
 
         self.snapshot_ids.clear();
-        Some((latest_id, latest_sn))
+        latest
     }
 }
 
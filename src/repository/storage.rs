@@ -15,13 +15,20 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use aes_gcm_siv::{Aes256GcmSiv, Key as AesKey, KeyInit, Nonce, aead::Aead};
-use anyhow::{Result, bail};
+use anyhow::{Context, Error, Result, anyhow, bail};
 use argon2::Argon2;
+use clap::ValueEnum;
 use rand::TryRngCore;
 use rand::rngs::OsRng;
 use secrecy::zeroize::Zeroize;
 use secrecy::{ExposeSecret, SecretBox};
-use std::io::{Read, Write};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    io::{Read, Write},
+    str::FromStr,
+};
+use zstd::DEFAULT_COMPRESSION_LEVEL;
 use zstd::stream::read::Decoder as ZstdDecoder;
 use zstd::stream::write::Encoder as ZstdEncoder;
 
@@ -29,11 +36,136 @@ use crate::global;
 
 const AES_GCM_NONCE_LEN: usize = 12;
 const ZSTD_WINDOW_LOG: u32 = global::defaults::AVG_CHUNK_SIZE.ilog2();
+/// Window used in long distance mode, large enough to match across a whole chunk
+const ZSTD_LONG_WINDOW_LOG: u32 = global::defaults::MAX_CHUNK_SIZE.ilog2();
+
+/// Prefix of the data stored without compression. Data compressed with zstd is stored as a plain
+/// zstd frame, which always starts with the zstd magic number, so all formats can be told apart.
+const UNCOMPRESSED_MAGIC: [u8; 4] = *b"MPUC";
+/// Prefix of the data compressed with lz4
+const LZ4_MAGIC: [u8; 4] = *b"MPL4";
+
+/// Number of bytes sampled to estimate whether some data is compressible
+const ENTROPY_SAMPLE_SIZE: usize = 64 * 1024;
+/// Number of windows the sample is taken from
+const ENTROPY_SAMPLE_WINDOWS: usize = 4;
+/// Data with a higher entropy (in bits per byte) is considered incompressible
+const INCOMPRESSIBLE_ENTROPY: f64 = 7.8;
+
+/// When to compress the data before encrypting it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionMode {
+    /// Compress the data unless it looks incompressible
+    #[default]
+    Auto,
+    /// Always compress the data
+    Always,
+    /// Never compress the data
+    Never,
+}
+
+/// Compression algorithm, parsed from `<algorithm>[:<level>]`, e.g. 'zstd:19', 'zstd-long',
+/// 'lz4' or 'none'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Compression {
+    /// zstd with a compression level, optionally in long distance matching mode
+    Zstd { level: i32, long: bool },
+    /// lz4, faster but with a lower compression ratio
+    Lz4,
+    /// No compression
+    None,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Zstd {
+            level: DEFAULT_COMPRESSION_LEVEL,
+            long: false,
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (algorithm, level) = match s.split_once(':') {
+            Some((algorithm, level)) => (algorithm, Some(level)),
+            None => (s, None),
+        };
+
+        let algorithm_name = algorithm.to_lowercase();
+        let compression = match algorithm_name.as_str() {
+            "zstd" | "zstd-long" => {
+                let level = match level {
+                    Some(level) => level
+                        .parse::<i32>()
+                        .with_context(|| format!("Invalid zstd compression level \'{level}\'"))?,
+                    None => DEFAULT_COMPRESSION_LEVEL,
+                };
+                let range = zstd::compression_level_range();
+                if !range.contains(&level) {
+                    bail!(
+                        "zstd compression level must be between {} and {}",
+                        range.start(),
+                        range.end()
+                    );
+                }
+
+                Compression::Zstd {
+                    level,
+                    long: algorithm_name == "zstd-long",
+                }
+            }
+            "lz4" => Compression::Lz4,
+            "none" => Compression::None,
+            _ => {
+                return Err(anyhow!(
+                    "Invalid compression \'{s}\': must be 'zstd[:LEVEL]', 'zstd-long[:LEVEL]', 'lz4' or 'none'"
+                ));
+            }
+        };
+
+        if level.is_some() && !matches!(compression, Compression::Zstd { .. }) {
+            bail!("Compression \'{algorithm}\' does not accept a level");
+        }
+
+        Ok(compression)
+    }
+}
+
+impl TryFrom<String> for Compression {
+    type Error = Error;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Compression> for String {
+    fn from(compression: Compression) -> Self {
+        compression.to_string()
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::Zstd { level, long: false } => write!(f, "zstd:{level}"),
+            Compression::Zstd { level, long: true } => write!(f, "zstd-long:{level}"),
+            Compression::Lz4 => write!(f, "lz4"),
+            Compression::None => write!(f, "none"),
+        }
+    }
+}
 
 /// Secure storage is an abstraction for file IO that handles compression and encryption.
 pub struct SecureStorage {
     key: Option<SecretBox<Vec<u8>>>,
-    compression_level: i32,
+    compression: Compression,
+    compression_mode: CompressionMode,
 }
 
 impl SecureStorage {
@@ -41,7 +173,8 @@ impl SecureStorage {
     pub fn build() -> Self {
         Self {
             key: Default::default(),
-            compression_level: Default::default(),
+            compression: Default::default(),
+            compression_mode: Default::default(),
         }
     }
 
@@ -52,24 +185,116 @@ impl SecureStorage {
         self
     }
 
-    /// Builder method to set a compression level
-    pub fn with_compression(mut self, level: i32) -> Self {
-        self.compression_level = level;
+    /// Builder method to set the compression algorithm
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Builder method to set when the data is compressed
+    pub fn with_compression_mode(mut self, mode: CompressionMode) -> Self {
+        self.compression_mode = mode;
         self
     }
 
     pub fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
-        let mut processed_data = Self::compress(data, self.compression_level)?;
+        let (encoded, _compressed) = self.encode_with_info(data)?;
+        Ok(encoded)
+    }
+
+    /// Encodes data like `encode`, also returning whether the data was compressed.
+    pub fn encode_with_info(&self, data: &[u8]) -> Result<(Vec<u8>, bool)> {
+        let compress = self.compression != Compression::None
+            && match self.compression_mode {
+                CompressionMode::Always => true,
+                CompressionMode::Never => false,
+                CompressionMode::Auto => Self::is_compressible(data),
+            };
+
+        let mut processed_data = Vec::new();
+        let mut compressed = false;
+        if compress {
+            processed_data = self.compress_with_algorithm(data)?;
+            compressed = self.compression_mode == CompressionMode::Always
+                || processed_data.len() < data.len();
+        }
+        if !compressed {
+            processed_data = Vec::with_capacity(UNCOMPRESSED_MAGIC.len() + data.len());
+            processed_data.extend_from_slice(&UNCOMPRESSED_MAGIC);
+            processed_data.extend_from_slice(data);
+        }
+
         processed_data = self.encrypt(&processed_data)?;
-        Ok(processed_data)
+        Ok((processed_data, compressed))
     }
 
     pub fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
         let mut processed_data = self.decrypt(data)?;
-        processed_data = Self::decompress(&processed_data)?;
+        processed_data =
+            if let Some(uncompressed) = processed_data.strip_prefix(&UNCOMPRESSED_MAGIC) {
+                uncompressed.to_vec()
+            } else if let Some(lz4_data) = processed_data.strip_prefix(&LZ4_MAGIC) {
+                lz4_flex::decompress_size_prepended(lz4_data)?
+            } else {
+                Self::decompress(&processed_data)?
+            };
         Ok(processed_data)
     }
 
+    /// Compresses data with the configured algorithm, prefixed with the tag `decode` uses to
+    /// find the algorithm.
+    fn compress_with_algorithm(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.compression {
+            Compression::Zstd { level, long: false } => Self::compress(data, level),
+            Compression::Zstd { level, long: true } => Self::compress_long(data, level),
+            Compression::Lz4 => {
+                let mut compressed = LZ4_MAGIC.to_vec();
+                compressed.extend_from_slice(&lz4_flex::compress_prepend_size(data));
+                Ok(compressed)
+            }
+            Compression::None => unreachable!("Data is never compressed with Compression::None"),
+        }
+    }
+
+    /// Estimates whether some data is worth compressing. The Shannon entropy of the bytes is
+    /// computed over a sample taken from a few windows spread across the data. Compressed or
+    /// encrypted data (archives, media, ...) has an entropy close to 8 bits per byte.
+    pub fn is_compressible(data: &[u8]) -> bool {
+        if data.len() <= ENTROPY_SAMPLE_SIZE {
+            return Self::entropy(data.iter()) < INCOMPRESSIBLE_ENTROPY;
+        }
+
+        let window_size = ENTROPY_SAMPLE_SIZE / ENTROPY_SAMPLE_WINDOWS;
+        let stride = (data.len() - window_size) / (ENTROPY_SAMPLE_WINDOWS - 1);
+        let sample =
+            (0..ENTROPY_SAMPLE_WINDOWS).flat_map(|i| &data[i * stride..i * stride + window_size]);
+
+        Self::entropy(sample) < INCOMPRESSIBLE_ENTROPY
+    }
+
+    /// Shannon entropy of a sequence of bytes, in bits per byte
+    fn entropy<'a>(bytes: impl Iterator<Item = &'a u8>) -> f64 {
+        let mut histogram = [0u64; 256];
+        let mut count = 0;
+        for &byte in bytes {
+            histogram[byte as usize] += 1;
+            count += 1;
+        }
+
+        if count == 0 {
+            return 0.0;
+        }
+
+        histogram
+            .iter()
+            .filter(|&&n| n > 0)
+            .map(|&n| {
+                let p = n as f64 / count as f64;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
     /// Compress a stream of bytes
     pub fn compress(data: &[u8], compression_level: i32) -> Result<Vec<u8>> {
         let mut compressed = Vec::with_capacity(data.len());
@@ -83,10 +308,26 @@ impl SecureStorage {
         Ok(compressed)
     }
 
+    /// Compress a stream of bytes with long distance matching and a window as large as a chunk
+    pub fn compress_long(data: &[u8], compression_level: i32) -> Result<Vec<u8>> {
+        let mut compressed = Vec::with_capacity(data.len());
+        let mut encoder = ZstdEncoder::new(&mut compressed, compression_level)?;
+
+        encoder.set_parameter(zstd::zstd_safe::CParameter::WindowLog(ZSTD_LONG_WINDOW_LOG))?;
+        encoder.set_parameter(zstd::zstd_safe::CParameter::EnableLongDistanceMatching(
+            true,
+        ))?;
+        encoder.set_parameter(zstd::zstd_safe::CParameter::ChecksumFlag(false))?;
+
+        encoder.write_all(data)?;
+        encoder.finish()?;
+        Ok(compressed)
+    }
+
     /// Decompress a stream of bytes
     pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
         let mut decoder = ZstdDecoder::new(data)?;
-        decoder.window_log_max(ZSTD_WINDOW_LOG)?;
+        decoder.window_log_max(ZSTD_LONG_WINDOW_LOG)?;
 
         let mut decompressed = Vec::with_capacity(data.len());
         decoder.read_to_end(&mut decompressed)?;
@@ -181,8 +422,6 @@ impl Drop for SecureStorage {
 
 #[cfg(test)]
 mod tests {
-    use zstd::DEFAULT_COMPRESSION_LEVEL;
-
     use crate::{repository::keys::generate_new_master_key, ui};
 
     use super::*;
@@ -217,6 +456,91 @@ cupiditat non proident, sunt in culpa qui officia deserunt mollit anim id est la
         }
     }
 
+    #[test]
+    fn test_skip_compression_for_incompressible_data() -> Result<()> {
+        let mut random_data = vec![0u8; 256 * 1024];
+        OsRng.try_fill_bytes(&mut random_data)?;
+        assert!(!SecureStorage::is_compressible(&random_data));
+        assert!(SecureStorage::is_compressible(TEXT));
+
+        let secure_storage = SecureStorage::build()
+            .with_compression(Compression::default())
+            .with_key(generate_new_master_key());
+
+        let (encoded, compressed) = secure_storage.encode_with_info(&random_data)?;
+        assert!(!compressed);
+        assert_eq!(secure_storage.decode(&encoded)?, random_data);
+
+        let (encoded, compressed) = secure_storage.encode_with_info(TEXT)?;
+        assert!(compressed);
+        assert_eq!(secure_storage.decode(&encoded)?, TEXT);
+
+        // Forced compression modes
+        let secure_storage = secure_storage.with_compression_mode(CompressionMode::Always);
+        let (encoded, compressed) = secure_storage.encode_with_info(&random_data)?;
+        assert!(compressed);
+        assert_eq!(secure_storage.decode(&encoded)?, random_data);
+
+        let secure_storage = secure_storage.with_compression_mode(CompressionMode::Never);
+        let (encoded, compressed) = secure_storage.encode_with_info(TEXT)?;
+        assert!(!compressed);
+        assert_eq!(secure_storage.decode(&encoded)?, TEXT);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compression_algorithms() -> Result<()> {
+        let compressions = [
+            "zstd",
+            "zstd:19",
+            "zstd-long",
+            "zstd-long:-5",
+            "lz4",
+            "none",
+        ];
+
+        // Blobs encoded with any algorithm can be decoded with any configuration.
+        let data = TEXT.repeat(4);
+        let key = generate_new_master_key();
+        let decoder = SecureStorage::build().with_key(key.clone());
+
+        for compression in compressions {
+            let compression: Compression = compression.parse()?;
+            assert_eq!(compression.to_string().parse::<Compression>()?, compression);
+
+            let secure_storage = SecureStorage::build()
+                .with_compression(compression)
+                .with_key(key.clone());
+            let (encoded, compressed) = secure_storage.encode_with_info(&data)?;
+            assert_eq!(compressed, compression != Compression::None);
+            assert_eq!(decoder.decode(&encoded)?, data);
+        }
+
+        // Algorithm names are case insensitive
+        assert_eq!(
+            "ZSTD-LONG".parse::<Compression>()?,
+            Compression::Zstd {
+                level: DEFAULT_COMPRESSION_LEVEL,
+                long: true
+            }
+        );
+        assert_eq!(
+            "Zstd-Long:19".parse::<Compression>()?,
+            Compression::Zstd {
+                level: 19,
+                long: true
+            }
+        );
+        assert_eq!("LZ4".parse::<Compression>()?, Compression::Lz4);
+
+        assert!("zstd:100".parse::<Compression>().is_err());
+        assert!("lz4:3".parse::<Compression>().is_err());
+        assert!("gzip".parse::<Compression>().is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_generate_salt() {
         let salt = SecureStorage::generate_salt::<4>();
@@ -236,7 +560,7 @@ cupiditat non proident, sunt in culpa qui officia deserunt mollit anim id est la
     fn test_deterministic_encryption() -> Result<()> {
         let key = generate_new_master_key();
         let secure_storage = SecureStorage::build()
-            .with_compression(DEFAULT_COMPRESSION_LEVEL)
+            .with_compression(Compression::default())
             .with_key(key);
         let ciphertext = secure_storage.encode(TEXT)?;
         let decoded_plaintext = secure_storage.decode(&ciphertext)?;
@@ -16,6 +16,8 @@
 
 use std::{
     cmp::Ordering,
+    fs::File,
+    io::Read,
     path::{Path, PathBuf},
     sync::Arc,
 };
@@ -24,7 +26,7 @@ use anyhow::{Context, Result, anyhow, bail};
 
 use crate::{global::ID, repository::repo::Repository, utils};
 
-use super::tree::{Node, Tree};
+use super::tree::{ChangeDetection, Node, Tree};
 
 #[derive(Debug)]
 pub struct StreamNode {
@@ -34,6 +36,27 @@ pub struct StreamNode {
 
 pub type StreamNodeInfo = (PathBuf, StreamNode);
 
+/// Signature that identifies a cache directory tag file.
+/// See <https://bford.info/cachedir/>.
+const CACHEDIR_TAG_FILENAME: &str = "CACHEDIR.TAG";
+const CACHEDIR_TAG_SIGNATURE: &[u8] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+/// Additional rules to skip items while exploring the filesystem.
+#[derive(Debug, Clone, Default)]
+pub struct ExcludeOptions {
+    /// Skip directories containing a valid CACHEDIR.TAG file.
+    pub exclude_caches: bool,
+
+    /// Skip directories containing any file with one of these names.
+    pub exclude_if_present: Vec<String>,
+
+    /// Do not cross filesystem boundaries.
+    pub one_file_system: bool,
+
+    /// Skip files larger than this size in bytes.
+    pub exclude_larger_than: Option<u64>,
+}
+
 /// A depth‑first *pre‑order* filesystem streamer.
 ///
 /// Items are produced in lexicographical order of their *full* paths. The root path is not emitted.
@@ -43,18 +66,25 @@ pub type StreamNodeInfo = (PathBuf, StreamNode);
 /// This streamer will emit all the merged nodes as if they belong to the same tree,
 /// intercalating intermediate paths between disjoint branches.
 /// This streamer also allows excluding a list of paths. Paths in this list, and their
-/// children, are never explored nor emitted.
+/// children, are never explored nor emitted. Items matching the `ExcludeOptions` are
+/// skipped in the same way and counted.
 #[derive(Debug)]
 pub struct FSNodeStreamer {
     stack: Vec<PathBuf>,
     intermediate_paths: Vec<(PathBuf, usize)>,
     exclude_paths: Vec<PathBuf>,
+    exclude_options: ExcludeOptions,
+    skipped_items: u64,
 }
 
 impl FSNodeStreamer {
     /// Creates an FSNodeStreamer from multiple root paths. The paths are iterated in lexicographical order.
     /// Exclude paths and their children are neither emitted nor explored into.
-    pub fn from_paths(mut paths: Vec<PathBuf>, mut exclude_paths: Vec<PathBuf>) -> Result<Self> {
+    pub fn from_paths(
+        mut paths: Vec<PathBuf>,
+        mut exclude_paths: Vec<PathBuf>,
+        exclude_options: ExcludeOptions,
+    ) -> Result<Self> {
         for path in &paths {
             if !path.exists() {
                 bail!("Path {} does not exist", path.display());
@@ -83,9 +113,53 @@ impl FSNodeStreamer {
             stack: paths,
             intermediate_paths,
             exclude_paths,
+            exclude_options,
+            skipped_items: 0,
         })
     }
 
+    /// Returns the number of items skipped so far by the `ExcludeOptions`.
+    pub fn skipped_items(&self) -> u64 {
+        self.skipped_items
+    }
+
+    // Checks if a child of a directory in device `parent_dev` must be skipped according
+    // to the `ExcludeOptions`. Items that cannot be inspected are never skipped, so that
+    // the error is reported when the node is read.
+    fn is_skipped(&self, path: &Path, parent_dev: Option<u64>) -> bool {
+        let metadata = match std::fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return false,
+        };
+
+        if metadata.is_file() {
+            return self
+                .exclude_options
+                .exclude_larger_than
+                .is_some_and(|limit| metadata.len() > limit);
+        }
+
+        if !metadata.is_dir() {
+            return false;
+        }
+
+        if self.exclude_options.one_file_system
+            && let Some(parent_dev) = parent_dev
+            && device_id(&metadata) != Some(parent_dev)
+        {
+            return true;
+        }
+
+        if self.exclude_options.exclude_caches && is_cache_dir(path) {
+            return true;
+        }
+
+        self.exclude_options
+            .exclude_if_present
+            .iter()
+            .any(|name| std::fs::symlink_metadata(path.join(name)).is_ok())
+    }
+
     // Get all children sorted in lexicographical order.
     fn get_children_sorted(dir: &Path) -> Result<Vec<PathBuf>> {
         match std::fs::read_dir(dir) {
@@ -170,11 +244,25 @@ impl Iterator for FSNodeStreamer {
                 let children = Self::get_children_sorted(&path)?;
                 let mut valid_children_count = 0;
 
+                let parent_dev = match self.exclude_options.one_file_system {
+                    true => std::fs::symlink_metadata(&path)
+                        .ok()
+                        .and_then(|metadata| device_id(&metadata)),
+                    false => None,
+                };
+
                 for child in children.into_iter().rev() {
-                    if utils::filter_path(&child, None, Some(&self.exclude_paths)) {
-                        self.stack.push(child);
-                        valid_children_count += 1;
+                    if !utils::filter_path(&child, None, Some(&self.exclude_paths)) {
+                        continue;
+                    }
+
+                    if self.is_skipped(&child, parent_dev) {
+                        self.skipped_items += 1;
+                        continue;
                     }
+
+                    self.stack.push(child);
+                    valid_children_count += 1;
                 }
                 valid_children_count
             } else {
@@ -188,6 +276,30 @@ impl Iterator for FSNodeStreamer {
     }
 }
 
+#[cfg(unix)]
+fn device_id(metadata: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device_id(_metadata: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+// Returns true if the directory contains a CACHEDIR.TAG file with a valid signature.
+fn is_cache_dir(dir: &Path) -> bool {
+    let tag_path = dir.join(CACHEDIR_TAG_FILENAME);
+    let mut signature = [0u8; CACHEDIR_TAG_SIGNATURE.len()];
+
+    match File::open(tag_path) {
+        Ok(mut file) => {
+            file.read_exact(&mut signature).is_ok() && signature == CACHEDIR_TAG_SIGNATURE
+        }
+        Err(_) => false,
+    }
+}
+
 /// A depth‑first *pre‑order* streamer of serialized nodes.
 ///
 /// Items are produced in lexicographical order of their *full* paths. The root node is not emitted.
@@ -331,6 +443,7 @@ where
     next: I,
     head_prev: Option<Result<(PathBuf, StreamNode)>>,
     head_next: Option<Result<(PathBuf, StreamNode)>>,
+    change_detection: ChangeDetection,
 }
 
 impl<P, I> NodeDiffStreamer<P, I>
@@ -344,8 +457,16 @@ where
             head_next: next.next(),
             prev,
             next,
+            change_detection: ChangeDetection::Default,
         }
     }
+
+    /// Sets the mode used to decide whether a node shared by `previous` and `next` changed.
+    /// In `content` mode, shared files are always reported as changed, so that they are read again.
+    pub fn with_change_detection(mut self, change_detection: ChangeDetection) -> Self {
+        self.change_detection = change_detection;
+        self
+    }
 }
 
 impl<P, I> Iterator for NodeDiffStreamer<P, I>
@@ -409,11 +530,14 @@ where
                         self.head_prev = self.prev.next();
                         self.head_next = self.next.next();
 
-                        let diff_type = if previous_node
-                            .node
-                            .metadata
-                            .has_changed(&incoming_node.node.metadata)
-                        {
+                        let read_content = self.change_detection == ChangeDetection::Content
+                            && incoming_node.node.is_file();
+
+                        let diff_type = if read_content
+                            || previous_node.node.metadata.has_changed_with(
+                                &incoming_node.node.metadata,
+                                self.change_detection,
+                            ) {
                             NodeDiff::Changed
                         } else {
                             NodeDiff::Unchanged
@@ -499,6 +623,79 @@ pub fn find_serialized_node(
     Ok(None)
 }
 
+/// Reads the contents of a file node. Blobs are loaded from the repository as they are needed.
+pub struct FileReader<'a> {
+    repo: &'a Repository,
+    blobs: &'a [ID],
+    next_blob: usize,
+    buffer: Vec<u8>,
+    pos: usize,
+}
+
+impl<'a> FileReader<'a> {
+    pub fn new(repo: &'a Repository, node: &'a Node) -> Self {
+        Self {
+            repo,
+            blobs: node.blobs.as_deref().unwrap_or_default(),
+            next_blob: 0,
+            buffer: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Skips the first `offset` bytes of the file. Whole blobs are skipped using their length
+    /// in the index, without loading them.
+    pub fn skip(&mut self, mut offset: u64) -> Result<()> {
+        while offset > 0 {
+            let buffered = (self.buffer.len() - self.pos) as u64;
+            if buffered > 0 {
+                let skipped = buffered.min(offset);
+                self.pos += skipped as usize;
+                offset -= skipped;
+                continue;
+            }
+
+            let Some(id) = self.blobs.get(self.next_blob) else {
+                break;
+            };
+            let raw_length = self
+                .repo
+                .index()
+                .read()
+                .get(id)
+                .map(|(_, _, _, _, raw_length)| raw_length as u64);
+            match raw_length {
+                Some(raw_length) if raw_length <= offset => offset -= raw_length,
+                _ => {
+                    self.buffer = self.repo.load_blob(id)?;
+                    self.pos = 0;
+                }
+            }
+            self.next_blob += 1;
+        }
+
+        Ok(())
+    }
+}
+
+impl Read for FileReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.buffer.len() {
+            let Some(id) = self.blobs.get(self.next_blob) else {
+                return Ok(0);
+            };
+            self.buffer = self.repo.load_blob(id).map_err(std::io::Error::other)?;
+            self.next_blob += 1;
+            self.pos = 0;
+        }
+
+        let len = buf.len().min(self.buffer.len() - self.pos);
+        buf[..len].copy_from_slice(&self.buffer[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use tempfile::tempdir;
@@ -534,7 +731,11 @@ mod tests {
         let tmp_path = temp_dir.path();
         create_tree(tmp_path)?;
 
-        let streamer = FSNodeStreamer::from_paths(vec![tmp_path.join("dir_a")], Vec::new())?;
+        let streamer = FSNodeStreamer::from_paths(
+            vec![tmp_path.join("dir_a")],
+            Vec::new(),
+            ExcludeOptions::default(),
+        )?;
         let nodes: Vec<Result<(PathBuf, StreamNode)>> = streamer.collect();
 
         assert_eq!(nodes.len(), 6);
@@ -572,6 +773,7 @@ mod tests {
         let streamer = FSNodeStreamer::from_paths(
             vec![tmp_path.join("dir_a"), tmp_path.join("dir_b")],
             Vec::new(),
+            ExcludeOptions::default(),
         )?;
         let nodes: Vec<Result<(PathBuf, StreamNode)>> = streamer.collect();
 
@@ -618,6 +820,7 @@ mod tests {
                 tmp_path.join("dir_a").join("dir2").join("file1"),
             ],
             Vec::new(),
+            ExcludeOptions::default(),
         )?;
         let nodes: Vec<Result<(PathBuf, StreamNode)>> = streamer.collect();
 
@@ -644,8 +847,16 @@ mod tests {
         let tmp_path = temp_dir.path();
         create_tree(tmp_path)?;
 
-        let dir_a = FSNodeStreamer::from_paths(vec![tmp_path.join("dir_a")], Vec::new())?;
-        let dir_b = FSNodeStreamer::from_paths(vec![tmp_path.join("dir_b")], Vec::new())?;
+        let dir_a = FSNodeStreamer::from_paths(
+            vec![tmp_path.join("dir_a")],
+            Vec::new(),
+            ExcludeOptions::default(),
+        )?;
+        let dir_b = FSNodeStreamer::from_paths(
+            vec![tmp_path.join("dir_b")],
+            Vec::new(),
+            ExcludeOptions::default(),
+        )?;
         let diff_streamer = NodeDiffStreamer::new(dir_a, dir_b);
         let diffs: Vec<Result<DiffTuple>> = diff_streamer.collect();
 
@@ -668,8 +879,16 @@ mod tests {
         let tmp_path = temp_dir.path();
         create_tree(tmp_path)?;
 
-        let dir_a1 = FSNodeStreamer::from_paths(vec![tmp_path.join("dir_a")], Vec::new())?;
-        let dir_a2 = FSNodeStreamer::from_paths(vec![tmp_path.join("dir_a")], Vec::new())?;
+        let dir_a1 = FSNodeStreamer::from_paths(
+            vec![tmp_path.join("dir_a")],
+            Vec::new(),
+            ExcludeOptions::default(),
+        )?;
+        let dir_a2 = FSNodeStreamer::from_paths(
+            vec![tmp_path.join("dir_a")],
+            Vec::new(),
+            ExcludeOptions::default(),
+        )?;
         let diff_streamer = NodeDiffStreamer::new(dir_a1, dir_a2);
         let diffs: Vec<Result<DiffTuple>> = diff_streamer.collect();
 
@@ -684,6 +903,39 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_diff_same_tree_content_mode() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let tmp_path = temp_dir.path();
+        create_tree(tmp_path)?;
+
+        let dir_a1 = FSNodeStreamer::from_paths(
+            vec![tmp_path.join("dir_a")],
+            Vec::new(),
+            ExcludeOptions::default(),
+        )?;
+        let dir_a2 = FSNodeStreamer::from_paths(
+            vec![tmp_path.join("dir_a")],
+            Vec::new(),
+            ExcludeOptions::default(),
+        )?;
+        let diff_streamer =
+            NodeDiffStreamer::new(dir_a1, dir_a2).with_change_detection(ChangeDetection::Content);
+        let diffs: Vec<DiffTuple> = diff_streamer.collect::<Result<_>>()?;
+
+        // Files are always read again, directories are compared by metadata.
+        assert_eq!(diffs.len(), 6);
+        for (_, _, next, diff) in &diffs {
+            if next.as_ref().unwrap().node.is_file() {
+                assert_eq!(*diff, NodeDiff::Changed);
+            } else {
+                assert_eq!(*diff, NodeDiff::Unchanged);
+            }
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_fs_node_streamer_with_exclude_paths() -> Result<()> {
         let temp_dir = tempdir()?;
@@ -693,6 +945,7 @@ mod tests {
         let streamer = FSNodeStreamer::from_paths(
             vec![tmp_path.join("dir_a"), tmp_path.join("dir_b")],
             vec![tmp_path.join("dir_b")],
+            ExcludeOptions::default(),
         )?;
         let nodes: Vec<Result<(PathBuf, StreamNode)>> = streamer.collect();
 
@@ -721,4 +974,74 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_fs_node_streamer_with_exclude_options() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let tmp_path = temp_dir.path();
+        create_tree(tmp_path)?;
+
+        // dir0 is a cache directory, dir1 contains a marker file and file0 is too large
+        std::fs::write(
+            tmp_path
+                .join("dir_a")
+                .join("dir0")
+                .join(CACHEDIR_TAG_FILENAME),
+            [CACHEDIR_TAG_SIGNATURE, b"\n# Cache directory tag"].concat(),
+        )?;
+        std::fs::File::create(tmp_path.join("dir_a").join("dir1").join(".nobackup"))?;
+        std::fs::write(tmp_path.join("dir_a").join("file0"), [0u8; 16])?;
+
+        let mut streamer = FSNodeStreamer::from_paths(
+            vec![tmp_path.join("dir_a")],
+            Vec::new(),
+            ExcludeOptions {
+                exclude_caches: true,
+                exclude_if_present: vec![".nobackup".to_string()],
+                one_file_system: true,
+                exclude_larger_than: Some(8),
+            },
+        )?;
+        let nodes: Vec<Result<(PathBuf, StreamNode)>> = streamer.by_ref().collect();
+
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(nodes[0].as_ref().unwrap().0, tmp_path.join("dir_a"));
+        assert_eq!(nodes[0].as_ref().unwrap().1.num_children, 1);
+        assert_eq!(
+            nodes[1].as_ref().unwrap().0,
+            tmp_path.join("dir_a").join("dir2")
+        );
+        assert_eq!(
+            nodes[2].as_ref().unwrap().0,
+            tmp_path.join("dir_a").join("dir2").join("file1")
+        );
+        assert_eq!(streamer.skipped_items(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fs_node_streamer_ignores_invalid_cache_tag() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let tmp_path = temp_dir.path();
+        create_tree(tmp_path)?;
+
+        std::fs::write(
+            tmp_path.join("dir_b").join(CACHEDIR_TAG_FILENAME),
+            b"Not a cache directory",
+        )?;
+
+        let exclude_options = ExcludeOptions {
+            exclude_caches: true,
+            ..Default::default()
+        };
+        let mut streamer =
+            FSNodeStreamer::from_paths(vec![tmp_path.to_path_buf()], Vec::new(), exclude_options)?;
+        let nodes: Vec<Result<(PathBuf, StreamNode)>> = streamer.by_ref().collect();
+
+        assert_eq!(nodes.len(), 10);
+        assert_eq!(streamer.skipped_items(), 0);
+
+        Ok(())
+    }
 }
@@ -20,6 +20,9 @@ use std::{
     time::SystemTime,
 };
 
+#[cfg(unix)]
+use std::time::{Duration, UNIX_EPOCH};
+
 #[cfg(unix)]
 use std::os::unix::fs::FileTypeExt;
 
@@ -27,17 +30,21 @@ use std::os::unix::fs::FileTypeExt;
 use std::os::unix::fs::MetadataExt;
 
 use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 
 use crate::global::{ID, SaveID};
+#[cfg(unix)]
+use crate::utils::host;
 use crate::{global::BlobType, repository::repo::Repository};
 
 /// The type of a node (file, directory, symlink, etc.)
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
 #[serde(rename_all = "lowercase")]
 pub enum NodeType {
     #[default]
     File,
+    #[value(alias = "dir")]
     Directory,
     Symlink,
     BlockDevice,
@@ -65,6 +72,10 @@ pub struct Node {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tree: Option<ID>, // For directories
+
+    /// The file changed while it was being read and its contents may be inconsistent
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub inconsistent: bool,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -123,6 +134,42 @@ pub struct Metadata {
     // Raw device ID for block/char devices
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rdev: Option<u64>,
+
+    /// Changed time (ctime). The last time the contents or the attributes changed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changed_time: Option<SystemTime>,
+
+    /// Name of the owner user, used to map the owner when restoring in another host
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner_user: Option<String>,
+    /// Name of the owner group, used to map the group when restoring in another host
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner_group: Option<String>,
+}
+
+/// How changes between two versions of a node are detected.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ChangeDetection {
+    /// Compare the size, mtime, ctime, mode, owner and inode.
+    #[default]
+    Default,
+    /// Like `default`, but ignore the inode. Useful in filesystems with unstable inodes.
+    IgnoreInode,
+    /// Like `default`, but ignore the ctime.
+    IgnoreCtime,
+    /// Always read the files again and compare their contents.
+    Content,
+}
+
+impl std::fmt::Display for ChangeDetection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChangeDetection::Default => write!(f, "default"),
+            ChangeDetection::IgnoreInode => write!(f, "ignore-inode"),
+            ChangeDetection::IgnoreCtime => write!(f, "ignore-ctime"),
+            ChangeDetection::Content => write!(f, "content"),
+        }
+    }
 }
 
 impl Metadata {
@@ -163,18 +210,52 @@ impl Metadata {
             rdev: Some(meta.rdev()),
             #[cfg(not(unix))]
             rdev: None,
+
+            #[cfg(unix)]
+            changed_time: u64::try_from(meta.ctime())
+                .ok()
+                .map(|secs| UNIX_EPOCH + Duration::new(secs, meta.ctime_nsec() as u32)),
+            #[cfg(not(unix))]
+            changed_time: None,
+
+            #[cfg(unix)]
+            owner_user: host::user_name(meta.uid()),
+            #[cfg(not(unix))]
+            owner_user: None,
+
+            #[cfg(unix)]
+            owner_group: host::group_name(meta.gid()),
+            #[cfg(not(unix))]
+            owner_group: None,
         }
     }
 
     /// Returns `true` iff any relevant metadata field differs.
     #[inline]
     pub fn has_changed(&self, other: &Self) -> bool {
+        self.has_changed_with(other, ChangeDetection::Default)
+    }
+
+    /// Returns `true` iff any relevant metadata field differs, according to the
+    /// change detection mode. The `content` mode compares the metadata like `default`.
+    ///
+    /// The ctime is only compared if it is known for both nodes, so that nodes saved
+    /// before it was recorded are not considered changed.
+    pub fn has_changed_with(&self, other: &Self, change_detection: ChangeDetection) -> bool {
+        let ctime_changed = change_detection != ChangeDetection::IgnoreCtime
+            && self.changed_time.is_some()
+            && other.changed_time.is_some()
+            && self.changed_time != other.changed_time;
+        let inode_changed =
+            change_detection != ChangeDetection::IgnoreInode && self.inode != other.inode;
+
         self.modified_time != other.modified_time
             || self.size != other.size
             || self.mode != other.mode
             || self.owner_uid != other.owner_uid
             || self.owner_gid != other.owner_gid
-            || self.inode != other.inode
+            || inode_changed
+            || ctime_changed
     }
 }
 
@@ -202,6 +283,7 @@ impl Node {
             blobs: None,
             tree: None,
             symlink_info: None,
+            inconsistent: false,
         };
 
         if node.is_symlink() {
@@ -323,7 +405,7 @@ impl Tree {
         self.nodes.sort_by_key(|node| node.name.clone());
 
         let tree_json = serde_json::to_string(self)?.as_bytes().to_vec();
-        let (id, (raw_data_size, encoded_data_size), (raw_meta_size, encoded_meta_size)) =
+        let (id, (raw_data_size, encoded_data_size, _), (raw_meta_size, encoded_meta_size)) =
             repo.encode_and_save_blob(BlobType::Tree, tree_json, SaveID::CalculateID)?;
 
         Ok((
@@ -121,10 +121,10 @@ pub fn verify_snapshot_links(repo: Arc<Repository>, snapshot_id: &ID) -> Result<
                 }
             }
             NodeType::Directory => {
-                if let Some(tree_id) = node.tree {
-                    if repo.index().read().get(&tree_id).is_none() {
-                        error_counter += 1;
-                    }
+                if let Some(tree_id) = node.tree
+                    && repo.index().read().get(&tree_id).is_none()
+                {
+                    error_counter += 1;
                 }
             }
             NodeType::Symlink
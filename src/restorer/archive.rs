@@ -0,0 +1,291 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use zip::write::{SimpleFileOptions, StreamWriter};
+
+use crate::{
+    repository::{
+        repo::Repository,
+        snapshot::Snapshot,
+        streamers::{FileReader, SerializedNodeStreamer},
+        tree::{Node, NodeType},
+    },
+    restorer::PrefixStripper,
+    ui,
+};
+
+/// Formats in which a snapshot tree can be written as an archive.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ArchiveFormat {
+    Tar,
+    #[value(name = "tar.zst")]
+    TarZst,
+    Zip,
+}
+
+/// Writes the tree of a snapshot as an archive. Nothing is written to the local filesystem;
+/// file contents are streamed from the repository into `writer`.
+pub fn write_archive<W: Write>(
+    repo: Arc<Repository>,
+    snapshot: &Snapshot,
+    include: Option<Vec<PathBuf>>,
+    exclude: Option<Vec<PathBuf>>,
+    strip_prefix: Option<PathBuf>,
+    format: ArchiveFormat,
+    writer: W,
+) -> Result<()> {
+    let node_streamer = SerializedNodeStreamer::new(
+        repo.clone(),
+        Some(snapshot.tree.clone()),
+        PathBuf::new(),
+        include,
+        exclude,
+    )?;
+    let prefix_stripper = PrefixStripper::new(strip_prefix);
+
+    let append_nodes = |archive: &mut dyn ArchiveWriter| -> Result<()> {
+        for node_res in node_streamer {
+            let (path, stream_node) = node_res?;
+            let Some(path) = prefix_stripper.strip(path)? else {
+                continue;
+            };
+
+            let node = &stream_node.node;
+            let mut contents = FileReader::new(repo.as_ref(), node).take(node.metadata.size);
+            archive
+                .append(&path, node, &mut contents)
+                .with_context(|| format!("Failed to archive '{}'", path.display()))?;
+
+            if node.is_file() && contents.limit() > 0 {
+                bail!(
+                    "The contents of '{}' are shorter than its size",
+                    path.display()
+                );
+            }
+        }
+        Ok(())
+    };
+
+    match format {
+        ArchiveFormat::Tar => {
+            let mut builder = tar::Builder::new(writer);
+            append_nodes(&mut builder)?;
+            builder.into_inner()?.flush()?;
+        }
+        ArchiveFormat::TarZst => {
+            let encoder = zstd::Encoder::new(writer, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+            let mut builder = tar::Builder::new(encoder);
+            append_nodes(&mut builder)?;
+            builder.into_inner()?.finish()?.flush()?;
+        }
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipWriter::new_stream(writer);
+            append_nodes(&mut zip)?;
+            zip.finish()?.into_inner().flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// An archive to which snapshot nodes are appended in order.
+trait ArchiveWriter {
+    fn append(&mut self, path: &Path, node: &Node, contents: &mut dyn Read) -> Result<()>;
+}
+
+impl<W: Write> ArchiveWriter for tar::Builder<W> {
+    fn append(&mut self, path: &Path, node: &Node, contents: &mut dyn Read) -> Result<()> {
+        let metadata = &node.metadata;
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(metadata.mode.unwrap_or_else(|| default_mode(node)) & 0o7777);
+        header.set_uid(metadata.owner_uid.unwrap_or(0) as u64);
+        header.set_gid(metadata.owner_gid.unwrap_or(0) as u64);
+        // Names longer than the header fields are left out. The numeric IDs are still set.
+        if let Some(user) = &metadata.owner_user {
+            let _ = header.set_username(user);
+        }
+        if let Some(group) = &metadata.owner_group {
+            let _ = header.set_groupname(group);
+        }
+        header.set_mtime(unix_seconds(metadata.modified_time));
+        header.set_size(0);
+
+        match node.node_type {
+            NodeType::File => {
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(metadata.size);
+                self.append_data(&mut header, path, contents)?;
+            }
+            NodeType::Directory => {
+                header.set_entry_type(tar::EntryType::Directory);
+                self.append_data(&mut header, path, io::empty())?;
+            }
+            NodeType::Symlink => {
+                let Some(symlink_info) = &node.symlink_info else {
+                    bail!("Symlink without target");
+                };
+                header.set_entry_type(tar::EntryType::Symlink);
+                self.append_link(&mut header, path, &symlink_info.target_path)?;
+            }
+            NodeType::BlockDevice | NodeType::CharDevice => {
+                header.set_entry_type(if node.is_block_device() {
+                    tar::EntryType::Block
+                } else {
+                    tar::EntryType::Char
+                });
+                let (major, minor) = device_numbers(metadata.rdev.unwrap_or(0));
+                header.set_device_major(major)?;
+                header.set_device_minor(minor)?;
+                self.append_data(&mut header, path, io::empty())?;
+            }
+            NodeType::Fifo => {
+                header.set_entry_type(tar::EntryType::Fifo);
+                self.append_data(&mut header, path, io::empty())?;
+            }
+            NodeType::Socket => {
+                ui::cli::warning!("Skipping socket '{}'", path.display());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write> ArchiveWriter for zip::ZipWriter<StreamWriter<W>> {
+    fn append(&mut self, path: &Path, node: &Node, contents: &mut dyn Read) -> Result<()> {
+        let metadata = &node.metadata;
+        let name = path.to_string_lossy();
+        let options = SimpleFileOptions::default()
+            .unix_permissions(metadata.mode.unwrap_or_else(|| default_mode(node)) & 0o7777)
+            .last_modified_time(zip_datetime(metadata.modified_time));
+
+        // Zip archives don't store ownership nor special files
+        match node.node_type {
+            NodeType::File => {
+                let options = options
+                    .compression_method(zip::CompressionMethod::Deflated)
+                    .large_file(metadata.size >= u32::MAX as u64);
+                self.start_file(name, options)?;
+                io::copy(contents, self)?;
+            }
+            NodeType::Directory => {
+                self.add_directory(name, options)?;
+            }
+            NodeType::Symlink => {
+                let Some(symlink_info) = &node.symlink_info else {
+                    bail!("Symlink without target");
+                };
+                self.add_symlink(name, symlink_info.target_path.to_string_lossy(), options)?;
+            }
+            NodeType::BlockDevice | NodeType::CharDevice | NodeType::Fifo | NodeType::Socket => {
+                ui::cli::warning!(
+                    "Skipping '{}': zip archives cannot store special files",
+                    path.display()
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Permissions used for nodes saved without a mode.
+fn default_mode(node: &Node) -> u32 {
+    match node.node_type {
+        NodeType::Directory => 0o755,
+        NodeType::Symlink => 0o777,
+        _ => 0o644,
+    }
+}
+
+fn unix_seconds(time: Option<SystemTime>) -> u64 {
+    time.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Converts a time to the local MS-DOS time stored in zip archives. Times out of range
+/// (before 1980) are clamped to the earliest representable time.
+fn zip_datetime(time: Option<SystemTime>) -> zip::DateTime {
+    time.and_then(|t| {
+        let local: chrono::DateTime<chrono::Local> = t.into();
+        zip::DateTime::try_from(local.naive_local()).ok()
+    })
+    .unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn device_numbers(rdev: u64) -> (u32, u32) {
+    let dev = rdev as libc::dev_t;
+    (libc::major(dev), libc::minor(dev))
+}
+
+#[cfg(not(unix))]
+fn device_numbers(_rdev: u64) -> (u32, u32) {
+    (0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repository::tree::Metadata;
+
+    use super::*;
+
+    /// Test that owner names that don't fit in a tar header don't abort the archive
+    #[test]
+    fn test_tar_long_owner_names() -> Result<()> {
+        let node = Node {
+            name: String::from("file.txt"),
+            node_type: NodeType::File,
+            metadata: Metadata {
+                size: 7,
+                owner_uid: Some(1234),
+                owner_gid: Some(5678),
+                owner_user: Some("u".repeat(64)),
+                owner_group: Some(String::from("mapache")),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut builder = tar::Builder::new(Vec::new());
+        ArchiveWriter::append(
+            &mut builder,
+            Path::new("file.txt"),
+            &node,
+            &mut "mapache".as_bytes(),
+        )?;
+        let data = builder.into_inner()?;
+
+        let mut archive = tar::Archive::new(data.as_slice());
+        let entry = archive.entries()?.next().context("Missing entry")??;
+        let header = entry.header();
+        assert_eq!(header.uid()?, 1234);
+        assert_eq!(header.gid()?, 5678);
+        assert_eq!(header.username()?, Some(""));
+        assert_eq!(header.groupname()?, Some("mapache"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::global::{ID, defaults::RESTORE_JOURNAL_FILENAME};
+
+#[derive(Serialize, Deserialize)]
+struct JournalHeader {
+    snapshot: ID,
+}
+
+/// Records the files whose restoration completed, so that an interrupted restore can be resumed.
+///
+/// The journal lives in the target directory. The first line is a header with the ID of the
+/// snapshot being restored; each following line is a JSON string with the path of a completed
+/// file. Lines are appended as files complete, so the journal is valid at any point.
+pub(crate) struct RestoreJournal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl RestoreJournal {
+    /// Returns the path of the journal for a target directory.
+    pub(crate) fn path(target_path: &Path) -> PathBuf {
+        target_path.join(RESTORE_JOURNAL_FILENAME)
+    }
+
+    /// Starts a new journal, replacing any previous one.
+    pub(crate) fn create(target_path: &Path, snapshot_id: &ID) -> Result<Self> {
+        fs::create_dir_all(target_path)
+            .with_context(|| format!("Could not create directory '{}'", target_path.display()))?;
+
+        let path = Self::path(target_path);
+        let mut file = File::create(&path)
+            .with_context(|| format!("Could not create restore journal '{}'", path.display()))?;
+        let header = JournalHeader {
+            snapshot: snapshot_id.clone(),
+        };
+        writeln!(file, "{}", serde_json::to_string(&header)?)?;
+        file.sync_data()?;
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Opens the journal of an interrupted restore of the same snapshot. Returns the journal and
+    /// the paths of the files already restored.
+    pub(crate) fn resume(target_path: &Path, snapshot_id: &ID) -> Result<(Self, HashSet<PathBuf>)> {
+        let path = Self::path(target_path);
+        let reader = match File::open(&path) {
+            Ok(file) => BufReader::new(file),
+            Err(_) => bail!("No restore to resume in '{}'", target_path.display()),
+        };
+
+        let mut lines = reader.lines();
+        let header: JournalHeader = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)
+                .with_context(|| format!("Invalid restore journal '{}'", path.display()))?,
+            None => bail!("Invalid restore journal '{}'", path.display()),
+        };
+        if header.snapshot != *snapshot_id {
+            bail!(
+                "The interrupted restore in '{}' is from snapshot {}",
+                target_path.display(),
+                header.snapshot
+            );
+        }
+
+        // The last line may be incomplete if the restore was interrupted while writing it
+        let mut completed = HashSet::new();
+        for line in lines {
+            if let Ok(completed_path) = serde_json::from_str::<PathBuf>(&line?) {
+                completed.insert(completed_path);
+            }
+        }
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Could not open restore journal '{}'", path.display()))?;
+        // Terminate a possibly incomplete last line. Empty lines are ignored.
+        writeln!(file)?;
+
+        Ok((
+            Self {
+                path,
+                file: Mutex::new(file),
+            },
+            completed,
+        ))
+    }
+
+    /// Records a file as completely restored.
+    pub(crate) fn record(&self, path: &Path) -> Result<()> {
+        let line = serde_json::to_string(path)?;
+        let mut file = self.file.lock();
+        writeln!(file, "{line}")
+            .with_context(|| format!("Could not write restore journal '{}'", self.path.display()))
+    }
+
+    /// Deletes the journal once the restore finished.
+    pub(crate) fn remove(self) -> Result<()> {
+        fs::remove_file(&self.path)
+            .with_context(|| format!("Could not delete restore journal '{}'", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_journal_resume() -> Result<()> {
+        let tmp_dir = tempdir()?;
+        let target = tmp_dir.path().join("target");
+        let snapshot_id = ID::new_random();
+
+        let journal = RestoreJournal::create(&target, &snapshot_id)?;
+        journal.record(Path::new("dir/a.txt"))?;
+        journal.record(Path::new("dir/with\nnewline"))?;
+        drop(journal);
+
+        // Simulate an interruption while writing a line
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(RestoreJournal::path(&target))?;
+        write!(file, "\"dir/b.t")?;
+        drop(file);
+
+        assert!(RestoreJournal::resume(&target, &ID::new_random()).is_err());
+
+        let (journal, completed) = RestoreJournal::resume(&target, &snapshot_id)?;
+        journal.record(Path::new("dir/b.txt"))?;
+        drop(journal);
+
+        let (journal, completed_after) = RestoreJournal::resume(&target, &snapshot_id)?;
+        assert!(completed_after.contains(Path::new("dir/b.txt")));
+        assert_eq!(completed_after.len(), completed.len() + 1);
+        assert_eq!(
+            completed,
+            HashSet::from([
+                PathBuf::from("dir/a.txt"),
+                PathBuf::from("dir/with\nnewline")
+            ])
+        );
+
+        journal.remove()?;
+        assert!(!RestoreJournal::path(&target).exists());
+        assert!(RestoreJournal::resume(&target, &snapshot_id).is_err());
+
+        Ok(())
+    }
+}
@@ -14,10 +14,17 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+pub mod archive;
+mod journal;
 pub mod node_restorer;
+pub mod owners;
+mod pack_restorer;
+pub mod verify;
 
 use std::{
-    collections::BTreeSet,
+    collections::{BTreeSet, HashSet},
+    fs::{self, File, OpenOptions},
+    io::BufReader,
     path::{Path, PathBuf},
     sync::Arc,
 };
@@ -26,8 +33,17 @@ use anyhow::{Context, Ok, Result, bail};
 use clap::ValueEnum;
 
 use crate::{
-    repository::{repo::Repository, snapshot::Snapshot, streamers::SerializedNodeStreamer},
-    ui::restore_progress::RestoreProgressReporter,
+    archiver::processor::new_chunker,
+    global::{ID, defaults::SHORT_SNAPSHOT_ID_LEN},
+    repository::{
+        repo::Repository, snapshot::Snapshot, streamers::SerializedNodeStreamer, tree::Node,
+    },
+    restorer::{
+        journal::RestoreJournal,
+        owners::{OwnerMapper, OwnerOptions},
+        pack_restorer::RestorePlan,
+    },
+    ui::{self, restore_progress::RestoreProgressReporter},
     utils,
 };
 
@@ -36,12 +52,71 @@ pub enum Resolution {
     Skip,
     Overwrite,
     Fail,
+    IfNewer,
+    IfChanged,
+    Rename,
 }
 
 pub struct Options {
     pub resolution: Resolution,
     pub strip_prefix: Option<PathBuf>,
     pub dry_run: bool,
+    /// Number of packs read and restored in parallel.
+    pub concurrency: usize,
+    /// Update existing files in place, only restoring the chunks that differ.
+    pub delta: bool,
+    /// Delete items in the target that are not in the snapshot.
+    pub delete: bool,
+    /// Resume an interrupted restore of the same snapshot, using the journal in the target.
+    pub resume: bool,
+    /// How the owners of the restored items are assigned.
+    pub owners: OwnerOptions,
+}
+
+/// Removes a common prefix from the paths emitted by a `SerializedNodeStreamer`.
+pub(crate) struct PrefixStripper {
+    prefix: Option<PathBuf>,
+    /// Components of the prefix itself, which are not restored
+    prefix_components: BTreeSet<PathBuf>,
+}
+
+impl PrefixStripper {
+    pub(crate) fn new(prefix: Option<PathBuf>) -> Self {
+        let prefix_components = if let Some(ref prefix) = prefix {
+            let (_, inter) =
+                utils::get_intermediate_paths(&PathBuf::new(), &[prefix.to_path_buf()]);
+            inter.into_keys().collect()
+        } else {
+            BTreeSet::new()
+        };
+
+        Self {
+            prefix,
+            prefix_components,
+        }
+    }
+
+    /// Returns the stripped path, or None if the path is part of the prefix.
+    pub(crate) fn strip(&self, path: PathBuf) -> Result<Option<PathBuf>> {
+        let Some(prefix) = &self.prefix else {
+            return Ok(Some(path));
+        };
+
+        if self.prefix_components.contains(&path) {
+            return Ok(None);
+        }
+
+        let path = path
+            .strip_prefix(prefix)
+            .with_context(|| "Failed to strip prefix from restore path")?
+            .to_path_buf();
+
+        if path.as_os_str().is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(path))
+    }
 }
 
 pub struct Restorer {}
@@ -50,6 +125,7 @@ impl Restorer {
     #[allow(clippy::too_many_arguments)]
     pub fn restore(
         repo: Arc<Repository>,
+        snapshot_id: &ID,
         snapshot: &Snapshot,
         target_path: &Path,
         include: Option<Vec<PathBuf>>,
@@ -62,17 +138,23 @@ impl Restorer {
             repo.clone(),
             Some(tree),
             PathBuf::new(),
-            include,
-            exclude,
+            include.clone(),
+            exclude.clone(),
         )?;
 
-        // Exclude prefix components
-        let strip_excludes = if let Some(ref prefix) = opts.strip_prefix {
-            let (_, inter) =
-                utils::get_intermediate_paths(&PathBuf::new(), &[prefix.to_path_buf()]);
-            inter.into_keys().collect()
+        let prefix_stripper = PrefixStripper::new(opts.strip_prefix.clone());
+        let owner_mapper = OwnerMapper::new(&opts.owners, target_path)?;
+
+        // The journal records completed files. Files restored by an interrupted run are
+        // skipped, and other existing files are validated and completed like in a delta restore.
+        // A new journal is only created before the first write, so that a restore failing on a
+        // conflict leaves the target untouched.
+        let delta = opts.delta || opts.resume;
+        let (mut journal, completed) = if !opts.dry_run && opts.resume {
+            let (journal, completed) = RestoreJournal::resume(target_path, snapshot_id)?;
+            (Some(journal), completed)
         } else {
-            BTreeSet::new()
+            (None, HashSet::new())
         };
 
         // Stack directories to restore file times later
@@ -81,39 +163,152 @@ impl Restorer {
         // pop them in reverse order from the stack.
         let mut dir_stack = Vec::new();
 
+        // File contents are restored in a second phase, grouped by pack
+        let mut plan = RestorePlan::new();
+
+        // With --delete, keep track of every restored item and the directories whose
+        // full contents were selected. Extraneous items are only removed from those.
+        let mut restored_paths = HashSet::new();
+        let mut prune_dirs = Vec::new();
+
+        // Directories restored under a new name by the rename resolution, as (snapshot path,
+        // original restore path, renamed restore path). Their descendants follow the new name.
+        let mut renamed_dirs: Vec<(PathBuf, PathBuf, PathBuf)> = Vec::new();
+        if opts.delete {
+            restored_paths.insert(RestoreJournal::path(target_path));
+            if include.is_none() && opts.strip_prefix.is_none() {
+                prune_dirs.push(target_path.to_path_buf());
+            }
+        }
+
         for node_res in node_streamer {
-            let (mut path, stream_node) = node_res?;
+            let (path, mut stream_node) = node_res?;
+            owner_mapper.apply(&mut stream_node.node.metadata);
+            let fully_included = include
+                .as_ref()
+                .is_none_or(|paths| paths.iter().any(|p| path.starts_with(p)));
 
-            if let Some(prefix) = &opts.strip_prefix {
-                if strip_excludes.contains(&path) {
-                    continue;
-                }
+            let Some(path) = prefix_stripper.strip(path)? else {
+                continue;
+            };
 
-                path = path
-                    .strip_prefix(prefix)
-                    .with_context(|| "Failed to strip prefix from restore path")?
-                    .to_path_buf();
+            let mut restore_path = target_path.join(&path);
 
-                if path.to_str().unwrap() == "" {
-                    continue;
+            // Items are emitted in pre-order, so renamed directories are left in reverse order
+            while renamed_dirs
+                .last()
+                .is_some_and(|(dir_path, _, _)| !path.starts_with(dir_path))
+            {
+                renamed_dirs.pop();
+            }
+            for (_, original_path, new_path) in &renamed_dirs {
+                if let Result::Ok(relative_path) = restore_path.strip_prefix(original_path) {
+                    restore_path = new_path.join(relative_path);
                 }
             }
 
-            let restore_path = target_path.join(&path);
+            // Directories are only pruned once they are known to be real directories, so that
+            // symlinks kept in the target are never followed
+            let prunable = opts.delete && stream_node.node.is_dir() && fully_included;
+            if opts.delete {
+                restored_paths.insert(restore_path.clone());
+            }
 
-            if restore_path.exists() {
-                match opts.resolution {
-                    Resolution::Skip => {
-                        progress_reporter.processed_file(&path);
-                        continue;
-                    }
-                    Resolution::Overwrite => {}
+            // Files completed by an interrupted restore
+            if completed.contains(&path)
+                && stream_node.node.is_file()
+                && restore_path
+                    .symlink_metadata()
+                    .is_ok_and(|m| m.is_file() && m.len() == stream_node.node.metadata.size)
+            {
+                progress_reporter.processing_file(path.clone());
+                progress_reporter.processed_bytes(stream_node.node.metadata.size);
+                progress_reporter.processed_file(&path);
+                continue;
+            }
+
+            // Delta restore: update existing files in place. Symlinks are replaced like other
+            // items, so that we never write through them.
+            if delta
+                && stream_node.node.is_file()
+                && restore_path
+                    .symlink_metadata()
+                    .is_ok_and(|metadata| metadata.is_file())
+            {
+                ensure_journal(&mut journal, target_path, snapshot_id, opts.dry_run)?;
+                Self::delta_restore_file(
+                    repo.as_ref(),
+                    &mut plan,
+                    path,
+                    restore_path,
+                    stream_node.node,
+                    opts.dry_run,
+                    progress_reporter.as_ref(),
+                    journal.as_ref(),
+                )?;
+                continue;
+            }
+
+            // Other existing items are replaced in a delta restore
+            let resolution = if delta {
+                &Resolution::Overwrite
+            } else {
+                &opts.resolution
+            };
+            if let Result::Ok(fs_metadata) = restore_path.symlink_metadata() {
+                // Existing directories are merged with the restored ones
+                let merge_dir = fs_metadata.is_dir() && stream_node.node.is_dir();
+                let replace = match resolution {
+                    Resolution::Skip => false,
+                    Resolution::Overwrite => true,
                     Resolution::Fail => {
                         bail!("Target \'{}\' already exists", restore_path.display());
                     }
+                    Resolution::IfNewer => merge_dir || is_newer(&stream_node.node, &fs_metadata),
+                    Resolution::IfChanged => {
+                        merge_dir || has_changed(&stream_node.node, &restore_path, &fs_metadata)?
+                    }
+                    Resolution::Rename => {
+                        if !merge_dir {
+                            let renamed = renamed_path(&restore_path, snapshot_id);
+                            if stream_node.node.is_dir() {
+                                renamed_dirs.push((
+                                    path.clone(),
+                                    restore_path.clone(),
+                                    renamed.clone(),
+                                ));
+                            }
+                            restore_path = renamed;
+                            if opts.delete {
+                                restored_paths.insert(restore_path.clone());
+                            }
+                        }
+                        true
+                    }
+                };
+
+                if !replace {
+                    if prunable && is_real_dir(target_path, &restore_path) {
+                        prune_dirs.push(restore_path);
+                    }
+                    progress_reporter.processed_file(&path);
+                    continue;
                 }
             }
 
+            ensure_journal(&mut journal, target_path, snapshot_id, opts.dry_run)?;
+
+            // Existing files are truncated in place. Other items must be removed first so that
+            // we don't write through an existing symlink or fail to create the new item.
+            if !opts.dry_run
+                && let Result::Ok(fs_metadata) = restore_path.symlink_metadata()
+                && !fs_metadata.is_dir()
+                && !(fs_metadata.is_file() && stream_node.node.is_file())
+            {
+                fs::remove_file(&restore_path)
+                    .with_context(|| format!("Could not replace '{}'", restore_path.display()))?;
+            }
+
             if stream_node.node.is_dir() {
                 let path = restore_path.clone();
                 let atime = stream_node.node.metadata.accessed_time;
@@ -121,16 +316,10 @@ impl Restorer {
                 dir_stack.push((path, atime, mtime));
             }
 
-            progress_reporter.processing_file(path.clone());
-
             // Attempt to restore the node.
-            if let Err(e) = node_restorer::restore_node_to_path(
-                repo.as_ref(),
-                progress_reporter.clone(),
-                &stream_node.node,
-                &restore_path,
-                opts.dry_run,
-            ) {
+            if let Err(e) =
+                node_restorer::restore_node_to_path(&stream_node.node, &restore_path, opts.dry_run)
+            {
                 bail!(
                     "Failed to restore item \'{}\': {}",
                     restore_path.display(),
@@ -138,7 +327,47 @@ impl Restorer {
                 )
             }
 
-            progress_reporter.processed_file(&path);
+            if prunable && is_real_dir(target_path, &restore_path) {
+                prune_dirs.push(restore_path.clone());
+            }
+
+            let has_contents = stream_node
+                .node
+                .blobs
+                .as_ref()
+                .is_some_and(|blobs| !blobs.is_empty());
+            if stream_node.node.is_file() && has_contents {
+                plan.add_file(repo.as_ref(), path, restore_path, stream_node.node, None)?;
+            } else {
+                if stream_node.node.is_file()
+                    && let Some(journal) = &journal
+                {
+                    journal.record(&path)?;
+                }
+                progress_reporter.processing_file(path.clone());
+                progress_reporter.processed_file(&path);
+            }
+        }
+
+        if !plan.is_empty() {
+            plan.execute(
+                repo.clone(),
+                opts.concurrency,
+                opts.dry_run,
+                progress_reporter.clone(),
+                journal.as_ref(),
+            )?;
+        }
+
+        if opts.delete {
+            delete_extraneous(
+                target_path,
+                &prune_dirs,
+                &restored_paths,
+                exclude.as_ref(),
+                opts.strip_prefix.as_deref(),
+                opts.dry_run,
+            )?;
         }
 
         // Second pass for the directory file times
@@ -148,6 +377,239 @@ impl Restorer {
             }
         }
 
+        if let Some(journal) = journal {
+            journal.remove()?;
+        }
+
+        Ok(())
+    }
+
+    /// Restores a file over an existing one. The existing file is chunked, and only the blobs that
+    /// are not already at their offset are restored. Files with the same size, modification time
+    /// and blobs are considered unchanged, and only their metadata is restored.
+    #[allow(clippy::too_many_arguments)]
+    fn delta_restore_file(
+        repo: &Repository,
+        plan: &mut RestorePlan,
+        path: PathBuf,
+        restore_path: PathBuf,
+        node: Node,
+        dry_run: bool,
+        progress_reporter: &RestoreProgressReporter,
+        journal: Option<&RestoreJournal>,
+    ) -> Result<()> {
+        let fs_metadata = restore_path.symlink_metadata()?;
+        let existing_chunks = existing_chunks(&restore_path)
+            .with_context(|| format!("Could not read '{}'", restore_path.display()))?;
+
+        let unchanged = fs_metadata.len() == node.metadata.size
+            && node.metadata.modified_time.is_some()
+            && fs_metadata.modified().ok() == node.metadata.modified_time
+            && existing_chunks
+                .iter()
+                .map(|(_, id)| id)
+                .eq(node.blobs.as_deref().unwrap_or_default());
+        if unchanged {
+            if !dry_run {
+                node_restorer::restore_node_metadata(&node, &restore_path)?;
+            }
+            if let Some(journal) = journal {
+                journal.record(&path)?;
+            }
+            progress_reporter.processing_file(path.clone());
+            progress_reporter.processed_bytes(node.metadata.size);
+            progress_reporter.processed_file(&path);
+            return Ok(());
+        }
+
+        let existing_chunks: HashSet<(u64, ID)> = existing_chunks.into_iter().collect();
+        let planned = plan.add_file(
+            repo,
+            path.clone(),
+            restore_path.clone(),
+            node.clone(),
+            Some(&existing_chunks),
+        )?;
+        progress_reporter.processed_bytes(planned.reused_bytes);
+
+        if !dry_run {
+            OpenOptions::new()
+                .write(true)
+                .open(&restore_path)
+                .and_then(|file| file.set_len(planned.size))
+                .with_context(|| format!("Could not resize '{}'", restore_path.display()))?;
+        }
+
+        if planned.complete {
+            if !dry_run {
+                node_restorer::restore_node_metadata(&node, &restore_path)?;
+            }
+            if let Some(journal) = journal {
+                journal.record(&path)?;
+            }
+            progress_reporter.processing_file(path.clone());
+            progress_reporter.processed_file(&path);
+        }
+
         Ok(())
     }
 }
+
+/// Chunks an existing file like the archiver does. Returns the offset and ID of each chunk.
+pub(crate) fn existing_chunks(path: &Path) -> Result<Vec<(u64, ID)>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut chunks = Vec::new();
+    for chunk in new_chunker(reader) {
+        let chunk = chunk.with_context(|| "Failed to chunk file")?;
+        chunks.push((chunk.offset, ID::from_content(&chunk.data)));
+    }
+    Ok(chunks)
+}
+
+/// Returns true if the node was modified after the existing item. If either time is unknown, the
+/// node is considered newer.
+fn is_newer(node: &Node, fs_metadata: &fs::Metadata) -> bool {
+    match (node.metadata.modified_time, fs_metadata.modified().ok()) {
+        (Some(node_mtime), Some(fs_mtime)) => node_mtime > fs_mtime,
+        _ => true,
+    }
+}
+
+/// Returns true if the existing item differs from the node. Files are compared by size,
+/// modification time and contents. Symlinks are compared by target.
+fn has_changed(node: &Node, path: &Path, fs_metadata: &fs::Metadata) -> Result<bool> {
+    if node.is_file() {
+        if !fs_metadata.is_file()
+            || fs_metadata.len() != node.metadata.size
+            || node.metadata.modified_time.is_none()
+            || fs_metadata.modified().ok() != node.metadata.modified_time
+        {
+            return Ok(true);
+        }
+
+        let existing_ids: Vec<ID> = existing_chunks(path)
+            .with_context(|| format!("Could not read '{}'", path.display()))?
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect();
+        return Ok(node.blobs.as_deref().unwrap_or_default() != existing_ids.as_slice());
+    }
+
+    if node.is_symlink() {
+        if !fs_metadata.is_symlink() {
+            return Ok(true);
+        }
+        let target = fs::read_link(path)?;
+        return Ok(node
+            .symlink_info
+            .as_ref()
+            .is_none_or(|info| info.target_path != target));
+    }
+
+    Ok(node.is_dir() != fs_metadata.is_dir())
+}
+
+/// Returns the path used to restore an item alongside an existing one: `name.mapache-<id>`.
+fn renamed_path(path: &Path, snapshot_id: &ID) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".mapache-{}",
+        snapshot_id.to_short_hex(SHORT_SNAPSHOT_ID_LEN)
+    ));
+    path.with_file_name(name)
+}
+
+/// Creates the restore journal if it does not exist yet. Nothing is written in a dry run.
+fn ensure_journal(
+    journal: &mut Option<RestoreJournal>,
+    target_path: &Path,
+    snapshot_id: &ID,
+    dry_run: bool,
+) -> Result<()> {
+    if !dry_run && journal.is_none() {
+        *journal = Some(RestoreJournal::create(target_path, snapshot_id)?);
+    }
+    Ok(())
+}
+
+/// Returns true if `path` is a directory inside `target_path` that is reached without following
+/// any symlink below the target.
+fn is_real_dir(target_path: &Path, path: &Path) -> bool {
+    let Result::Ok(relative_path) = path.strip_prefix(target_path) else {
+        return false;
+    };
+
+    let mut current_path = target_path.to_path_buf();
+    for component in relative_path.components() {
+        current_path.push(component);
+        if !current_path
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.is_dir())
+        {
+            return false;
+        }
+    }
+
+    target_path.is_dir()
+}
+
+/// Deletes the items inside `prune_dirs` that were not restored. Items matching the exclude list
+/// are left untouched, and directories reached through symlinks are never pruned.
+fn delete_extraneous(
+    target_path: &Path,
+    prune_dirs: &[PathBuf],
+    restored_paths: &HashSet<PathBuf>,
+    exclude: Option<&Vec<PathBuf>>,
+    strip_prefix: Option<&Path>,
+    dry_run: bool,
+) -> Result<()> {
+    let mut num_deleted = 0;
+
+    for dir in prune_dirs {
+        if !is_real_dir(target_path, dir) {
+            continue;
+        }
+        let Result::Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if restored_paths.contains(&path) {
+                continue;
+            }
+
+            // Exclude paths are relative to the snapshot root
+            let mut snapshot_path = path.strip_prefix(target_path)?.to_path_buf();
+            if let Some(prefix) = strip_prefix {
+                snapshot_path = prefix.join(snapshot_path);
+            }
+            if !utils::filter_path(&snapshot_path, None, exclude) {
+                continue;
+            }
+
+            ui::cli::verbose_1!("Deleting {}", path.display());
+            num_deleted += 1;
+            if dry_run {
+                continue;
+            }
+
+            let res = if entry.file_type()?.is_dir() {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+            res.with_context(|| format!("Could not delete '{}'", path.display()))?;
+        }
+    }
+
+    if num_deleted > 0 {
+        ui::cli::log!(
+            "Deleted {} not present in the snapshot",
+            utils::format_count(num_deleted, "item", "items")
+        );
+    }
+
+    Ok(())
+}
@@ -14,25 +14,20 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::sync::Arc;
 use std::time::SystemTime;
 
 use {
     anyhow::{Context, Result},
-    filetime::{FileTime, set_file_times},
+    filetime::{FileTime, set_file_atime, set_file_mtime, set_file_times, set_symlink_file_times},
     std::{
         fs::{self, OpenOptions},
-        io::Write,
         path::Path,
     },
 };
 
 use crate::{
-    repository::{
-        repo::Repository,
-        tree::{Node, NodeType},
-    },
-    ui::{self, restore_progress::RestoreProgressReporter},
+    repository::tree::{Node, NodeType},
+    ui,
 };
 
 #[cfg(unix)]
@@ -43,22 +38,13 @@ use {
 
 /// Restores a node to the specified destination path.
 /// This function does not restore file times for directory nodes. This must be
-/// done in a reparate pass.
-pub(crate) fn restore_node_to_path(
-    repo: &Repository,
-    progress_reporter: Arc<RestoreProgressReporter>,
-    node: &Node,
-    dst_path: &Path,
-    dry_run: bool,
-) -> Result<()> {
+/// done in a reparate pass. Files are created empty; their contents and metadata are
+/// restored by a `RestorePlan`.
+pub(crate) fn restore_node_to_path(node: &Node, dst_path: &Path, dry_run: bool) -> Result<()> {
     match node.node_type {
         NodeType::File => {
-            let blocks = node
-                .blobs
-                .as_ref()
-                .expect("File Node must have contents (even if empty)");
-
-            let dst_file = if !dry_run {
+            // The contents are written later, by pack. See `RestorePlan`.
+            if !dry_run {
                 if let Some(parent) = dst_path.parent() {
                     fs::create_dir_all(parent).with_context(|| {
                         format!(
@@ -68,53 +54,19 @@ pub(crate) fn restore_node_to_path(
                     })?;
                 }
 
-                Some(
-                    OpenOptions::new()
-                        .create(true)
-                        .truncate(true)
-                        .write(true)
-                        .open(dst_path)
-                        .with_context(|| {
-                            format!("Could not create destination file '{}'", dst_path.display())
-                        })?,
-                )
-            } else {
-                None
-            };
-
-            for (index, blob_id) in blocks.iter().enumerate() {
-                let chunk_data = repo.load_blob(blob_id).with_context(|| {
-                    format!(
-                        "Could not load block #{} ({}) for restoring file '{}'",
-                        index + 1,
-                        blob_id,
-                        dst_path.display()
-                    )
-                })?;
-
-                let chunk_size = chunk_data.len() as u64;
+                OpenOptions::new()
+                    .create(true)
+                    .truncate(true)
+                    .write(true)
+                    .open(dst_path)
+                    .with_context(|| {
+                        format!("Could not create destination file '{}'", dst_path.display())
+                    })?;
 
-                if !dry_run {
-                    dst_file
-                        .as_ref()
-                        .expect("Destination file should exist")
-                        .write_all(&chunk_data)
-                        .with_context(|| {
-                            format!(
-                                "Could not restore block #{} ({}) to file '{}'",
-                                index + 1,
-                                blob_id,
-                                dst_path.display()
-                            )
-                        })?;
+                // Files without contents are complete
+                if node.blobs.as_ref().is_none_or(|blobs| blobs.is_empty()) {
+                    restore_node_metadata(node, dst_path)?;
                 }
-
-                progress_reporter.processed_bytes(chunk_size);
-            }
-
-            // Restore metadata after content is written
-            if !dry_run {
-                restore_node_metadata(node, dst_path)?;
             }
         }
 
@@ -142,15 +94,17 @@ pub(crate) fn restore_node_to_path(
 
             #[cfg(unix)]
             {
-                if !dry_run
-                    && let Err(e) = std::os::unix::fs::symlink(&symlink_info.target_path, dst_path)
-                {
-                    ui::cli::warning!(
-                        "Could not create symlink '{}' pointing to '{}' : {}",
-                        dst_path.display(),
-                        symlink_info.target_path.display(),
-                        e.to_string()
-                    );
+                if !dry_run {
+                    match std::os::unix::fs::symlink(&symlink_info.target_path, dst_path) {
+                        // The owner and times of the link itself, not of its target
+                        Ok(()) => restore_node_metadata(node, dst_path)?,
+                        Err(e) => ui::cli::warning!(
+                            "Could not create symlink '{}' pointing to '{}' : {}",
+                            dst_path.display(),
+                            symlink_info.target_path.display(),
+                            e.to_string()
+                        ),
+                    }
                 }
             }
             #[cfg(windows)]
@@ -195,9 +149,6 @@ pub(crate) fn restore_node_to_path(
                     }
                 }
             }
-
-            // TODO:
-            // Restoring symlink metadata is a bit special, so let's skip it for now.
         }
 
         NodeType::BlockDevice => {
@@ -257,7 +208,7 @@ pub(crate) fn restore_node_to_path(
 }
 
 /// Restores the metadata of a node to the specified destination path.
-fn restore_node_metadata(node: &Node, dst_path: &Path) -> Result<()> {
+pub(crate) fn restore_node_metadata(node: &Node, dst_path: &Path) -> Result<()> {
     // Set file times
     restore_times(
         dst_path,
@@ -282,19 +233,23 @@ fn restore_node_metadata(node: &Node, dst_path: &Path) -> Result<()> {
             }
         }
 
-        if !node.is_symlink() {
-            // Set owner (uid) and group (gid)
-            let uid = node.metadata.owner_uid;
-            let gid = node.metadata.owner_gid;
-
-            if uid.is_some() || gid.is_some() {
-                if let Err(e) = std::os::unix::fs::chown(dst_path, uid, gid) {
-                    bail!(
-                        "Could not set owner/group for '{}': {}. This operation often requires elevated privileges (e.g., root) and may not be supported for all node types (e.g. symlinks).",
-                        dst_path.display(),
-                        e.to_string()
-                    );
-                }
+        // Set owner (uid) and group (gid). lchown changes symlinks instead of their targets.
+        let uid = node.metadata.owner_uid;
+        let gid = node.metadata.owner_gid;
+
+        if uid.is_some() || gid.is_some() {
+            let res = if node.is_symlink() {
+                std::os::unix::fs::lchown(dst_path, uid, gid)
+            } else {
+                std::os::unix::fs::chown(dst_path, uid, gid)
+            };
+
+            if let Err(e) = res {
+                bail!(
+                    "Could not set owner/group for '{}': {}. This operation often requires elevated privileges (e.g., root).",
+                    dst_path.display(),
+                    e.to_string()
+                );
             }
         }
     }
@@ -302,20 +257,36 @@ fn restore_node_metadata(node: &Node, dst_path: &Path) -> Result<()> {
     Ok(())
 }
 
-/// Restores file times
+/// Restores file times. The times of symlinks are set on the link itself, not on its target.
+/// A missing time keeps its current value. In particular, the access time is not saved in
+/// snapshots, so restoring a directory after its children doesn't change it.
 pub fn restore_times(
     dst_path: &Path,
     atime: Option<&SystemTime>,
     mtime: Option<&SystemTime>,
 ) -> Result<()> {
-    if let Some(modified_time) = mtime {
-        let ft_mtime = FileTime::from(*modified_time);
-        let ft_atime = atime.map_or(ft_mtime, |atime| FileTime::from(*atime));
-
-        set_file_times(dst_path, ft_atime, ft_mtime)
-            .with_context(|| format!("Could not set file times for '{}'", dst_path.display()))?;
+    let ft_atime = atime.map(|atime| FileTime::from(*atime));
+    let ft_mtime = mtime.map(|mtime| FileTime::from(*mtime));
+    if ft_atime.is_none() && ft_mtime.is_none() {
+        return Ok(());
     }
 
+    let meta = fs::symlink_metadata(dst_path)
+        .with_context(|| format!("Could not read metadata of '{}'", dst_path.display()))?;
+
+    let res = match (ft_atime, ft_mtime) {
+        _ if meta.is_symlink() => set_symlink_file_times(
+            dst_path,
+            ft_atime.unwrap_or_else(|| FileTime::from_last_access_time(&meta)),
+            ft_mtime.unwrap_or_else(|| FileTime::from_last_modification_time(&meta)),
+        ),
+        (Some(ft_atime), Some(ft_mtime)) => set_file_times(dst_path, ft_atime, ft_mtime),
+        (None, Some(ft_mtime)) => set_file_mtime(dst_path, ft_mtime),
+        (Some(ft_atime), None) => set_file_atime(dst_path, ft_atime),
+        (None, None) => Ok(()),
+    };
+    res.with_context(|| format!("Could not set file times for '{}'", dst_path.display()))?;
+
     Ok(())
 }
 
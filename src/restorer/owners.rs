@@ -0,0 +1,276 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{path::Path, str::FromStr};
+
+use anyhow::{Error, Result, anyhow, bail};
+
+use crate::{repository::tree::Metadata, utils::host};
+
+/// How owners are assigned to restored items.
+#[derive(Debug, Clone, Default)]
+pub struct OwnerOptions {
+    /// Use the numeric IDs saved in the snapshot, ignoring the user and group names.
+    pub numeric: bool,
+    /// Don't restore owners. Items belong to the user running the restore.
+    pub no_owner: bool,
+    /// Give all items the owner and group of the target directory.
+    pub same_as_target: bool,
+    pub user_map: Vec<OwnerMapping>,
+    pub group_map: Vec<OwnerMapping>,
+}
+
+/// Maps a user or group of the snapshot to another one in this host. Both can be given as a
+/// name or as a numeric ID.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnerMapping {
+    pub from: String,
+    pub to: String,
+}
+
+impl FromStr for OwnerMapping {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((from, to)) if !from.trim().is_empty() && !to.trim().is_empty() => Ok(Self {
+                from: from.trim().to_string(),
+                to: to.trim().to_string(),
+            }),
+            _ => bail!("Invalid mapping '{}'. Use old=new", s),
+        }
+    }
+}
+
+/// Assigns the owner and group of restored items according to the `OwnerOptions`.
+pub(crate) struct OwnerMapper {
+    no_owner: bool,
+    numeric: bool,
+    /// Owner and group for all items
+    fixed: Option<(u32, u32)>,
+    users: Vec<(String, u32)>,
+    groups: Vec<(String, u32)>,
+}
+
+impl OwnerMapper {
+    pub(crate) fn new(opts: &OwnerOptions, target_path: &Path) -> Result<Self> {
+        let fixed = if opts.same_as_target {
+            target_owner(target_path)
+        } else {
+            None
+        };
+
+        let users = opts
+            .user_map
+            .iter()
+            .map(|m| Ok((m.from.clone(), resolve(&m.to, host::user_id, "user")?)))
+            .collect::<Result<_>>()?;
+        let groups = opts
+            .group_map
+            .iter()
+            .map(|m| Ok((m.from.clone(), resolve(&m.to, host::group_id, "group")?)))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            no_owner: opts.no_owner,
+            numeric: opts.numeric,
+            fixed,
+            users,
+            groups,
+        })
+    }
+
+    /// Replaces the owner and group of a node with the ones to restore.
+    pub(crate) fn apply(&self, metadata: &mut Metadata) {
+        if self.no_owner {
+            metadata.owner_uid = None;
+            metadata.owner_gid = None;
+        } else if let Some((uid, gid)) = self.fixed {
+            metadata.owner_uid = Some(uid);
+            metadata.owner_gid = Some(gid);
+        } else {
+            metadata.owner_uid = map_owner(
+                metadata.owner_uid,
+                metadata.owner_user.as_deref(),
+                &self.users,
+                self.numeric,
+                host::user_id,
+            );
+            metadata.owner_gid = map_owner(
+                metadata.owner_gid,
+                metadata.owner_group.as_deref(),
+                &self.groups,
+                self.numeric,
+                host::group_id,
+            );
+        }
+    }
+}
+
+/// Resolves a user or group of this host, given as a name or a numeric ID.
+fn resolve(owner: &str, lookup: fn(&str) -> Option<u32>, kind: &str) -> Result<u32> {
+    owner
+        .parse::<u32>()
+        .ok()
+        .or_else(|| lookup(owner))
+        .ok_or_else(|| anyhow!("Unknown {} '{}'", kind, owner))
+}
+
+/// Maps an owner of the snapshot. Explicit mappings take precedence. Otherwise, unless `numeric`
+/// is set, names known in this host are translated to their local ID. The saved ID is used
+/// as a fallback.
+fn map_owner(
+    id: Option<u32>,
+    name: Option<&str>,
+    mappings: &[(String, u32)],
+    numeric: bool,
+    lookup: fn(&str) -> Option<u32>,
+) -> Option<u32> {
+    let id_str = id.map(|id| id.to_string());
+    if let Some((_, to)) = mappings
+        .iter()
+        .find(|(from, _)| Some(from.as_str()) == name || Some(from) == id_str.as_ref())
+    {
+        return Some(*to);
+    }
+
+    if !numeric
+        && let Some(name) = name
+        && let Some(local_id) = lookup(name)
+    {
+        return Some(local_id);
+    }
+
+    id
+}
+
+/// Returns the owner and group of the target directory. If it doesn't exist yet, it will be
+/// created by the user running the restore.
+#[cfg(unix)]
+fn target_owner(target_path: &Path) -> Option<(u32, u32)> {
+    use std::os::unix::fs::MetadataExt;
+
+    match target_path.metadata() {
+        Ok(meta) => Some((meta.uid(), meta.gid())),
+        Err(_) => match host::user_ids() {
+            (Some(uid), Some(gid)) => Some((uid, gid)),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(not(unix))]
+fn target_owner(_target_path: &Path) -> Option<(u32, u32)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<u32> {
+        match name {
+            "alice" => Some(1500),
+            "bob" => Some(1600),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_parse_owner_mapping() {
+        assert_eq!(
+            OwnerMapping::from_str("alice=1000").unwrap(),
+            OwnerMapping {
+                from: "alice".to_string(),
+                to: "1000".to_string()
+            }
+        );
+        assert!(OwnerMapping::from_str("alice").is_err());
+        assert!(OwnerMapping::from_str("=bob").is_err());
+        assert!(OwnerMapping::from_str("alice=").is_err());
+    }
+
+    #[test]
+    fn test_map_owner() {
+        let mappings = vec![("carol".to_string(), 42), ("1001".to_string(), 43)];
+
+        // Names known in this host are translated
+        assert_eq!(
+            map_owner(Some(1000), Some("alice"), &[], false, lookup),
+            Some(1500)
+        );
+        // Unless the numeric IDs are requested
+        assert_eq!(
+            map_owner(Some(1000), Some("alice"), &[], true, lookup),
+            Some(1000)
+        );
+        // Unknown names and missing names fall back to the ID
+        assert_eq!(
+            map_owner(Some(1000), Some("dave"), &[], false, lookup),
+            Some(1000)
+        );
+        assert_eq!(map_owner(Some(1000), None, &[], false, lookup), Some(1000));
+
+        // Explicit mappings by name or ID take precedence
+        assert_eq!(
+            map_owner(Some(1000), Some("carol"), &mappings, true, lookup),
+            Some(42)
+        );
+        assert_eq!(
+            map_owner(Some(1001), Some("alice"), &mappings, false, lookup),
+            Some(43)
+        );
+    }
+
+    #[test]
+    fn test_owner_mapper() -> Result<()> {
+        let mut metadata = Metadata {
+            owner_uid: Some(1000),
+            owner_gid: Some(1000),
+            ..Default::default()
+        };
+
+        let opts = OwnerOptions {
+            no_owner: true,
+            ..Default::default()
+        };
+        OwnerMapper::new(&opts, Path::new("/"))?.apply(&mut metadata);
+        assert_eq!((metadata.owner_uid, metadata.owner_gid), (None, None));
+
+        let opts = OwnerOptions {
+            user_map: vec![OwnerMapping::from_str("1000=0")?],
+            ..Default::default()
+        };
+        let mut metadata = Metadata {
+            owner_uid: Some(1000),
+            owner_gid: Some(1000),
+            ..Default::default()
+        };
+        OwnerMapper::new(&opts, Path::new("/"))?.apply(&mut metadata);
+        assert_eq!(
+            (metadata.owner_uid, metadata.owner_gid),
+            (Some(0), Some(1000))
+        );
+
+        let opts = OwnerOptions {
+            group_map: vec![OwnerMapping::from_str("1000=mapache-no-such-group")?],
+            ..Default::default()
+        };
+        assert!(OwnerMapper::new(&opts, Path::new("/")).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,414 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{File, OpenOptions},
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use parking_lot::Mutex;
+use rayon::prelude::*;
+
+use crate::{
+    global::{
+        FileType, ID,
+        defaults::{RESTORE_MAX_READ_GAP, RESTORE_MAX_READ_SIZE},
+    },
+    repository::{repo::Repository, tree::Node},
+    restorer::{journal::RestoreJournal, node_restorer},
+    ui::restore_progress::RestoreProgressReporter,
+};
+
+/// A file whose contents are restored from the packs.
+struct FileToRestore {
+    /// Path relative to the restore target, used for reporting and the journal.
+    path: PathBuf,
+    restore_path: PathBuf,
+    node: Node,
+    /// Number of blob writes missing to complete the file.
+    pending_blobs: AtomicUsize,
+    started: AtomicBool,
+    /// Destination file, opened on the first write and closed after the last one.
+    handle: Mutex<Option<Arc<File>>>,
+}
+
+impl FileToRestore {
+    /// Returns the destination file, opening it if needed.
+    fn handle(&self) -> Result<Arc<File>> {
+        let mut handle = self.handle.lock();
+        if let Some(file) = handle.as_ref() {
+            return Ok(file.clone());
+        }
+
+        let file = OpenOptions::new()
+            .write(true)
+            .open(&self.restore_path)
+            .with_context(|| {
+                format!(
+                    "Could not open destination file '{}'",
+                    self.restore_path.display()
+                )
+            })?;
+        let file = Arc::new(file);
+        *handle = Some(file.clone());
+        Ok(file)
+    }
+}
+
+/// A location where a blob must be written.
+struct BlobTarget {
+    file_index: usize,
+    file_offset: u64,
+}
+
+/// A blob within a pack, and all the locations where it must be written.
+struct PackBlob {
+    id: ID,
+    offset: u32,
+    length: u32,
+    raw_length: u32,
+    targets: Vec<BlobTarget>,
+}
+
+/// The layout of a file added to a `RestorePlan`.
+pub(crate) struct PlannedFile {
+    /// Size of the restored file.
+    pub size: u64,
+    /// Bytes already in place, which are not restored.
+    pub reused_bytes: u64,
+    /// True if no blobs need to be restored.
+    pub complete: bool,
+}
+
+/// Plans the restoration of file contents by pack, so that each pack is read only once and the
+/// packs are processed in parallel.
+#[derive(Default)]
+pub(crate) struct RestorePlan {
+    files: Vec<FileToRestore>,
+    /// Pack ID -> offset in pack -> blob
+    packs: BTreeMap<ID, BTreeMap<u32, PackBlob>>,
+}
+
+impl RestorePlan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the contents of a file node to the plan. Blobs found in `existing_chunks` at the same
+    /// offset are already in place and are not restored.
+    pub fn add_file(
+        &mut self,
+        repo: &Repository,
+        path: PathBuf,
+        restore_path: PathBuf,
+        node: Node,
+        existing_chunks: Option<&HashSet<(u64, ID)>>,
+    ) -> Result<PlannedFile> {
+        let blobs = node
+            .blobs
+            .as_ref()
+            .expect("File Node must have contents (even if empty)");
+        let file_index = self.files.len();
+
+        let index = repo.index();
+        let index = index.read();
+        let mut file_offset = 0;
+        let mut reused_bytes = 0;
+        let mut pending_blobs = 0;
+        for (blob_index, blob_id) in blobs.iter().enumerate() {
+            let Some((pack_id, _blob_type, offset, length, raw_length)) = index.get(blob_id) else {
+                bail!(
+                    "Could not find block #{} ({}) for restoring file '{}' in index",
+                    blob_index + 1,
+                    blob_id,
+                    restore_path.display()
+                );
+            };
+
+            if existing_chunks
+                .is_some_and(|chunks| chunks.contains(&(file_offset, blob_id.clone())))
+            {
+                reused_bytes += raw_length as u64;
+            } else {
+                self.packs
+                    .entry(pack_id)
+                    .or_default()
+                    .entry(offset)
+                    .or_insert_with(|| PackBlob {
+                        id: blob_id.clone(),
+                        offset,
+                        length,
+                        raw_length,
+                        targets: Vec::new(),
+                    })
+                    .targets
+                    .push(BlobTarget {
+                        file_index,
+                        file_offset,
+                    });
+                pending_blobs += 1;
+            }
+            file_offset += raw_length as u64;
+        }
+
+        if pending_blobs > 0 {
+            self.files.push(FileToRestore {
+                path,
+                restore_path,
+                pending_blobs: AtomicUsize::new(pending_blobs),
+                started: AtomicBool::new(false),
+                handle: Mutex::new(None),
+                node,
+            });
+        }
+
+        Ok(PlannedFile {
+            size: file_offset,
+            reused_bytes,
+            complete: pending_blobs == 0,
+        })
+    }
+
+    /// Returns true if there are no file contents to restore.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Reads the packs in parallel and writes the blobs to the files. The metadata of each file is
+    /// restored once all its contents have been written.
+    pub fn execute(
+        self,
+        repo: Arc<Repository>,
+        concurrency: usize,
+        dry_run: bool,
+        progress_reporter: Arc<RestoreProgressReporter>,
+        journal: Option<&RestoreJournal>,
+    ) -> Result<()> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(concurrency.max(1))
+            .build()
+            .with_context(|| "Could not create the restore thread pool")?;
+
+        let files = &self.files;
+        pool.install(|| {
+            self.packs.par_iter().try_for_each(|(pack_id, blobs)| {
+                for range in read_ranges(blobs) {
+                    restore_range(
+                        repo.as_ref(),
+                        files,
+                        pack_id,
+                        &range,
+                        dry_run,
+                        progress_reporter.as_ref(),
+                        journal,
+                    )?;
+                }
+                Ok(())
+            })
+        })
+    }
+}
+
+/// Groups the blobs of a pack in contiguous ranges, so that close blobs are read at once.
+/// Small gaps between blobs are read and discarded, since that is cheaper than an extra request.
+fn read_ranges(blobs: &BTreeMap<u32, PackBlob>) -> Vec<Vec<&PackBlob>> {
+    let mut ranges: Vec<Vec<&PackBlob>> = Vec::new();
+    let mut range_start = 0;
+    let mut range_end = 0;
+
+    for blob in blobs.values() {
+        let blob_start = blob.offset as u64;
+        let blob_end = blob_start + blob.length as u64;
+
+        let fits_in_range = !ranges.is_empty()
+            && blob_start <= range_end + RESTORE_MAX_READ_GAP
+            && blob_end - range_start <= RESTORE_MAX_READ_SIZE;
+
+        if fits_in_range {
+            ranges.last_mut().unwrap().push(blob);
+            range_end = range_end.max(blob_end);
+        } else {
+            ranges.push(vec![blob]);
+            range_start = blob_start;
+            range_end = blob_end;
+        }
+    }
+
+    ranges
+}
+
+/// Reads a range of a pack, decodes its blobs and writes them to their files.
+#[allow(clippy::too_many_arguments)]
+fn restore_range(
+    repo: &Repository,
+    files: &[FileToRestore],
+    pack_id: &ID,
+    range: &[&PackBlob],
+    dry_run: bool,
+    progress_reporter: &RestoreProgressReporter,
+    journal: Option<&RestoreJournal>,
+) -> Result<()> {
+    let range_start = range.first().map_or(0, |blob| blob.offset as u64);
+    let range_end = range
+        .iter()
+        .map(|blob| blob.offset as u64 + blob.length as u64)
+        .max()
+        .unwrap_or(range_start);
+
+    let data = repo
+        .read_from_file(
+            FileType::Pack,
+            pack_id,
+            range_start,
+            range_end - range_start,
+        )
+        .with_context(|| format!("Could not read pack {pack_id}"))?;
+
+    for blob in range {
+        let start = (blob.offset as u64 - range_start) as usize;
+        let end = start + blob.length as usize;
+        let encoded = data.get(start..end).ok_or_else(|| {
+            anyhow!(
+                "Pack {} is shorter than expected while reading blob {}",
+                pack_id,
+                blob.id
+            )
+        })?;
+        let chunk_data = repo
+            .decode_blob(encoded)
+            .with_context(|| format!("Could not load blob {} from pack {}", blob.id, pack_id))?;
+        if chunk_data.len() != blob.raw_length as usize {
+            bail!(
+                "Blob {} has {} bytes, but the index expects {}",
+                blob.id,
+                chunk_data.len(),
+                blob.raw_length
+            );
+        }
+
+        for target in &blob.targets {
+            let file = &files[target.file_index];
+            if !file.started.swap(true, Ordering::AcqRel) {
+                progress_reporter.processing_file(file.path.clone());
+            }
+
+            if !dry_run {
+                file.handle()
+                    .and_then(|handle| write_at(&handle, &chunk_data, target.file_offset))
+                    .map_err(|e| {
+                        anyhow!(
+                            "Failed to restore item '{}': {}",
+                            file.restore_path.display(),
+                            e
+                        )
+                    })?;
+            }
+            progress_reporter.processed_bytes(chunk_data.len() as u64);
+
+            // The last write completes the file
+            if file.pending_blobs.fetch_sub(1, Ordering::AcqRel) == 1 {
+                file.handle.lock().take();
+                if !dry_run {
+                    node_restorer::restore_node_metadata(&file.node, &file.restore_path)?;
+                }
+                if let Some(journal) = journal {
+                    journal.record(&file.path)?;
+                }
+                progress_reporter.processed_file(&file.path);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes data at an offset of an open file.
+fn write_at(file: &File, data: &[u8], offset: u64) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileExt;
+        file.write_all_at(data, offset)?;
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::FileExt;
+        let mut written = 0;
+        while written < data.len() {
+            match file.seek_write(&data[written..], offset + written as u64)? {
+                0 => return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into()),
+                n => written += n,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack_blob(offset: u32, length: u32) -> PackBlob {
+        PackBlob {
+            id: ID::from_content(offset.to_le_bytes()),
+            offset,
+            length,
+            raw_length: length,
+            targets: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_read_ranges() {
+        let gap = RESTORE_MAX_READ_GAP as u32;
+        let max = RESTORE_MAX_READ_SIZE as u32;
+
+        let blobs: BTreeMap<u32, PackBlob> = [
+            pack_blob(0, 100),
+            pack_blob(100, 100),
+            // Small gap, same range
+            pack_blob(200 + gap, 100),
+            // Large gap, new range
+            pack_blob(400 + 2 * gap, 100),
+            // Too large to share the range
+            pack_blob(500 + 2 * gap, max),
+        ]
+        .into_iter()
+        .map(|blob| (blob.offset, blob))
+        .collect();
+
+        let ranges: Vec<Vec<u32>> = read_ranges(&blobs)
+            .iter()
+            .map(|range| range.iter().map(|blob| blob.offset).collect())
+            .collect();
+
+        assert_eq!(
+            ranges,
+            vec![
+                vec![0, 100, 200 + gap],
+                vec![400 + 2 * gap],
+                vec![500 + 2 * gap]
+            ]
+        );
+    }
+}
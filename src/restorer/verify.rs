@@ -0,0 +1,223 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Result, bail};
+use colored::Colorize;
+
+use crate::{
+    repository::{
+        repo::Repository,
+        snapshot::Snapshot,
+        streamers::{
+            ExcludeOptions, FSNodeStreamer, NodeDiff, NodeDiffStreamer, SerializedNodeStreamer,
+        },
+        tree::Node,
+    },
+    restorer::{
+        PrefixStripper, Restorer, existing_chunks,
+        journal::RestoreJournal,
+        owners::{OwnerMapper, OwnerOptions},
+    },
+    ui, utils,
+};
+
+/// Result of comparing a restore target with a snapshot.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TargetCheck {
+    /// Items that match the snapshot
+    pub matching: u64,
+    /// Items in the snapshot that are not in the target
+    pub missing: u64,
+    /// Items in the target that are not in the snapshot
+    pub extra: u64,
+    /// Items whose type, contents or metadata differ
+    pub differing: u64,
+}
+
+impl TargetCheck {
+    /// Returns true if the target matches the snapshot.
+    pub fn is_ok(&self) -> bool {
+        self.missing == 0 && self.extra == 0 && self.differing == 0
+    }
+}
+
+impl Restorer {
+    /// Compares a restore target with a snapshot without modifying it. Every file is read
+    /// and chunked again to compare its contents. Each difference is reported.
+    pub fn verify(
+        repo: Arc<Repository>,
+        snapshot: &Snapshot,
+        target_path: &Path,
+        include: Option<Vec<PathBuf>>,
+        exclude: Option<Vec<PathBuf>>,
+        strip_prefix: Option<PathBuf>,
+        owners: &OwnerOptions,
+    ) -> Result<TargetCheck> {
+        if !target_path.is_dir() {
+            bail!("Target '{}' is not a directory", target_path.display());
+        }
+
+        // Both streams emit paths relative to the target
+        let prefix_stripper = PrefixStripper::new(strip_prefix.clone());
+        let owner_mapper = OwnerMapper::new(owners, target_path)?;
+        let snapshot_streamer = SerializedNodeStreamer::new(
+            repo.clone(),
+            Some(snapshot.tree.clone()),
+            PathBuf::new(),
+            include.clone(),
+            exclude.clone(),
+        )?
+        .filter_map(|node_res| {
+            node_res
+                .and_then(|(path, mut stream_node)| {
+                    owner_mapper.apply(&mut stream_node.node.metadata);
+                    Ok(prefix_stripper.strip(path)?.map(|path| (path, stream_node)))
+                })
+                .transpose()
+        });
+
+        let fs_streamer = FSNodeStreamer::from_paths(
+            vec![target_path.to_path_buf()],
+            vec![RestoreJournal::path(target_path)],
+            ExcludeOptions::default(),
+        )?
+        .filter_map(|node_res| {
+            node_res
+                .and_then(|(path, stream_node)| {
+                    let path = path.strip_prefix(target_path)?.to_path_buf();
+                    if path.as_os_str().is_empty() {
+                        return Ok(None);
+                    }
+
+                    // Apply the filters to the path in the snapshot
+                    let snapshot_path = match &strip_prefix {
+                        Some(prefix) => prefix.join(&path),
+                        None => path.clone(),
+                    };
+                    if !utils::filter_path(&snapshot_path, include.as_ref(), exclude.as_ref()) {
+                        return Ok(None);
+                    }
+
+                    Ok(Some((path, stream_node)))
+                })
+                .transpose()
+        });
+
+        let mut check = TargetCheck::default();
+        for diff_res in NodeDiffStreamer::new(snapshot_streamer, fs_streamer) {
+            let (path, snapshot_node, fs_node, diff_type) = diff_res?;
+            let path_str = path.display();
+
+            match (diff_type, snapshot_node, fs_node) {
+                (NodeDiff::Deleted, _, _) => {
+                    ui::cli::log!("{}  {} (missing)", "-".bold().red(), path_str);
+                    check.missing += 1;
+                }
+                (NodeDiff::New, _, _) => {
+                    ui::cli::log!("{}  {} (not in snapshot)", "+".bold().green(), path_str);
+                    check.extra += 1;
+                }
+                (_, Some(snapshot_node), Some(fs_node)) => {
+                    let restore_path = target_path.join(&path);
+                    let differences =
+                        compare_nodes(&snapshot_node.node, &fs_node.node, &restore_path)?;
+                    if differences.is_empty() {
+                        check.matching += 1;
+                        continue;
+                    }
+
+                    let symbol = if differences.contains(&"type") {
+                        "T".bold().purple()
+                    } else if differences.contains(&"contents") {
+                        "M".bold().yellow()
+                    } else {
+                        "m".bold().cyan()
+                    };
+                    ui::cli::log!("{}  {} ({})", symbol, path_str, differences.join(", "));
+                    check.differing += 1;
+                }
+                _ => bail!("Inconsistent comparison for '{}'", path_str),
+            }
+        }
+
+        Ok(check)
+    }
+}
+
+/// Returns the properties of an item in the target that differ from the node in the snapshot.
+fn compare_nodes(
+    snapshot_node: &Node,
+    fs_node: &Node,
+    restore_path: &Path,
+) -> Result<Vec<&'static str>> {
+    if snapshot_node.node_type != fs_node.node_type {
+        return Ok(vec!["type"]);
+    }
+
+    let mut differences = Vec::new();
+    let snapshot_meta = &snapshot_node.metadata;
+    let fs_meta = &fs_node.metadata;
+
+    if snapshot_node.is_file() {
+        if snapshot_meta.size != fs_meta.size {
+            differences.push("size");
+        } else {
+            let ids: Vec<_> = existing_chunks(restore_path)?
+                .into_iter()
+                .map(|(_, id)| id)
+                .collect();
+            if snapshot_node.blobs.as_deref().unwrap_or_default() != ids.as_slice() {
+                differences.push("contents");
+            }
+        }
+    }
+
+    if snapshot_node.is_symlink()
+        && snapshot_node.symlink_info.as_ref().map(|i| &i.target_path)
+            != fs_node.symlink_info.as_ref().map(|i| &i.target_path)
+    {
+        differences.push("target");
+    }
+
+    if snapshot_meta.modified_time.is_some() && snapshot_meta.modified_time != fs_meta.modified_time
+    {
+        differences.push("mtime");
+    }
+
+    if !snapshot_node.is_symlink()
+        && let (Some(mode), Some(fs_mode)) = (snapshot_meta.mode, fs_meta.mode)
+        && mode & 0o7777 != fs_mode & 0o7777
+    {
+        differences.push("mode");
+    }
+
+    let owner_differs = snapshot_meta
+        .owner_uid
+        .is_some_and(|uid| Some(uid) != fs_meta.owner_uid)
+        || snapshot_meta
+            .owner_gid
+            .is_some_and(|gid| Some(gid) != fs_meta.owner_gid);
+    if owner_differs {
+        differences.push("owner");
+    }
+
+    Ok(differences)
+}
@@ -48,6 +48,19 @@ macro_rules! log_with_level {
     };
 }
 
+/// Logs to stderr, so that the message does not mix with data written to stdout.
+#[macro_export]
+macro_rules! log_stderr {
+    ($($arg:tt)*) => {
+        {
+            let current_verbosity = $crate::global::global_opts().as_ref().unwrap().verbosity;
+            if current_verbosity >= 1 {
+                eprintln!($($arg)*)
+            }
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! log_always {
     ($($arg:tt)*) => {
@@ -103,4 +116,4 @@ macro_rules! verbose_2 {
     };
 }
 
-pub use {error, log, log_always, log_with_level, verbose_1, verbose_2, warning};
+pub use {error, log, log_always, log_stderr, log_with_level, verbose_1, verbose_2, warning};
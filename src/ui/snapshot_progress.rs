@@ -42,8 +42,11 @@ pub struct SnapshotProgressReporter {
     // Processed items
     processed_items_count: Arc<AtomicU64>, // Number of files processed (written or not)
     processed_bytes: Arc<AtomicU64>,       // Bytes processed (only data)
+    skipped_items_count: Arc<AtomicU64>,   // Items skipped by the exclusion rules
+    inconsistent_files_count: Arc<AtomicU64>, // Files that changed while being read
     raw_bytes: Arc<AtomicU64>,             // Bytes 'written' before encoding
     encoded_bytes: Arc<AtomicU64>,         // Bytes written after encoding
+    uncompressed_bytes: Arc<AtomicU64>,    // Bytes written without compression
 
     // Metadata
     meta_raw_bytes: Arc<AtomicU64>, // Metadata bytes 'written' before encoding
@@ -133,8 +136,11 @@ impl SnapshotProgressReporter {
         Self {
             processed_items_count: processed_items_count_arc,
             processed_bytes: processed_bytes_arc,
+            skipped_items_count: Arc::new(AtomicU64::new(0)),
+            inconsistent_files_count: Arc::new(AtomicU64::new(0)),
             raw_bytes: raw_bytes_arc,
             encoded_bytes: encoded_bytes_arc,
+            uncompressed_bytes: Arc::new(AtomicU64::new(0)),
             meta_raw_bytes: meta_raw_bytes_arc,
             meta_encoded_bytes: meta_encoded_bytes_arc,
             diff_counts: RwLock::new(DiffCounts::default()),
@@ -196,12 +202,28 @@ impl SnapshotProgressReporter {
         self.progress_bar.inc(bytes);
     }
 
+    #[inline]
+    pub fn skipped_items(&self, count: u64) {
+        self.skipped_items_count.fetch_add(count, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn inconsistent_file(&self) {
+        self.inconsistent_files_count
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
     #[inline]
     pub fn written_data_bytes(&self, raw: u64, encoded: u64) {
         self.raw_bytes.fetch_add(raw, Ordering::Relaxed);
         self.encoded_bytes.fetch_add(encoded, Ordering::Relaxed);
     }
 
+    #[inline]
+    pub fn uncompressed_data_bytes(&self, raw: u64) {
+        self.uncompressed_bytes.fetch_add(raw, Ordering::Relaxed);
+    }
+
     #[inline]
     pub fn written_meta_bytes(&self, raw: u64, encoded: u64) {
         self.meta_raw_bytes.fetch_add(raw, Ordering::Relaxed);
@@ -263,8 +285,11 @@ impl SnapshotProgressReporter {
         SnapshotSummary {
             processed_items_count: self.processed_items_count.load(Ordering::SeqCst),
             processed_bytes: self.processed_bytes.load(Ordering::SeqCst),
+            skipped_items_count: self.skipped_items_count.load(Ordering::SeqCst),
+            inconsistent_files_count: self.inconsistent_files_count.load(Ordering::SeqCst),
             raw_bytes: self.raw_bytes.load(Ordering::SeqCst),
             encoded_bytes: self.encoded_bytes.load(Ordering::SeqCst),
+            uncompressed_bytes: self.uncompressed_bytes.load(Ordering::SeqCst),
             meta_raw_bytes: self.meta_raw_bytes.load(Ordering::SeqCst),
             meta_encoded_bytes: self.meta_encoded_bytes.load(Ordering::SeqCst),
             total_raw_bytes,
@@ -0,0 +1,266 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Information about the machine and user creating a snapshot.
+
+use std::{collections::HashMap, sync::LazyLock};
+
+use parking_lot::Mutex;
+
+/// Returns the name of this host, or an empty string if it cannot be determined.
+pub fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        let ret = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+        if ret == 0 {
+            let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+            let name = String::from_utf8_lossy(&buf[..len]).trim().to_string();
+            if !name.is_empty() {
+                return name;
+            }
+        }
+    }
+
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .unwrap_or_default()
+}
+
+/// Returns the name of the user running the program, or an empty string if it cannot be
+/// determined.
+pub fn username() -> String {
+    #[cfg(unix)]
+    if let Some(name) = user_name(unsafe { libc::getuid() }) {
+        return name;
+    }
+
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default()
+}
+
+/// Returns the real user and group IDs of the process. Not available on non-Unix platforms.
+pub fn user_ids() -> (Option<u32>, Option<u32>) {
+    #[cfg(unix)]
+    {
+        unsafe { (Some(libc::getuid()), Some(libc::getgid())) }
+    }
+
+    #[cfg(not(unix))]
+    {
+        (None, None)
+    }
+}
+
+/// Returns the version of this program.
+pub fn program_version() -> String {
+    format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
+type Cache<K, V> = LazyLock<Mutex<HashMap<K, Option<V>>>>;
+
+static USER_NAMES: Cache<u32, String> = LazyLock::new(Default::default);
+static GROUP_NAMES: Cache<u32, String> = LazyLock::new(Default::default);
+static USER_IDS: Cache<String, u32> = LazyLock::new(Default::default);
+static GROUP_IDS: Cache<String, u32> = LazyLock::new(Default::default);
+
+/// Returns the name of a user ID in this host. Lookups are cached.
+pub fn user_name(uid: u32) -> Option<String> {
+    USER_NAMES
+        .lock()
+        .entry(uid)
+        .or_insert_with(|| lookup::user_name(uid))
+        .clone()
+}
+
+/// Returns the name of a group ID in this host. Lookups are cached.
+pub fn group_name(gid: u32) -> Option<String> {
+    GROUP_NAMES
+        .lock()
+        .entry(gid)
+        .or_insert_with(|| lookup::group_name(gid))
+        .clone()
+}
+
+/// Returns the ID of a user name in this host. Lookups are cached.
+pub fn user_id(name: &str) -> Option<u32> {
+    *USER_IDS
+        .lock()
+        .entry(name.to_string())
+        .or_insert_with(|| lookup::user_id(name))
+}
+
+/// Returns the ID of a group name in this host. Lookups are cached.
+pub fn group_id(name: &str) -> Option<u32> {
+    *GROUP_IDS
+        .lock()
+        .entry(name.to_string())
+        .or_insert_with(|| lookup::group_id(name))
+}
+
+#[cfg(unix)]
+mod lookup {
+    use std::ffi::{CStr, CString};
+
+    /// Calls a reentrant `getpw*_r` or `getgr*_r` function, growing the buffer while it is
+    /// too small.
+    fn with_buffer(mut call: impl FnMut(&mut [libc::c_char]) -> libc::c_int) {
+        const MAX_BUFFER_LEN: usize = 1024 * 1024;
+        let mut len = 1024;
+        loop {
+            let mut buf = vec![0; len];
+            match call(&mut buf) {
+                libc::ERANGE if len < MAX_BUFFER_LEN => len *= 2,
+                _ => return,
+            }
+        }
+    }
+
+    pub(super) fn user_name(uid: u32) -> Option<String> {
+        let mut name = None;
+        with_buffer(|buf| {
+            let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+            let mut result = std::ptr::null_mut();
+            let ret = unsafe {
+                libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result)
+            };
+            if ret == 0 && !result.is_null() {
+                name = Some(
+                    unsafe { CStr::from_ptr(pwd.pw_name) }
+                        .to_string_lossy()
+                        .into_owned(),
+                );
+            }
+            ret
+        });
+        name
+    }
+
+    pub(super) fn group_name(gid: u32) -> Option<String> {
+        let mut name = None;
+        with_buffer(|buf| {
+            let mut grp: libc::group = unsafe { std::mem::zeroed() };
+            let mut result = std::ptr::null_mut();
+            let ret = unsafe {
+                libc::getgrgid_r(gid, &mut grp, buf.as_mut_ptr(), buf.len(), &mut result)
+            };
+            if ret == 0 && !result.is_null() {
+                name = Some(
+                    unsafe { CStr::from_ptr(grp.gr_name) }
+                        .to_string_lossy()
+                        .into_owned(),
+                );
+            }
+            ret
+        });
+        name
+    }
+
+    pub(super) fn user_id(name: &str) -> Option<u32> {
+        let c_name = CString::new(name).ok()?;
+        let mut uid = None;
+        with_buffer(|buf| {
+            let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+            let mut result = std::ptr::null_mut();
+            let ret = unsafe {
+                libc::getpwnam_r(
+                    c_name.as_ptr(),
+                    &mut pwd,
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                    &mut result,
+                )
+            };
+            if ret == 0 && !result.is_null() {
+                uid = Some(pwd.pw_uid);
+            }
+            ret
+        });
+        uid
+    }
+
+    pub(super) fn group_id(name: &str) -> Option<u32> {
+        let c_name = CString::new(name).ok()?;
+        let mut gid = None;
+        with_buffer(|buf| {
+            let mut grp: libc::group = unsafe { std::mem::zeroed() };
+            let mut result = std::ptr::null_mut();
+            let ret = unsafe {
+                libc::getgrnam_r(
+                    c_name.as_ptr(),
+                    &mut grp,
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                    &mut result,
+                )
+            };
+            if ret == 0 && !result.is_null() {
+                gid = Some(grp.gr_gid);
+            }
+            ret
+        });
+        gid
+    }
+}
+
+#[cfg(not(unix))]
+mod lookup {
+    pub(super) fn user_name(_uid: u32) -> Option<String> {
+        None
+    }
+
+    pub(super) fn group_name(_gid: u32) -> Option<String> {
+        None
+    }
+
+    pub(super) fn user_id(_name: &str) -> Option<u32> {
+        None
+    }
+
+    pub(super) fn group_id(_name: &str) -> Option<u32> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_program_version() {
+        assert!(program_version().starts_with("mapache "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_owner_names() {
+        // root is the only user and group that exists everywhere
+        assert_eq!(user_name(0).as_deref(), Some("root"));
+        assert_eq!(user_id("root"), Some(0));
+        let root_group = group_name(0).expect("Group 0 should exist");
+        assert_eq!(group_id(&root_group), Some(0));
+        assert_eq!(user_id("mapache-no-such-user"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_user_ids() {
+        let (uid, gid) = user_ids();
+        assert_eq!(uid, Some(unsafe { libc::getuid() }));
+        assert_eq!(gid, Some(unsafe { libc::getgid() }));
+    }
+}
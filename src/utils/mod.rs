@@ -14,7 +14,10 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+pub mod host;
 pub mod indexset;
+pub mod priority;
+pub mod rate_limit;
 pub mod url;
 
 use std::{
@@ -25,7 +28,7 @@ use std::{
 
 use anyhow::{Context, Result, anyhow};
 use blake3::Hasher;
-use chrono::{DateTime, Duration, Local};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime};
 
 use crate::global::Hash256;
 
@@ -372,6 +375,75 @@ pub fn parse_duration_string(s: &str) -> Result<Duration> {
     Ok(total_duration)
 }
 
+/// Parses a size string (e.g., "512", "100K", "20MiB", "1.5G") into a number of bytes.
+///
+/// Suffixes are case-insensitive. Single letter suffixes and the `iB` forms use binary
+/// prefixes (K = KiB), while the `B` forms use decimal prefixes (KB = 1000 bytes).
+pub fn parse_size_string(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split_idx = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (num_str, unit) = s.split_at(split_idx);
+
+    if num_str.is_empty() {
+        return Err(anyhow!("Invalid size format: missing number in \"{}\"", s));
+    }
+
+    let num = num_str
+        .parse::<f64>()
+        .with_context(|| format!("Failed to parse number in \"{s}\""))?;
+
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => size::KiB,
+        "m" | "mib" => size::MiB,
+        "g" | "gib" => size::GiB,
+        "t" | "tib" => size::TiB,
+        "kb" => size::KB,
+        "mb" => size::MB,
+        "gb" => size::GB,
+        "tb" => size::TB,
+        _ => return Err(anyhow!("Invalid size unit: '{}' in \"{}\"", unit, s)),
+    };
+
+    Ok((num * multiplier as f64) as u64)
+}
+
+/// Parses a point in time, either as a local date ("2025-05-25"), a local date and time
+/// ("2025-05-25 13:45:00" or "2025-05-25T13:45:00"), or a duration before now ("2w", see
+/// [`parse_duration_string`]). Dates without time refer to the start of the day.
+pub fn parse_time_string(s: &str) -> Result<DateTime<Local>> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err(anyhow!("Invalid time format: empty string"));
+    }
+
+    let naive = if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        Some(date.and_time(NaiveTime::MIN))
+    } else {
+        ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+    };
+
+    match naive {
+        Some(naive) => naive
+            .and_local_timezone(Local)
+            .earliest()
+            .ok_or_else(|| anyhow!("Invalid local time \"{}\"", s)),
+        None => {
+            let duration = parse_duration_string(s).map_err(|_| {
+                anyhow!(
+                    "Invalid time \"{}\": expected a date (YYYY-MM-DD[ HH:MM:SS]) or a duration",
+                    s
+                )
+            })?;
+            Ok(Local::now() - duration)
+        }
+    }
+}
+
 // --- Permissions Utilities ---
 
 /// Converts a Unix file mode (as `u32`) into a human-readable permission string
@@ -692,6 +764,64 @@ mod tests {
         assert!(parse_duration_string("1d 2h").is_err()); // spaces are not supported
     }
 
+    #[test]
+    fn test_parse_size_string() {
+        assert_eq!(parse_size_string("0").unwrap(), 0);
+        assert_eq!(parse_size_string("512").unwrap(), 512);
+        assert_eq!(parse_size_string("512B").unwrap(), 512);
+        assert_eq!(parse_size_string("1K").unwrap(), size::KiB);
+        assert_eq!(parse_size_string("1KiB").unwrap(), size::KiB);
+        assert_eq!(parse_size_string("1KB").unwrap(), size::KB);
+        assert_eq!(parse_size_string("20m").unwrap(), 20 * size::MiB);
+        assert_eq!(parse_size_string("20MB").unwrap(), 20 * size::MB);
+        assert_eq!(parse_size_string("1.5G").unwrap(), 3 * size::GiB / 2);
+        assert_eq!(parse_size_string("2TiB").unwrap(), 2 * size::TiB);
+
+        // Test invalid formats
+        assert!(parse_size_string("").is_err());
+        assert!(parse_size_string("K").is_err());
+        assert!(parse_size_string("1X").is_err());
+        assert!(parse_size_string("1.2.3M").is_err());
+    }
+
+    #[test]
+    fn test_parse_time_string() {
+        let date = parse_time_string("2025-05-25").unwrap();
+        assert_eq!(
+            date.naive_local(),
+            NaiveDate::from_ymd_opt(2025, 5, 25)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        );
+
+        let expected = NaiveDate::from_ymd_opt(2025, 5, 25)
+            .unwrap()
+            .and_hms_opt(13, 45, 10)
+            .unwrap();
+        assert_eq!(
+            parse_time_string("2025-05-25 13:45:10")
+                .unwrap()
+                .naive_local(),
+            expected
+        );
+        assert_eq!(
+            parse_time_string("2025-05-25T13:45:10")
+                .unwrap()
+                .naive_local(),
+            expected
+        );
+
+        let before = Local::now() - Duration::days(2);
+        let two_days_ago = parse_time_string("2d").unwrap();
+        assert!(two_days_ago >= before && two_days_ago <= Local::now() - Duration::days(2));
+
+        // Test invalid formats
+        assert!(parse_time_string("").is_err());
+        assert!(parse_time_string("2025-13-01").is_err());
+        assert!(parse_time_string("yesterday").is_err());
+    }
+
     #[test]
     fn test_filter_path() {
         let path1 = PathBuf::from("/a/b/c");
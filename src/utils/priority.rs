@@ -0,0 +1,128 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use anyhow::Result;
+#[cfg(not(target_os = "linux"))]
+use anyhow::bail;
+use clap::ValueEnum;
+
+/// I/O scheduling class, as used by `ionice`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum IoniceClass {
+    /// Best-effort scheduling, at the lowest priority level of the class
+    BestEffort,
+    /// Only get disk time when no other program needs it
+    Idle,
+}
+
+/// CPU and I/O priority of a thread.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadPriority {
+    /// Niceness, from -20 (highest priority) to 19 (lowest priority)
+    pub nice: Option<i32>,
+    /// I/O scheduling class
+    pub ionice_class: Option<IoniceClass>,
+}
+
+impl ThreadPriority {
+    /// Returns true if the priority does not change anything.
+    pub fn is_default(&self) -> bool {
+        self.nice.is_none() && self.ionice_class.is_none()
+    }
+
+    /// Applies the priority to the calling thread.
+    pub fn apply(&self) -> Result<()> {
+        if let Some(nice) = self.nice {
+            set_thread_nice(nice)?;
+        }
+        if let Some(class) = self.ionice_class {
+            set_thread_ionice(class)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the priority can be applied, by applying it to a short-lived thread.
+    pub fn validate(&self) -> Result<()> {
+        let priority = *self;
+        std::thread::spawn(move || priority.apply())
+            .join()
+            .expect("Priority thread panicked")
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_thread_nice(nice: i32) -> Result<()> {
+    use anyhow::Context;
+
+    // On Linux, the priority of a thread ID only affects that thread.
+    let tid = unsafe { libc::gettid() };
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, nice) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Could not set the nice value to {nice}"));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_thread_ionice(class: IoniceClass) -> Result<()> {
+    use anyhow::Context;
+
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+    const IOPRIO_BE_LOWEST_LEVEL: libc::c_int = 7;
+
+    let ioprio = match class {
+        IoniceClass::BestEffort => (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | IOPRIO_BE_LOWEST_LEVEL,
+        IoniceClass::Idle => IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+    };
+
+    // A `who` of 0 refers to the calling thread.
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Could not set the I/O scheduling class to {class:?}"));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_thread_nice(_nice: i32) -> Result<()> {
+    bail!("Setting the nice value is only supported on Linux")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_thread_ionice(_class: IoniceClass) -> Result<()> {
+    bail!("Setting the I/O scheduling class is only supported on Linux")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_lower_thread_priority() -> Result<()> {
+        let priority = ThreadPriority {
+            nice: Some(19),
+            ionice_class: Some(IoniceClass::Idle),
+        };
+        assert!(!priority.is_default());
+        priority.validate()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    io::Read,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+/// A token bucket rate limiter, shared between threads.
+///
+/// The bucket holds up to one second worth of tokens (bytes). Consuming more tokens than
+/// available puts the bucket in debt, and the caller sleeps until the debt is paid off, so a
+/// single large transfer is also limited.
+pub struct RateLimiter {
+    bytes_per_second: u64,
+    state: Mutex<(f64, Instant)>, // (available tokens, last refill)
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_second: u64) -> Self {
+        assert!(bytes_per_second > 0);
+        Self {
+            bytes_per_second,
+            state: Mutex::new((bytes_per_second as f64, Instant::now())),
+        }
+    }
+
+    /// Consumes `bytes` tokens, blocking until the rate allows it.
+    pub fn consume(&self, bytes: u64) {
+        let wait = {
+            let mut state = self.state.lock();
+            let (tokens, last_refill) = &mut *state;
+
+            let now = Instant::now();
+            let capacity = self.bytes_per_second as f64;
+            let refill = now.duration_since(*last_refill).as_secs_f64() * capacity;
+            *tokens = (*tokens + refill).min(capacity) - bytes as f64;
+            *last_refill = now;
+
+            if *tokens < 0.0 {
+                Duration::from_secs_f64(-*tokens / capacity)
+            } else {
+                Duration::ZERO
+            }
+        };
+
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// A reader that limits how fast the inner reader is read.
+pub struct RateLimitedReader<R: Read> {
+    inner: R,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<R: Read> RateLimitedReader<R> {
+    pub fn new(inner: R, limiter: Arc<RateLimiter>) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+impl<R: Read> Read for RateLimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read_bytes = self.inner.read(buf)?;
+        self.limiter.consume(read_bytes as u64);
+        Ok(read_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(1000);
+
+        // The first second worth of bytes is available immediately
+        let start = Instant::now();
+        limiter.consume(1000);
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        // The next bytes must wait for the bucket to refill
+        limiter.consume(250);
+        assert!(start.elapsed() >= Duration::from_millis(240));
+    }
+
+    #[test]
+    fn test_rate_limited_reader() -> std::io::Result<()> {
+        let data = vec![7u8; 3000];
+        let limiter = Arc::new(RateLimiter::new(2000));
+        let mut reader = RateLimitedReader::new(data.as_slice(), limiter);
+
+        let start = Instant::now();
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+
+        assert_eq!(read_data, data);
+        assert!(start.elapsed() >= Duration::from_millis(490));
+
+        Ok(())
+    }
+}
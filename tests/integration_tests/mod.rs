@@ -14,14 +14,31 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use anyhow::{Context, Result};
 
-use mapache::{backend::localfs::LocalFS, repository::repo::Repository};
+use mapache::{
+    backend::localfs::LocalFS,
+    commands::{GlobalArgs, UseSnapshot, cmd_restore, cmd_snapshot},
+    global::defaults::DEFAULT_DEFAULT_PACK_SIZE_MIB,
+    repository::{
+        repo::Repository,
+        storage::{Compression, CompressionMode},
+        tree::ChangeDetection,
+    },
+    restorer::Resolution,
+};
 
 mod test_cmd_amend;
+mod test_cmd_cat;
 mod test_cmd_clean;
+mod test_cmd_diff;
+mod test_cmd_dump;
+mod test_cmd_find;
 mod test_cmd_init;
 mod test_cmd_restore;
 mod test_cmd_snapshot;
@@ -30,6 +47,81 @@ const BACKUP_DATA_PATH: &str = "backup_data.tar.xz";
 
 fn init_repo(password: &str, repo_path: PathBuf) -> Result<()> {
     let backend = Arc::new(LocalFS::new(repo_path));
-    Repository::init(Some(password.to_owned()), None, backend)
-        .with_context(|| "Failed to init repo")
+    Repository::init(
+        Some(password.to_owned()),
+        None,
+        backend,
+        Compression::default(),
+        CompressionMode::Auto,
+    )
+    .with_context(|| "Failed to init repo")
+}
+
+fn default_global_args(repo_path: &Path, password_path: PathBuf) -> GlobalArgs {
+    GlobalArgs {
+        repo: repo_path.to_string_lossy().to_string(),
+        password_file: Some(password_path),
+        key: None,
+        quiet: true,
+        verbosity: None,
+        ssh_pubkey: None,
+        ssh_privatekey: None,
+        limit_upload: None,
+        limit_download: None,
+        pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
+    }
+}
+
+fn default_snapshot_args(paths: Vec<PathBuf>) -> cmd_snapshot::CmdArgs {
+    cmd_snapshot::CmdArgs {
+        paths,
+        files_from: Vec::new(),
+        files_from_verbatim: Vec::new(),
+        files_from_raw: Vec::new(),
+        as_root: false,
+        exclude: None,
+        exclude_caches: false,
+        exclude_if_present: Vec::new(),
+        one_file_system: false,
+        exclude_larger_than: None,
+        tags_str: String::new(),
+        description: None,
+        rescan: false,
+        parent: UseSnapshot::Latest,
+        checkpoint_interval: chrono::Duration::zero(),
+        changed_file_retries: 2,
+        change_detection: ChangeDetection::Default,
+        compression: None,
+        limit_read: None,
+        nice: None,
+        ionice_class: None,
+        max_cpu_threads: None,
+        host: None,
+        read_concurrency: 2,
+        write_concurrency: 5,
+        dry_run: false,
+    }
+}
+
+fn default_restore_args(target: PathBuf) -> cmd_restore::CmdArgs {
+    cmd_restore::CmdArgs {
+        target,
+        snapshot: UseSnapshot::Latest,
+        dry_run: false,
+        include: None,
+        exclude: None,
+        strip_prefix: false,
+        resolution: Resolution::Fail,
+        delta: false,
+        delete: false,
+        resume: false,
+        numeric_owner: false,
+        no_owner: false,
+        map_user: Vec::new(),
+        map_group: Vec::new(),
+        same_owner_as_target: false,
+        verify_only: false,
+        restore_concurrency: 4,
+        no_verify: false,
+    }
 }
@@ -27,6 +27,7 @@ mod tests {
         repository::{
             repo::{RepoConfig, Repository},
             snapshot::SnapshotStreamer,
+            tree::ChangeDetection,
         },
     };
 
@@ -60,6 +61,8 @@ mod tests {
             verbosity: None,
             ssh_pubkey: None,
             ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
             pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
         };
         set_global_opts_with_args(&global);
@@ -75,12 +78,28 @@ mod tests {
                 backup_data_tmp_path.join("2"),
                 backup_data_tmp_path.join("file.txt"),
             ],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
             as_root: false,
             exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
             tags_str: String::new(),
             description: None,
             rescan: false,
             parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
             read_concurrency: 2,
             write_concurrency: 5,
             dry_run: false,
@@ -116,6 +135,16 @@ mod tests {
             exclude: None,
             strip_prefix: false,
             resolution: mapache::restorer::Resolution::Skip,
+            delta: false,
+            delete: false,
+            resume: false,
+            numeric_owner: false,
+            no_owner: false,
+            map_user: Vec::new(),
+            map_group: Vec::new(),
+            same_owner_as_target: false,
+            verify_only: false,
+            restore_concurrency: 4,
             no_verify: false,
         };
         commands::cmd_restore::run(&global, &restore_args)
@@ -187,6 +216,8 @@ mod tests {
             verbosity: None,
             ssh_pubkey: None,
             ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
             pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
         };
         set_global_opts_with_args(&global);
@@ -203,12 +234,28 @@ mod tests {
         // Run snapshot twice
         let snapshot_args = cmd_snapshot::CmdArgs {
             paths: Vec::new(),
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
             as_root: false,
             exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
             tags_str: "tag0,tag1".to_string(),
             description: Some(String::from("This snapshot will be amended")),
             rescan: false,
             parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
             read_concurrency: 2,
             write_concurrency: 5,
             dry_run: false,
@@ -0,0 +1,160 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+mod tests {
+    use std::{path::Path, str::FromStr, sync::Arc};
+
+    use anyhow::{Context, Result};
+    use mapache::{
+        backend::localfs::LocalFS,
+        commands::{
+            self, GlobalArgs, UseSnapshot,
+            cmd_cat::{self, Object},
+            cmd_snapshot,
+        },
+        global::{defaults::DEFAULT_DEFAULT_PACK_SIZE_MIB, set_global_opts_with_args},
+        repository::{
+            repo::{RepoConfig, Repository},
+            snapshot::SnapshotStreamer,
+            tree::ChangeDetection,
+        },
+    };
+    use rand::RngCore;
+    use tempfile::tempdir;
+
+    use crate::integration_tests::init_repo;
+
+    #[test]
+    fn test_cat_file() -> Result<()> {
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        // A file large enough to be split in several chunks
+        let backup_data_tmp_path = tmp_path.join("backup");
+        std::fs::create_dir_all(&backup_data_tmp_path)?;
+        let mut data = vec![0u8; 6 * 1024 * 1024];
+        rand::rng().fill_bytes(&mut data);
+        std::fs::write(backup_data_tmp_path.join("file.bin"), &data)?;
+
+        let repo_path = tmp_path.join(String::from("repo"));
+        let backend = Arc::new(LocalFS::new(repo_path.clone()));
+
+        let global = GlobalArgs {
+            repo: repo_path.to_string_lossy().to_string(),
+            password_file: Some(password_path),
+            key: None,
+            quiet: true,
+            verbosity: None,
+            ssh_pubkey: None,
+            ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
+            pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
+        };
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+
+        // Run snapshot
+        let snapshot_args = cmd_snapshot::CmdArgs {
+            paths: vec![backup_data_tmp_path.clone()],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
+            as_root: false,
+            exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
+            tags_str: String::new(),
+            description: None,
+            rescan: false,
+            parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
+            read_concurrency: 2,
+            write_concurrency: 5,
+            dry_run: false,
+        };
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        let (repo, _) = Repository::try_open(
+            Some(password.to_string()),
+            None,
+            backend,
+            RepoConfig::default(),
+        )?;
+        let (_, snapshot) = SnapshotStreamer::new(repo.clone())?
+            .latest()
+            .expect("There should be at least one snapshot");
+
+        let cat = |path: &str, offset: u64, length: Option<u64>| -> Result<Vec<u8>> {
+            let mut out = Vec::new();
+            cmd_cat::write_file(
+                repo.as_ref(),
+                &snapshot,
+                Path::new(path),
+                offset,
+                length,
+                &mut out,
+            )?;
+            Ok(out)
+        };
+
+        // Whole file, with and without a leading slash
+        assert!(cat("backup/file.bin", 0, None)? == data);
+        assert!(cat("/backup/file.bin", 0, None)? == data);
+
+        // Ranges across chunk boundaries
+        let offset = 3 * 1024 * 1024 + 17;
+        assert!(cat("backup/file.bin", offset as u64, None)? == data[offset..]);
+        assert!(
+            cat("backup/file.bin", offset as u64, Some(2 * 1024 * 1024))?
+                == data[offset..offset + 2 * 1024 * 1024]
+        );
+        assert!(cat("backup/file.bin", data.len() as u64 + 10, None)?.is_empty());
+
+        // Errors
+        assert!(cat("backup", 0, None).is_err());
+        assert!(cat("backup/missing.bin", 0, None).is_err());
+
+        // Object parsing
+        match Object::from_str("file:latest:dir/with:colon.txt") {
+            Ok(Object::File(UseSnapshot::Latest, path)) => {
+                assert_eq!(path, Path::new("dir/with:colon.txt"))
+            }
+            other => panic!("Unexpected object: {other:?}"),
+        }
+        assert!(Object::from_str("file:latest").is_err());
+
+        Ok(())
+    }
+}
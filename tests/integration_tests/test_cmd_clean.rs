@@ -23,6 +23,7 @@ mod tests {
     use mapache::{
         commands::{self, GlobalArgs, UseSnapshot, cmd_clean, cmd_restore, cmd_snapshot},
         global::{defaults::DEFAULT_DEFAULT_PACK_SIZE_MIB, set_global_opts_with_args},
+        repository::{policy::RetentionPolicy, tree::ChangeDetection},
     };
 
     use tempfile::tempdir;
@@ -56,6 +57,8 @@ mod tests {
             verbosity: None,
             ssh_pubkey: None,
             ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
             pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
         };
         set_global_opts_with_args(&global);
@@ -71,12 +74,28 @@ mod tests {
                 backup_data_tmp_path.join("2"),
                 backup_data_tmp_path.join("file.txt"),
             ],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
             as_root: false,
             exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
             tags_str: String::new(),
             description: None,
             rescan: false,
             parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
             read_concurrency: 2,
             write_concurrency: 5,
             dry_run: false,
@@ -90,12 +109,28 @@ mod tests {
                 backup_data_tmp_path.join("1"),
                 backup_data_tmp_path.join("2"),
             ],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
             as_root: false,
             exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
             tags_str: String::new(),
             description: None,
             rescan: false,
             parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
             read_concurrency: 2,
             write_concurrency: 5,
             dry_run: false,
@@ -106,17 +141,16 @@ mod tests {
         // Keep the last snapshot
         let forget_args = commands::cmd_forget::CmdArgs {
             forget: Vec::new(),
-            keep_last: Some(1),
-            keep_within: None,
-            keep_yearly: None,
-            keep_monthly: None,
-            keep_weekly: None,
-            keep_daily: None,
+            policy: None,
+            rules: RetentionPolicy {
+                tags: Some(String::new()),
+                keep_last: Some(1),
+                keep_tags: Some(String::new()),
+                ..Default::default()
+            },
             run_gc: false,
             dry_run: false,
             tolerance: 0.0_f32,
-            tags_str: Some(String::new()),
-            keep_tags_str: Some(String::new()),
             verify: true,
         };
         commands::cmd_forget::run(&global, &forget_args)
@@ -139,6 +173,16 @@ mod tests {
             exclude: None,
             strip_prefix: false,
             resolution: mapache::restorer::Resolution::Skip,
+            delta: false,
+            delete: false,
+            resume: false,
+            numeric_owner: false,
+            no_owner: false,
+            map_user: Vec::new(),
+            map_group: Vec::new(),
+            same_owner_as_target: false,
+            verify_only: false,
+            restore_concurrency: 4,
             no_verify: false,
         };
         commands::cmd_restore::run(&global, &restore_args)
@@ -0,0 +1,193 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+mod tests {
+    use std::sync::Arc;
+
+    use anyhow::{Context, Result};
+    use filetime::{FileTime, set_file_mtime};
+    use mapache::{
+        backend::localfs::LocalFS,
+        commands::{self, GlobalArgs, UseSnapshot, cmd_diff, cmd_snapshot},
+        global::{defaults::DEFAULT_DEFAULT_PACK_SIZE_MIB, set_global_opts_with_args},
+        repository::{
+            repo::{RepoConfig, Repository},
+            snapshot::SnapshotStreamer,
+            tree::ChangeDetection,
+        },
+    };
+    use rand::RngCore;
+    use tempfile::tempdir;
+
+    use crate::integration_tests::init_repo;
+
+    #[test]
+    fn test_diff_against_fs() -> Result<()> {
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        let backup_data_tmp_path = tmp_path.join("backup");
+        std::fs::create_dir_all(backup_data_tmp_path.join("sub"))?;
+        let mut data = vec![0u8; 256 * 1024];
+        rand::rng().fill_bytes(&mut data);
+        std::fs::write(backup_data_tmp_path.join("a.bin"), &data)?;
+        std::fs::write(backup_data_tmp_path.join("b.txt"), "mapache")?;
+        std::fs::write(backup_data_tmp_path.join("sub").join("c.txt"), "tejon")?;
+
+        let repo = String::from("repo");
+        let repo_path = tmp_path.join(&repo);
+        let backend = Arc::new(LocalFS::new(repo_path.clone()));
+
+        let global = GlobalArgs {
+            repo: repo_path.to_string_lossy().to_string(),
+            password_file: Some(password_path),
+            key: None,
+            quiet: true,
+            verbosity: None,
+            ssh_pubkey: None,
+            ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
+            pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
+        };
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+
+        // Run snapshot
+        let snapshot_args = cmd_snapshot::CmdArgs {
+            paths: vec![backup_data_tmp_path.clone()],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
+            as_root: false,
+            exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
+            tags_str: String::new(),
+            description: None,
+            rescan: false,
+            parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
+            read_concurrency: 2,
+            write_concurrency: 5,
+            dry_run: false,
+        };
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        let (repo, _) = Repository::try_open(
+            Some(password.to_string()),
+            None,
+            backend,
+            RepoConfig::default(),
+        )?;
+        let (snapshot_id, snapshot) = SnapshotStreamer::new(repo.clone())?
+            .latest()
+            .expect("There should be at least one snapshot");
+
+        // Nothing changed yet
+        let (counts, size) = cmd_diff::diff_against_fs(
+            repo.clone(),
+            &snapshot,
+            &backup_data_tmp_path,
+            None,
+            None,
+            true,
+        )?;
+        assert_eq!(counts.unchanged_files, 3);
+        assert_eq!(counts.unchanged_dirs, 2);
+        assert_eq!(
+            counts.changed_files + counts.new_files + counts.deleted_files,
+            0
+        );
+        assert_eq!(size, snapshot.size());
+
+        // Change the contents of a file keeping its size and modification time, delete a file and
+        // add a new one
+        let a_path = backup_data_tmp_path.join("a.bin");
+        let a_mtime = FileTime::from_last_modification_time(&std::fs::metadata(&a_path)?);
+        data[0] ^= 0xFF;
+        std::fs::write(&a_path, &data)?;
+        set_file_mtime(&a_path, a_mtime)?;
+        std::fs::remove_file(backup_data_tmp_path.join("b.txt"))?;
+        std::fs::write(backup_data_tmp_path.join("sub").join("d.txt"), "nuevo")?;
+
+        for content in [false, true] {
+            let (counts, _) = cmd_diff::diff_against_fs(
+                repo.clone(),
+                &snapshot,
+                &backup_data_tmp_path,
+                None,
+                None,
+                content,
+            )?;
+            assert_eq!(counts.new_files, 1);
+            assert_eq!(counts.deleted_files, 1);
+            assert_eq!(counts.changed_files, 1);
+            assert_eq!(counts.unchanged_files, 1);
+            assert_eq!(counts.changed_dirs, 2);
+        }
+
+        // Only the items under the given path are compared
+        let (counts, _) = cmd_diff::diff_against_fs(
+            repo.clone(),
+            &snapshot,
+            &backup_data_tmp_path.join("sub"),
+            None,
+            None,
+            true,
+        )?;
+        assert_eq!(counts.new_files, 1);
+        assert_eq!(counts.deleted_files, 0);
+        assert_eq!(counts.unchanged_files, 1);
+        assert_eq!(counts.changed_dirs, 1);
+
+        // Paths outside the snapshot root can't be compared
+        assert!(
+            commands::cmd_diff::run(
+                &global,
+                &cmd_diff::CmdArgs {
+                    source_snapshot_id: snapshot_id.to_hex(),
+                    target_snapshot_id: None,
+                    against_fs: Some(std::path::PathBuf::from("/")),
+                    content: false,
+                    include: None,
+                    exclude: None,
+                },
+            )
+            .is_err()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        fs::File,
+        io::Read,
+        os::unix::fs::{PermissionsExt, symlink},
+        path::{Path, PathBuf},
+    };
+
+    use anyhow::{Context, Result};
+    use mapache::{
+        commands::{self, GlobalArgs, UseSnapshot, cmd_dump, cmd_snapshot},
+        global::{defaults::DEFAULT_DEFAULT_PACK_SIZE_MIB, set_global_opts_with_args},
+        repository::tree::ChangeDetection,
+        restorer::archive::ArchiveFormat,
+    };
+    use rand::RngCore;
+    use tempfile::tempdir;
+
+    use crate::integration_tests::init_repo;
+
+    /// Type, mode, mtime and contents (or link target) of a tar entry
+    type TarEntry = (tar::EntryType, u32, u64, Vec<u8>);
+
+    /// Reads the entries of a tar archive.
+    fn read_tar<R: Read>(reader: R) -> Result<BTreeMap<PathBuf, TarEntry>> {
+        let mut archive = tar::Archive::new(reader);
+        let mut entries = BTreeMap::new();
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let header = entry.header().clone();
+            let path = entry.path()?.to_path_buf();
+            let mut data = Vec::new();
+            if header.entry_type().is_symlink() {
+                data = entry
+                    .link_name()?
+                    .unwrap()
+                    .to_string_lossy()
+                    .as_bytes()
+                    .to_vec();
+            } else {
+                entry.read_to_end(&mut data)?;
+            }
+            entries.insert(
+                path,
+                (header.entry_type(), header.mode()?, header.mtime()?, data),
+            );
+        }
+        Ok(entries)
+    }
+
+    fn mtime_secs(path: &Path) -> Result<u64> {
+        Ok(path
+            .symlink_metadata()?
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs())
+    }
+
+    #[test]
+    fn test_dump_archives() -> Result<()> {
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        let backup_data_tmp_path = tmp_path.join("backup");
+        std::fs::create_dir_all(backup_data_tmp_path.join("sub"))?;
+        std::fs::write(backup_data_tmp_path.join("a.txt"), "mapache")?;
+        std::fs::set_permissions(
+            backup_data_tmp_path.join("a.txt"),
+            std::fs::Permissions::from_mode(0o640),
+        )?;
+        let mut big = vec![0u8; 3 * 1024 * 1024];
+        rand::rng().fill_bytes(&mut big);
+        std::fs::write(backup_data_tmp_path.join("sub").join("big.bin"), &big)?;
+        symlink("a.txt", backup_data_tmp_path.join("link"))?;
+
+        let repo = String::from("repo");
+        let repo_path = tmp_path.join(&repo);
+
+        let global = GlobalArgs {
+            repo: repo_path.to_string_lossy().to_string(),
+            password_file: Some(password_path),
+            key: None,
+            quiet: true,
+            verbosity: None,
+            ssh_pubkey: None,
+            ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
+            pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
+        };
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+
+        // Run snapshot
+        let snapshot_args = cmd_snapshot::CmdArgs {
+            paths: vec![backup_data_tmp_path.clone()],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
+            as_root: false,
+            exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
+            tags_str: String::new(),
+            description: None,
+            rescan: false,
+            parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
+            read_concurrency: 2,
+            write_concurrency: 5,
+            dry_run: false,
+        };
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        // Tar and tar.zst
+        let tar_path = tmp_path.join("dump.tar");
+        let mut dump_args = cmd_dump::CmdArgs {
+            snapshot: UseSnapshot::Latest,
+            format: ArchiveFormat::Tar,
+            output: tar_path.clone(),
+            include: None,
+            exclude: Some(vec![PathBuf::from("backup/sub")]),
+            strip_prefix: false,
+        };
+        commands::cmd_dump::run(&global, &dump_args).with_context(|| "Failed to run cmd_dump")?;
+        let tar_entries = read_tar(File::open(&tar_path)?)?;
+
+        let tar_zst_path = tmp_path.join("dump.tar.zst");
+        dump_args.format = ArchiveFormat::TarZst;
+        dump_args.output = tar_zst_path.clone();
+        commands::cmd_dump::run(&global, &dump_args)
+            .with_context(|| "Failed to run cmd_dump --format tar.zst")?;
+        let tar_zst_entries = read_tar(zstd::Decoder::new(File::open(&tar_zst_path)?)?)?;
+        assert_eq!(tar_entries, tar_zst_entries);
+
+        let paths: Vec<&PathBuf> = tar_entries.keys().collect();
+        assert_eq!(
+            paths,
+            [
+                Path::new("backup"),
+                Path::new("backup/a.txt"),
+                Path::new("backup/link"),
+            ]
+        );
+
+        let (entry_type, mode, mtime, data) = &tar_entries[Path::new("backup/a.txt")];
+        assert_eq!(*entry_type, tar::EntryType::Regular);
+        assert_eq!(*mode, 0o640);
+        assert_eq!(*mtime, mtime_secs(&backup_data_tmp_path.join("a.txt"))?);
+        assert_eq!(data, b"mapache");
+
+        let (entry_type, _, _, target) = &tar_entries[Path::new("backup/link")];
+        assert_eq!(*entry_type, tar::EntryType::Symlink);
+        assert_eq!(target, b"a.txt");
+
+        // Zip, with the common prefix stripped
+        let zip_path = tmp_path.join("dump.zip");
+        dump_args.format = ArchiveFormat::Zip;
+        dump_args.output = zip_path.clone();
+        dump_args.include = Some(vec![PathBuf::from("backup/sub")]);
+        dump_args.exclude = None;
+        dump_args.strip_prefix = true;
+        commands::cmd_dump::run(&global, &dump_args)
+            .with_context(|| "Failed to run cmd_dump --format zip")?;
+
+        let mut zip = zip::ZipArchive::new(File::open(&zip_path)?)?;
+        let names: Vec<&str> = zip.file_names().collect();
+        assert_eq!(names, ["sub/", "sub/big.bin"]);
+        let mut data = Vec::new();
+        zip.by_name("sub/big.bin")?.read_to_end(&mut data)?;
+        assert!(data == big);
+
+        Ok(())
+    }
+}
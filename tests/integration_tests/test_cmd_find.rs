@@ -0,0 +1,198 @@
+// mapache is an incremental backup tool
+// Copyright (C) 2025  Javier Lancha Vázquez <javier.lancha@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+mod tests {
+    use std::{path::PathBuf, sync::Arc};
+
+    use anyhow::{Context, Result};
+    use glob::Pattern;
+    use mapache::{
+        backend::localfs::LocalFS,
+        commands::{
+            self, GlobalArgs, UseSnapshot,
+            cmd_find::{Criteria, Finder},
+            cmd_snapshot,
+        },
+        global::{defaults::DEFAULT_DEFAULT_PACK_SIZE_MIB, set_global_opts_with_args},
+        repository::{
+            repo::{RepoConfig, Repository},
+            snapshot::SnapshotStreamer,
+            tree::{ChangeDetection, NodeType},
+        },
+    };
+    use tempfile::tempdir;
+
+    use crate::integration_tests::init_repo;
+
+    #[test]
+    fn test_find() -> Result<()> {
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        let backup_data_tmp_path = tmp_path.join("backup");
+        std::fs::create_dir_all(backup_data_tmp_path.join("a"))?;
+        std::fs::create_dir_all(backup_data_tmp_path.join("b"))?;
+        std::fs::write(backup_data_tmp_path.join("a").join("x.txt"), "mapache")?;
+        std::fs::write(backup_data_tmp_path.join("b").join("y.log"), "tejon")?;
+
+        let repo = String::from("repo");
+        let repo_path = tmp_path.join(&repo);
+        let backend = Arc::new(LocalFS::new(repo_path.clone()));
+
+        let global = GlobalArgs {
+            repo: repo_path.to_string_lossy().to_string(),
+            password_file: Some(password_path),
+            key: None,
+            quiet: true,
+            verbosity: None,
+            ssh_pubkey: None,
+            ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
+            pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
+        };
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+
+        // Three snapshots: a new file is added to b, then a file is deleted from a
+        let snapshot_args = cmd_snapshot::CmdArgs {
+            paths: vec![backup_data_tmp_path.clone()],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
+            as_root: false,
+            exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
+            tags_str: String::new(),
+            description: None,
+            rescan: false,
+            parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
+            read_concurrency: 2,
+            write_concurrency: 5,
+            dry_run: false,
+        };
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+        std::fs::write(backup_data_tmp_path.join("b").join("z.txt"), "nuevo")?;
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+        std::fs::remove_file(backup_data_tmp_path.join("a").join("x.txt"))?;
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        let (repo, _) = Repository::try_open(
+            Some(password.to_string()),
+            None,
+            backend,
+            RepoConfig::default(),
+        )?;
+        let mut snapshots: Vec<_> = SnapshotStreamer::new(repo.clone())?.collect();
+        snapshots.sort_by_key(|(_id, snapshot)| snapshot.timestamp);
+        assert_eq!(snapshots.len(), 3);
+
+        // Returns the paths matched in each snapshot and the number of trees scanned
+        let find = |criteria: Criteria| -> Result<(Vec<Vec<PathBuf>>, usize)> {
+            let mut finder = Finder::new(repo.clone(), criteria);
+            let mut found = Vec::new();
+            for (_id, snapshot) in &snapshots {
+                let matches = finder.find(snapshot)?;
+                found.push(
+                    matches
+                        .items()
+                        .into_iter()
+                        .map(|(path, _node)| path)
+                        .collect(),
+                );
+            }
+            Ok((found, finder.num_scanned_trees()))
+        };
+
+        // The deleted file is only found in the first two snapshots. Each snapshot has 4 trees,
+        // but the unchanged directories are only scanned once.
+        let (found, num_scanned_trees) = find(Criteria {
+            patterns: vec![Pattern::new("x.txt")?],
+            ..Criteria::default()
+        })?;
+        let x_path = PathBuf::from("backup/a/x.txt");
+        assert_eq!(found, vec![vec![x_path.clone()], vec![x_path], vec![]]);
+        assert_eq!(num_scanned_trees, 10);
+
+        // Path patterns and node types
+        let (found, _) = find(Criteria {
+            patterns: vec![Pattern::new("backup/*")?],
+            node_type: Some(NodeType::Directory),
+            ..Criteria::default()
+        })?;
+        let dirs = vec![PathBuf::from("backup/a"), PathBuf::from("backup/b")];
+        assert_eq!(found, vec![dirs.clone(), dirs.clone(), dirs]);
+
+        let (found, _) = find(Criteria {
+            patterns: vec![Pattern::new("**/*.txt")?],
+            ..Criteria::default()
+        })?;
+        assert_eq!(found[1].len(), 2);
+        assert_eq!(found[2], vec![PathBuf::from("backup/b/z.txt")]);
+
+        // Sizes only match files
+        let (found, _) = find(Criteria {
+            min_size: Some(6),
+            ..Criteria::default()
+        })?;
+        assert_eq!(found[2], Vec::<PathBuf>::new());
+        assert_eq!(found[0], vec![PathBuf::from("backup/a/x.txt")]);
+
+        // Files containing a blob
+        let mut finder = Finder::new(repo.clone(), Criteria::default());
+        let first_matches = finder.find(&snapshots[0].1)?;
+        let y_blob = first_matches
+            .items()
+            .into_iter()
+            .find(|(path, _)| path.ends_with("y.log"))
+            .and_then(|(_, node)| node.blobs.clone())
+            .expect("y.log should have blobs")[0]
+            .clone();
+        let (found, _) = find(Criteria {
+            blob: Some(y_blob),
+            ..Criteria::default()
+        })?;
+        let y_path = PathBuf::from("backup/b/y.log");
+        assert_eq!(
+            found,
+            vec![vec![y_path.clone()], vec![y_path.clone()], vec![y_path]]
+        );
+
+        Ok(())
+    }
+}
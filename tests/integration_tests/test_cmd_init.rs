@@ -23,7 +23,11 @@ mod tests {
         backend::localfs::LocalFS,
         commands::{self, GlobalArgs, cmd_init::CmdArgs},
         global::{defaults::DEFAULT_DEFAULT_PACK_SIZE_MIB, set_global_opts_with_args},
-        repository::{repo::RepoConfig, repo::Repository},
+        repository::{
+            repo::RepoConfig,
+            repo::Repository,
+            storage::{Compression, CompressionMode},
+        },
     };
 
     use anyhow::{Context, Result};
@@ -48,9 +52,14 @@ mod tests {
             verbosity: None,
             ssh_pubkey: None,
             ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
             pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
         };
-        let args = CmdArgs {};
+        let args = CmdArgs {
+            compression: Compression::Lz4,
+            compression_mode: CompressionMode::Never,
+        };
         set_global_opts_with_args(&global);
 
         // Init repo
@@ -71,13 +80,16 @@ mod tests {
 
         // Try to open repo
         let backend = Arc::new(LocalFS::new(repo_path));
-        Repository::try_open(
+        let (repo, _) = Repository::try_open(
             Some(password.to_string()),
             None,
             backend,
             RepoConfig::default(),
         )
         .with_context(|| "Failed to open repository")?;
+        let manifest = repo.load_manifest()?;
+        assert_eq!(manifest.compression, Compression::Lz4);
+        assert_eq!(manifest.compression_mode, CompressionMode::Never);
 
         Ok(())
     }
@@ -102,9 +114,14 @@ mod tests {
             verbosity: None,
             ssh_pubkey: None,
             ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
             pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
         };
-        let args = CmdArgs {};
+        let args = CmdArgs {
+            compression: Compression::default(),
+            compression_mode: CompressionMode::Auto,
+        };
         set_global_opts_with_args(&global);
 
         // Init repo
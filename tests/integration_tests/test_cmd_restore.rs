@@ -17,17 +17,27 @@
 #![cfg(test)]
 
 mod tests {
-    use std::path::PathBuf;
+    use std::{path::PathBuf, sync::Arc};
 
     use anyhow::{Context, Result};
     use mapache::{
+        backend::localfs::LocalFS,
         commands::{self, GlobalArgs, UseSnapshot, cmd_restore, cmd_snapshot},
         global::{defaults::DEFAULT_DEFAULT_PACK_SIZE_MIB, set_global_opts_with_args},
+        repository::{
+            repo::{RepoConfig, Repository},
+            snapshot::SnapshotStreamer,
+            tree::ChangeDetection,
+        },
     };
+    use rand::RngCore;
     use tempfile::tempdir;
 
     use crate::{
-        integration_tests::{BACKUP_DATA_PATH, init_repo},
+        integration_tests::{
+            BACKUP_DATA_PATH, default_global_args, default_restore_args, default_snapshot_args,
+            init_repo,
+        },
         test_utils,
     };
 
@@ -54,6 +64,8 @@ mod tests {
             verbosity: None,
             ssh_pubkey: None,
             ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
             pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
         };
         set_global_opts_with_args(&global);
@@ -69,12 +81,28 @@ mod tests {
                 backup_data_tmp_path.join("2"),
                 backup_data_tmp_path.join("file.txt"),
             ],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
             as_root: false,
             exclude: Some(vec![backup_data_tmp_path.join("0/01")]),
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
             tags_str: String::new(),
             description: None,
             rescan: false,
             parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
             read_concurrency: 2,
             write_concurrency: 5,
             dry_run: false,
@@ -92,6 +120,16 @@ mod tests {
             exclude: Some(vec![PathBuf::from("0/00/file00.txt")]),
             strip_prefix: false,
             resolution: mapache::restorer::Resolution::Skip,
+            delta: false,
+            delete: false,
+            resume: false,
+            numeric_owner: false,
+            no_owner: false,
+            map_user: Vec::new(),
+            map_group: Vec::new(),
+            same_owner_as_target: false,
+            verify_only: false,
+            restore_concurrency: 4,
             no_verify: false,
         };
         commands::cmd_restore::run(&global, &restore_args)
@@ -163,6 +201,8 @@ mod tests {
             verbosity: None,
             ssh_pubkey: None,
             ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
             pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
         };
         set_global_opts_with_args(&global);
@@ -178,12 +218,28 @@ mod tests {
                 backup_data_tmp_path.join("2"),
                 backup_data_tmp_path.join("file.txt"),
             ],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
             as_root: false,
             exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
             tags_str: String::new(),
             description: None,
             rescan: false,
             parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
             read_concurrency: 2,
             write_concurrency: 5,
             dry_run: false,
@@ -201,6 +257,16 @@ mod tests {
             exclude: None,
             strip_prefix: false,
             resolution: mapache::restorer::Resolution::Skip,
+            delta: false,
+            delete: false,
+            resume: false,
+            numeric_owner: false,
+            no_owner: false,
+            map_user: Vec::new(),
+            map_group: Vec::new(),
+            same_owner_as_target: false,
+            verify_only: false,
+            restore_concurrency: 4,
             no_verify: false,
         };
         commands::cmd_restore::run(&global, &restore_args)
@@ -234,6 +300,8 @@ mod tests {
             verbosity: None,
             ssh_pubkey: None,
             ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
             pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
         };
         set_global_opts_with_args(&global);
@@ -249,12 +317,28 @@ mod tests {
                 backup_data_tmp_path.join("2"),
                 backup_data_tmp_path.join("file.txt"),
             ],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
             as_root: false,
             exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
             tags_str: String::new(),
             description: None,
             rescan: false,
             parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
             read_concurrency: 2,
             write_concurrency: 5,
             dry_run: false,
@@ -275,6 +359,16 @@ mod tests {
             exclude: None,
             strip_prefix: true,
             resolution: mapache::restorer::Resolution::Skip,
+            delta: false,
+            delete: false,
+            resume: false,
+            numeric_owner: false,
+            no_owner: false,
+            map_user: Vec::new(),
+            map_group: Vec::new(),
+            same_owner_as_target: false,
+            verify_only: false,
+            restore_concurrency: 4,
             no_verify: false,
         };
         commands::cmd_restore::run(&global, &restore_args)
@@ -296,6 +390,16 @@ mod tests {
             exclude: None,
             strip_prefix: true,
             resolution: mapache::restorer::Resolution::Skip,
+            delta: false,
+            delete: false,
+            resume: false,
+            numeric_owner: false,
+            no_owner: false,
+            map_user: Vec::new(),
+            map_group: Vec::new(),
+            same_owner_as_target: false,
+            verify_only: false,
+            restore_concurrency: 4,
             no_verify: false,
         };
         commands::cmd_restore::run(&global, &restore_args)
@@ -309,4 +413,581 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_restore_delta() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        use filetime::{FileTime, set_file_mtime};
+
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        // Files large enough to be split in several chunks
+        let backup_data_tmp_path = tmp_path.join("backup");
+        std::fs::create_dir_all(&backup_data_tmp_path)?;
+        let mut rng = rand::rng();
+        for name in [
+            "modified.bin",
+            "truncated.bin",
+            "deleted.bin",
+            "unchanged.bin",
+        ] {
+            let mut data = vec![0u8; 6 * 1024 * 1024];
+            rng.fill_bytes(&mut data);
+            std::fs::write(backup_data_tmp_path.join(name), &data)?;
+        }
+
+        let repo = String::from("repo");
+        let repo_path = tmp_path.join(&repo);
+
+        let global = default_global_args(&repo_path, password_path);
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+
+        // Run snapshot
+        let snapshot_args = default_snapshot_args(vec![backup_data_tmp_path.clone()]);
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        // Run a full restore
+        let restore_path = tmp_path.join("restore");
+        let mut restore_args = default_restore_args(restore_path.clone());
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore")?;
+
+        // Modify the restored copy
+        let restored_data_path = restore_path.join("backup");
+        let modified_path = restored_data_path.join("modified.bin");
+        let mut modified = std::fs::read(&modified_path)?;
+        modified[3 * 1024 * 1024..3 * 1024 * 1024 + 100].fill(0xAA);
+        modified.extend_from_slice(b"appended");
+        std::fs::write(&modified_path, &modified)?;
+
+        let truncated_path = restored_data_path.join("truncated.bin");
+        let truncated = std::fs::read(&truncated_path)?;
+        std::fs::write(&truncated_path, &truncated[..1024 * 1024])?;
+
+        std::fs::remove_file(restored_data_path.join("deleted.bin"))?;
+
+        // Run a delta restore over the modified copy
+        restore_args.delta = true;
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore --delta")?;
+
+        for name in [
+            "modified.bin",
+            "truncated.bin",
+            "deleted.bin",
+            "unchanged.bin",
+        ] {
+            assert_eq!(
+                std::fs::read(restored_data_path.join(name))?,
+                std::fs::read(backup_data_tmp_path.join(name))?,
+                "{name}"
+            );
+            assert_eq!(
+                restored_data_path.join(name).metadata()?.modified()?,
+                backup_data_tmp_path.join(name).metadata()?.modified()?,
+                "{name}"
+            );
+        }
+
+        // A symlink in place of a file is replaced, and its target is never written
+        let victim_path = tmp_path.join("victim.bin");
+        std::fs::write(&victim_path, &truncated[..1024 * 1024])?;
+        std::fs::remove_file(&truncated_path)?;
+        std::os::unix::fs::symlink(&victim_path, &truncated_path)?;
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore --delta over a symlink")?;
+        assert!(truncated_path.symlink_metadata()?.is_file());
+        assert_eq!(std::fs::read(&truncated_path)?, truncated);
+        assert_eq!(std::fs::read(&victim_path)?, &truncated[..1024 * 1024]);
+
+        // Files with the same size and modification time are still compared by contents, and
+        // the metadata of unchanged files is restored
+        let unchanged_path = restored_data_path.join("unchanged.bin");
+        let unchanged_mtime =
+            FileTime::from_last_modification_time(&std::fs::metadata(&unchanged_path)?);
+        let mut unchanged = std::fs::read(&unchanged_path)?;
+        unchanged[0] ^= 0xFF;
+        std::fs::write(&unchanged_path, &unchanged)?;
+        set_file_mtime(&unchanged_path, unchanged_mtime)?;
+        let modified_mode = std::fs::metadata(&modified_path)?.permissions().mode();
+        std::fs::set_permissions(&modified_path, std::fs::Permissions::from_mode(0o600))?;
+
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore --delta over unchanged metadata")?;
+        assert_eq!(
+            std::fs::read(&unchanged_path)?,
+            std::fs::read(backup_data_tmp_path.join("unchanged.bin"))?
+        );
+        assert_eq!(
+            std::fs::metadata(&modified_path)?.permissions().mode(),
+            modified_mode
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_resolutions_and_delete() -> Result<()> {
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        let backup_data_tmp_path = tmp_path.join("backup");
+        std::fs::create_dir_all(backup_data_tmp_path.join("sub"))?;
+        std::fs::write(backup_data_tmp_path.join("a.txt"), "original a")?;
+        std::fs::write(backup_data_tmp_path.join("b.txt"), "original b")?;
+        std::fs::write(backup_data_tmp_path.join("sub").join("c.txt"), "original c")?;
+
+        let repo = String::from("repo");
+        let repo_path = tmp_path.join(&repo);
+
+        let global = default_global_args(&repo_path, password_path);
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+
+        // Run snapshot
+        let snapshot_args = default_snapshot_args(vec![backup_data_tmp_path.clone()]);
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        // Run a full restore
+        let restore_path = tmp_path.join("restore");
+        let mut restore_args = default_restore_args(restore_path.clone());
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore")?;
+
+        // A restore failing on a conflict leaves the target untouched
+        assert!(commands::cmd_restore::run(&global, &restore_args).is_err());
+        assert!(!restore_path.join(".mapache-restore-journal").exists());
+
+        // Edit the restored copy and add items that are not in the snapshot
+        let restored_data_path = restore_path.join("backup");
+        let a_path = restored_data_path.join("a.txt");
+        std::fs::write(&a_path, "local a")?;
+        std::fs::write(restored_data_path.join("extra.txt"), "extra")?;
+        std::fs::create_dir_all(restored_data_path.join("sub").join("extra_dir"))?;
+        std::fs::write(
+            restored_data_path.join("sub").join("extra_dir").join("x"),
+            "x",
+        )?;
+
+        // The local edit is newer than the snapshot, so it is kept
+        restore_args.resolution = mapache::restorer::Resolution::IfNewer;
+        restore_args.delete = true;
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore --resolution if-newer --delete")?;
+        assert_eq!(std::fs::read_to_string(&a_path)?, "local a");
+        assert!(!restored_data_path.join("extra.txt").exists());
+        assert!(!restored_data_path.join("sub").join("extra_dir").exists());
+        assert!(restored_data_path.join("sub").join("c.txt").exists());
+
+        // The local edit differs from the snapshot, so it is overwritten
+        restore_args.resolution = mapache::restorer::Resolution::IfChanged;
+        restore_args.delete = false;
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore --resolution if-changed")?;
+        assert_eq!(std::fs::read_to_string(&a_path)?, "original a");
+        assert_eq!(
+            a_path.metadata()?.modified()?,
+            backup_data_tmp_path.join("a.txt").metadata()?.modified()?
+        );
+
+        // Renamed items are restored alongside the existing ones
+        std::fs::write(&a_path, "local a")?;
+        restore_args.resolution = mapache::restorer::Resolution::Rename;
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore --resolution rename")?;
+        assert_eq!(std::fs::read_to_string(&a_path)?, "local a");
+        let renamed: Vec<PathBuf> = std::fs::read_dir(&restored_data_path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|path| {
+                path.file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with("a.txt.mapache-"))
+            })
+            .collect();
+        assert_eq!(renamed.len(), 1);
+        assert_eq!(std::fs::read_to_string(&renamed[0])?, "original a");
+
+        // A directory renamed because a file has its name is restored with all its contents,
+        // which are kept by --delete
+        let sub_path = restored_data_path.join("sub");
+        std::fs::remove_dir_all(&sub_path)?;
+        std::fs::write(&sub_path, "local sub")?;
+        restore_args.delete = true;
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore --resolution rename --delete")?;
+        assert_eq!(std::fs::read_to_string(&sub_path)?, "local sub");
+        let renamed_sub = renamed[0]
+            .to_string_lossy()
+            .replace("a.txt.mapache-", "sub.mapache-");
+        assert_eq!(
+            std::fs::read_to_string(PathBuf::from(renamed_sub).join("c.txt"))?,
+            "original c"
+        );
+        assert_eq!(std::fs::read_to_string(&renamed[0])?, "original a");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_delete_keeps_symlinked_dirs() -> Result<()> {
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        let backup_data_tmp_path = tmp_path.join("backup");
+        std::fs::create_dir_all(backup_data_tmp_path.join("dir"))?;
+        std::fs::write(backup_data_tmp_path.join("dir").join("y.txt"), "mapache")?;
+
+        let repo = String::from("repo");
+        let repo_path = tmp_path.join(&repo);
+
+        let global = default_global_args(&repo_path, password_path);
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+
+        // Run snapshot
+        let snapshot_args = default_snapshot_args(vec![backup_data_tmp_path.clone()]);
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        // A directory of the snapshot is a symlink to a directory outside the target
+        let outside_path = tmp_path.join("outside");
+        std::fs::create_dir_all(&outside_path)?;
+        std::fs::write(outside_path.join("secret"), "secret")?;
+        let restore_path = tmp_path.join("restore");
+        std::fs::create_dir_all(restore_path.join("backup"))?;
+        let link_path = restore_path.join("backup").join("dir");
+        std::os::unix::fs::symlink(&outside_path, &link_path)?;
+
+        // The symlink is kept, and the contents of its target are never deleted
+        for resolution in [
+            mapache::restorer::Resolution::Skip,
+            mapache::restorer::Resolution::IfNewer,
+        ] {
+            let restore_args = cmd_restore::CmdArgs {
+                resolution,
+                delete: true,
+                ..default_restore_args(restore_path.clone())
+            };
+            commands::cmd_restore::run(&global, &restore_args)
+                .with_context(|| "Failed to run cmd_restore --delete")?;
+
+            assert!(link_path.symlink_metadata()?.is_symlink());
+            assert_eq!(
+                std::fs::read_to_string(outside_path.join("secret"))?,
+                "secret"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_resume() -> Result<()> {
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        let backup_data_tmp_path = tmp_path.join("backup");
+        std::fs::create_dir_all(&backup_data_tmp_path)?;
+        let mut rng = rand::rng();
+        let names = ["completed.bin", "partial.bin", "missing.bin"];
+        for name in names {
+            let mut data = vec![0u8; 4 * 1024 * 1024];
+            rng.fill_bytes(&mut data);
+            std::fs::write(backup_data_tmp_path.join(name), &data)?;
+        }
+
+        let repo = String::from("repo");
+        let repo_path = tmp_path.join(&repo);
+        let backend = Arc::new(LocalFS::new(repo_path.clone()));
+
+        let global = default_global_args(&repo_path, password_path);
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+
+        // Run snapshot
+        let snapshot_args = default_snapshot_args(vec![backup_data_tmp_path.clone()]);
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        let (repo, _) = Repository::try_open(
+            Some(password.to_string()),
+            None,
+            backend,
+            RepoConfig::default(),
+        )?;
+        let (snapshot_id, _) = SnapshotStreamer::new(repo.clone())?
+            .latest()
+            .expect("There should be at least one snapshot");
+
+        // Nothing to resume yet
+        let restore_path = tmp_path.join("restore");
+        let mut restore_args = cmd_restore::CmdArgs {
+            resume: true,
+            ..default_restore_args(restore_path.clone())
+        };
+        assert!(commands::cmd_restore::run(&global, &restore_args).is_err());
+
+        // A complete restore doesn't leave a journal behind
+        restore_args.resume = false;
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore")?;
+        let journal_path = restore_path.join(".mapache-restore-journal");
+        assert!(!journal_path.exists());
+
+        // Simulate an interrupted restore: one file completed, one partially written with a hole
+        // and one not started yet
+        let restored_data_path = restore_path.join("backup");
+        let completed_path = restored_data_path.join("completed.bin");
+        let mut completed = std::fs::read(&completed_path)?;
+        completed[0] ^= 0xFF;
+        std::fs::write(&completed_path, &completed)?;
+
+        let partial_path = restored_data_path.join("partial.bin");
+        let mut partial = std::fs::read(&partial_path)?;
+        partial[2 * 1024 * 1024..].fill(0);
+        std::fs::write(&partial_path, &partial)?;
+
+        std::fs::remove_file(restored_data_path.join("missing.bin"))?;
+
+        std::fs::write(
+            &journal_path,
+            format!(
+                "{{\"snapshot\":\"{}\"}}\n\"backup/completed.bin\"\n",
+                snapshot_id.to_hex()
+            ),
+        )?;
+
+        // Resume
+        restore_args.resume = true;
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore --resume")?;
+        assert!(!journal_path.exists());
+
+        // Files recorded in the journal are trusted. The rest are completed.
+        assert!(std::fs::read(&completed_path)? == completed);
+        for name in ["partial.bin", "missing.bin"] {
+            assert!(
+                std::fs::read(restored_data_path.join(name))?
+                    == std::fs::read(backup_data_tmp_path.join(name))?,
+                "{name}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_symlink_and_dir_metadata() -> Result<()> {
+        use std::os::unix::fs::{MetadataExt, symlink};
+
+        use filetime::{FileTime, set_file_times, set_symlink_file_times};
+
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        // A directory and a symlink with old times. The symlink target has different times.
+        let backup_data_tmp_path = tmp_path.join("backup");
+        let dir_path = backup_data_tmp_path.join("dir");
+        std::fs::create_dir_all(&dir_path)?;
+        std::fs::write(dir_path.join("target.txt"), "mapache")?;
+        symlink("target.txt", dir_path.join("link"))?;
+
+        let link_mtime = FileTime::from_unix_time(1_000_000_000, 0);
+        let dir_mtime = FileTime::from_unix_time(1_100_000_000, 0);
+        set_symlink_file_times(dir_path.join("link"), link_mtime, link_mtime)?;
+        set_file_times(&dir_path, dir_mtime, dir_mtime)?;
+
+        let repo = String::from("repo");
+        let repo_path = tmp_path.join(&repo);
+
+        let global = default_global_args(&repo_path, password_path);
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+
+        // Run snapshot
+        let snapshot_args = default_snapshot_args(vec![backup_data_tmp_path.clone()]);
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        // Changing owners requires root. Otherwise, restore the owners as they are.
+        let uid = dir_path.join("link").symlink_metadata()?.uid();
+        let is_root = uid == 0;
+        let mapped_uid: u32 = 12345;
+
+        let restore_path = tmp_path.join("restore");
+        let restore_args = cmd_restore::CmdArgs {
+            map_user: if is_root {
+                vec![format!("{uid}={mapped_uid}").parse()?]
+            } else {
+                Vec::new()
+            },
+            ..default_restore_args(restore_path.clone())
+        };
+        let restore_start = FileTime::now();
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore")?;
+
+        // The symlink itself gets its times and owner back, not its target
+        let restored_dir_path = restore_path.join("backup").join("dir");
+        let link_meta = restored_dir_path.join("link").symlink_metadata()?;
+        assert!(link_meta.is_symlink());
+        assert_eq!(
+            FileTime::from_last_modification_time(&link_meta),
+            link_mtime
+        );
+        let target_meta = restored_dir_path.join("target.txt").symlink_metadata()?;
+        assert_eq!(
+            FileTime::from_last_modification_time(&target_meta),
+            FileTime::from_last_modification_time(&dir_path.join("target.txt").metadata()?)
+        );
+        if is_root {
+            assert_eq!(link_meta.uid(), mapped_uid);
+            assert_eq!(target_meta.uid(), mapped_uid);
+        } else {
+            assert_eq!(link_meta.uid(), uid);
+        }
+
+        // The directory gets its modification time back. The access time is not saved in
+        // snapshots, so it is not overwritten with the modification time.
+        let dir_meta = restored_dir_path.metadata()?;
+        assert_eq!(FileTime::from_last_modification_time(&dir_meta), dir_mtime);
+        assert!(
+            FileTime::from_last_access_time(&dir_meta).unix_seconds()
+                >= restore_start.unix_seconds() - 1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_verify_only() -> Result<()> {
+        use filetime::{FileTime, set_file_mtime};
+        use mapache::restorer::{Restorer, owners::OwnerOptions};
+
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        let backup_data_tmp_path = tmp_path.join("backup");
+        std::fs::create_dir_all(backup_data_tmp_path.join("sub"))?;
+        let mut data = vec![0u8; 1024 * 1024];
+        rand::rng().fill_bytes(&mut data);
+        std::fs::write(backup_data_tmp_path.join("a.bin"), &data)?;
+        std::fs::write(backup_data_tmp_path.join("b.txt"), "mapache")?;
+        std::fs::write(backup_data_tmp_path.join("sub").join("c.txt"), "tejon")?;
+
+        let repo = String::from("repo");
+        let repo_path = tmp_path.join(&repo);
+        let backend = Arc::new(LocalFS::new(repo_path.clone()));
+
+        let global = default_global_args(&repo_path, password_path);
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+
+        // Run snapshot
+        let snapshot_args = default_snapshot_args(vec![backup_data_tmp_path.clone()]);
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        // Restore and verify the result
+        let restore_path = tmp_path.join("restore");
+        let mut restore_args = default_restore_args(restore_path.clone());
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore")?;
+
+        restore_args.verify_only = true;
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to verify restored target")?;
+
+        // Change the contents of a file keeping its size and modification time, delete a file and
+        // add an extra one
+        let restored_data_path = restore_path.join("backup");
+        let a_path = restored_data_path.join("a.bin");
+        let a_mtime = FileTime::from_last_modification_time(&std::fs::metadata(&a_path)?);
+        data[512 * 1024] ^= 0xFF;
+        std::fs::write(&a_path, &data)?;
+        set_file_mtime(&a_path, a_mtime)?;
+        std::fs::remove_file(restored_data_path.join("b.txt"))?;
+        std::fs::write(restored_data_path.join("sub").join("extra.txt"), "extra")?;
+
+        assert!(commands::cmd_restore::run(&global, &restore_args).is_err());
+
+        let (repo, _) = Repository::try_open(
+            Some(password.to_string()),
+            None,
+            backend,
+            RepoConfig::default(),
+        )?;
+        let (_, snapshot) = SnapshotStreamer::new(repo.clone())?
+            .latest()
+            .expect("There should be at least one snapshot");
+
+        // The directories containing the deleted and extra files were also modified
+        let check = Restorer::verify(
+            repo.clone(),
+            &snapshot,
+            &restore_path,
+            None,
+            None,
+            None,
+            &OwnerOptions::default(),
+        )?;
+        assert_eq!(check.missing, 1);
+        assert_eq!(check.extra, 1);
+        assert_eq!(check.differing, 3);
+        assert_eq!(check.matching, 1);
+
+        // Filters apply to both the snapshot and the target. Only the modified ancestors of the
+        // included file differ.
+        let check = Restorer::verify(
+            repo,
+            &snapshot,
+            &restore_path,
+            Some(vec![PathBuf::from("backup/sub/c.txt")]),
+            None,
+            None,
+            &OwnerOptions::default(),
+        )?;
+        assert_eq!(check.missing, 0);
+        assert_eq!(check.extra, 0);
+        assert_eq!(check.differing, 2);
+        assert_eq!(check.matching, 1);
+
+        Ok(())
+    }
 }
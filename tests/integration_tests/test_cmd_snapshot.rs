@@ -17,15 +17,22 @@
 #![cfg(test)]
 
 mod tests {
-    use std::path::PathBuf;
+    use std::{path::PathBuf, sync::Arc};
 
     use anyhow::{Context, Result};
     use mapache::{
+        backend::localfs::LocalFS,
         commands::{self, GlobalArgs, UseSnapshot, cmd_restore, cmd_snapshot},
-        global::{defaults::DEFAULT_DEFAULT_PACK_SIZE_MIB, set_global_opts_with_args},
+        global::{FileType, defaults::DEFAULT_DEFAULT_PACK_SIZE_MIB, set_global_opts_with_args},
+        repository::{
+            repo::{RepoConfig, Repository},
+            snapshot::SnapshotStreamer,
+            tree::ChangeDetection,
+        },
         restorer::Resolution,
     };
 
+    use rand::RngCore;
     use tempfile::tempdir;
 
     use crate::{
@@ -56,6 +63,8 @@ mod tests {
             verbosity: None,
             ssh_pubkey: None,
             ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
             pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
         };
         set_global_opts_with_args(&global);
@@ -71,12 +80,28 @@ mod tests {
                 backup_data_tmp_path.join("2"),
                 backup_data_tmp_path.join("file.txt"),
             ],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
             as_root: false,
             exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
             tags_str: String::new(),
             description: None,
             rescan: false,
             parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
             read_concurrency: 2,
             write_concurrency: 5,
             dry_run: false,
@@ -94,6 +119,16 @@ mod tests {
             exclude: None,
             strip_prefix: false,
             resolution: mapache::restorer::Resolution::Skip,
+            delta: false,
+            delete: false,
+            resume: false,
+            numeric_owner: false,
+            no_owner: false,
+            map_user: Vec::new(),
+            map_group: Vec::new(),
+            same_owner_as_target: false,
+            verify_only: false,
+            restore_concurrency: 4,
             no_verify: false,
         };
         commands::cmd_restore::run(&global, &restore_args)
@@ -161,6 +196,8 @@ mod tests {
             verbosity: None,
             ssh_pubkey: None,
             ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
             pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
         };
         set_global_opts_with_args(&global);
@@ -176,12 +213,28 @@ mod tests {
                 backup_data_tmp_path.join("2"),
                 backup_data_tmp_path.join("file.txt"),
             ],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
             as_root: false,
             exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
             tags_str: String::new(),
             description: None,
             rescan: false,
             parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
             read_concurrency: 2,
             write_concurrency: 5,
             dry_run: true,
@@ -207,6 +260,16 @@ mod tests {
             exclude: None,
             strip_prefix: false,
             resolution: mapache::restorer::Resolution::Skip,
+            delta: false,
+            delete: false,
+            resume: false,
+            numeric_owner: false,
+            no_owner: false,
+            map_user: Vec::new(),
+            map_group: Vec::new(),
+            same_owner_as_target: false,
+            verify_only: false,
+            restore_concurrency: 4,
             no_verify: false,
         };
 
@@ -239,6 +302,8 @@ mod tests {
             verbosity: None,
             ssh_pubkey: None,
             ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
             pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
         };
         set_global_opts_with_args(&global);
@@ -254,12 +319,28 @@ mod tests {
                 backup_data_tmp_path.join("2"),
                 backup_data_tmp_path.join("file.txt"),
             ],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
             as_root: false,
             exclude: Some(vec![backup_data_tmp_path.join("0/01")]),
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
             tags_str: String::new(),
             description: None,
             rescan: false,
             parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
             read_concurrency: 2,
             write_concurrency: 5,
             dry_run: false,
@@ -277,6 +358,16 @@ mod tests {
             exclude: None,
             strip_prefix: false,
             resolution: Resolution::Skip,
+            delta: false,
+            delete: false,
+            resume: false,
+            numeric_owner: false,
+            no_owner: false,
+            map_user: Vec::new(),
+            map_group: Vec::new(),
+            same_owner_as_target: false,
+            verify_only: false,
+            restore_concurrency: 4,
             no_verify: false,
         };
         commands::cmd_restore::run(&global, &restore_args)
@@ -343,6 +434,8 @@ mod tests {
             verbosity: None,
             ssh_pubkey: None,
             ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
             pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
         };
         set_global_opts_with_args(&global);
@@ -358,12 +451,28 @@ mod tests {
                 backup_data_tmp_path.join("2"),
                 backup_data_tmp_path.join("file.txt"),
             ],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
             as_root: false,
             exclude: Some(vec![backup_data_tmp_path.join("0/01")]),
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
             tags_str: String::new(),
             description: None,
             rescan: false,
             parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
             read_concurrency: 2,
             write_concurrency: 5,
             dry_run: false,
@@ -378,12 +487,28 @@ mod tests {
                 backup_data_tmp_path.join("2"),
                 backup_data_tmp_path.join("file.txt"),
             ],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
             as_root: false,
             exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
             tags_str: String::new(),
             description: None,
             rescan: false,
             parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
             read_concurrency: 2,
             write_concurrency: 5,
             dry_run: false,
@@ -400,6 +525,16 @@ mod tests {
             exclude: None,
             strip_prefix: false,
             resolution: mapache::restorer::Resolution::Skip,
+            delta: false,
+            delete: false,
+            resume: false,
+            numeric_owner: false,
+            no_owner: false,
+            map_user: Vec::new(),
+            map_group: Vec::new(),
+            same_owner_as_target: false,
+            verify_only: false,
+            restore_concurrency: 4,
             no_verify: false,
         };
         commands::cmd_restore::run(&global, &restore_args)
@@ -437,4 +572,668 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_snapshot_files_from() -> Result<()> {
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        let backup_data_path = test_utils::get_test_data_path(BACKUP_DATA_PATH);
+        let backup_data_tmp_path = tmp_path.join("backup");
+        test_utils::extract_tar_xz_archive(&backup_data_path, &backup_data_tmp_path)?;
+
+        let repo = String::from("repo");
+        let repo_path = tmp_path.join(&repo);
+
+        let global = GlobalArgs {
+            repo: repo_path.to_string_lossy().to_string(),
+            password_file: Some(password_path),
+            key: None,
+            quiet: true,
+            verbosity: None,
+            ssh_pubkey: None,
+            ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
+            pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
+        };
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+
+        // Write the path lists. `0/00` is nested in `0` and must not change the snapshot root.
+        let files_from_path = tmp_path.join("files_from");
+        std::fs::write(
+            &files_from_path,
+            format!(
+                "# Paths to backup\n\n  {}  \n{}\n",
+                backup_data_tmp_path.join("1").display(),
+                backup_data_tmp_path.join("0").join("00").display()
+            ),
+        )?;
+        let files_from_raw_path = tmp_path.join("files_from_raw");
+        std::fs::write(
+            &files_from_raw_path,
+            format!(
+                "{}\0{}\0",
+                backup_data_tmp_path.join("2").display(),
+                backup_data_tmp_path.join("file.txt").display()
+            ),
+        )?;
+
+        // Run snapshot
+        let snapshot_args = cmd_snapshot::CmdArgs {
+            paths: vec![backup_data_tmp_path.join("0")],
+            files_from: vec![files_from_path],
+            files_from_verbatim: Vec::new(),
+            files_from_raw: vec![files_from_raw_path],
+            as_root: false,
+            exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
+            tags_str: String::new(),
+            description: None,
+            rescan: false,
+            parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
+            read_concurrency: 2,
+            write_concurrency: 5,
+            dry_run: false,
+        };
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        // Run restore
+        let restore_path = tmp_path.join("restore");
+        let restore_args = cmd_restore::CmdArgs {
+            target: restore_path.clone(),
+            snapshot: UseSnapshot::Latest,
+            dry_run: false,
+            include: None,
+            exclude: None,
+            strip_prefix: false,
+            resolution: Resolution::Skip,
+            delta: false,
+            delete: false,
+            resume: false,
+            numeric_owner: false,
+            no_owner: false,
+            map_user: Vec::new(),
+            map_group: Vec::new(),
+            same_owner_as_target: false,
+            verify_only: false,
+            restore_concurrency: 4,
+            no_verify: false,
+        };
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore")?;
+
+        let paths = vec![
+            PathBuf::from("0"),
+            PathBuf::from("0/file0.txt"),
+            PathBuf::from("0/00"),
+            PathBuf::from("0/00/file00.txt"),
+            PathBuf::from("0/01/file01a.txt"),
+            PathBuf::from("1/10/file10.txt"),
+            PathBuf::from("2"),
+            PathBuf::from("file.txt"),
+        ];
+
+        for path in &paths {
+            let backup_path = backup_data_tmp_path.join(path);
+            let restored_path = restore_path.join(path);
+            assert!(restored_path.exists());
+
+            if restored_path.is_file() {
+                assert_eq!(std::fs::read(&restored_path)?, std::fs::read(&backup_path)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_with_checkpoints() -> Result<()> {
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        let backup_data_path = test_utils::get_test_data_path(BACKUP_DATA_PATH);
+        let backup_data_tmp_path = tmp_path.join("backup");
+        test_utils::extract_tar_xz_archive(&backup_data_path, &backup_data_tmp_path)?;
+
+        let repo = String::from("repo");
+        let repo_path = tmp_path.join(&repo);
+
+        let global = GlobalArgs {
+            repo: repo_path.to_string_lossy().to_string(),
+            password_file: Some(password_path),
+            key: None,
+            quiet: true,
+            verbosity: None,
+            ssh_pubkey: None,
+            ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
+            pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
+        };
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+
+        // Run snapshot, saving a checkpoint after every item
+        let snapshot_args = cmd_snapshot::CmdArgs {
+            paths: vec![backup_data_tmp_path.clone()],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
+            as_root: true,
+            exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
+            tags_str: String::new(),
+            description: None,
+            rescan: false,
+            parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::nanoseconds(1),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
+            read_concurrency: 2,
+            write_concurrency: 5,
+            dry_run: false,
+        };
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        // Checkpoints are removed once the snapshot is complete
+        assert_eq!(std::fs::read_dir(repo_path.join("snapshots"))?.count(), 1);
+
+        // Run restore
+        let restore_path = tmp_path.join("restore");
+        let restore_args = cmd_restore::CmdArgs {
+            target: restore_path.clone(),
+            snapshot: UseSnapshot::Latest,
+            dry_run: false,
+            include: None,
+            exclude: None,
+            strip_prefix: false,
+            resolution: Resolution::Skip,
+            delta: false,
+            delete: false,
+            resume: false,
+            numeric_owner: false,
+            no_owner: false,
+            map_user: Vec::new(),
+            map_group: Vec::new(),
+            same_owner_as_target: false,
+            verify_only: false,
+            restore_concurrency: 4,
+            no_verify: false,
+        };
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore")?;
+
+        let paths = vec![
+            PathBuf::from("0/file0.txt"),
+            PathBuf::from("0/00/file00.txt"),
+            PathBuf::from("0/01/file01a.txt"),
+            PathBuf::from("0/01/file01b.txt"),
+            PathBuf::from("1/10/file10.txt"),
+            PathBuf::from("2"),
+            PathBuf::from("file.txt"),
+        ];
+
+        for path in &paths {
+            let backup_path = backup_data_tmp_path.join(path);
+            let restored_path = restore_path.join(path);
+            assert!(restored_path.exists());
+
+            if restored_path.is_file() {
+                assert_eq!(std::fs::read(&restored_path)?, std::fs::read(&backup_path)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_resume_from_checkpoint() -> Result<()> {
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        let backup_data_tmp_path = tmp_path.join("backup");
+        std::fs::create_dir_all(&backup_data_tmp_path)?;
+        std::fs::write(backup_data_tmp_path.join("a.txt"), "mapache")?;
+
+        let repo = String::from("repo");
+        let repo_path = tmp_path.join(&repo);
+        let backend = Arc::new(LocalFS::new(repo_path.clone()));
+
+        let global = GlobalArgs {
+            repo: repo_path.to_string_lossy().to_string(),
+            password_file: Some(password_path),
+            key: None,
+            quiet: true,
+            verbosity: None,
+            ssh_pubkey: None,
+            ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
+            pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
+        };
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+
+        // Run a first snapshot
+        let snapshot_args = cmd_snapshot::CmdArgs {
+            paths: vec![backup_data_tmp_path.clone()],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
+            as_root: false,
+            exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
+            tags_str: String::new(),
+            description: None,
+            rescan: false,
+            parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: None,
+            ionice_class: None,
+            max_cpu_threads: None,
+            host: None,
+            read_concurrency: 2,
+            write_concurrency: 5,
+            dry_run: false,
+        };
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        let (repo, _) = Repository::try_open(
+            Some(password.to_string()),
+            None,
+            backend,
+            RepoConfig::default(),
+        )?;
+        let (first_id, _) = SnapshotStreamer::new(repo.clone())?
+            .latest()
+            .expect("There should be at least one snapshot");
+
+        // Simulate a snapshot interrupted after saving all its data: its last checkpoint is left
+        // behind, and the final snapshot is never saved
+        let mut data = vec![0u8; 4 * 1024 * 1024];
+        rand::rng().fill_bytes(&mut data);
+        std::fs::write(backup_data_tmp_path.join("b.bin"), &data)?;
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        let (interrupted_id, mut checkpoint) = SnapshotStreamer::new(repo.clone())?
+            .latest()
+            .expect("There should be at least one snapshot");
+        checkpoint.checkpoint = true;
+        let (checkpoint_id, _, _) = repo.save_file(
+            FileType::Snapshot,
+            serde_json::to_string(&checkpoint)?.as_bytes(),
+        )?;
+        repo.remove_snapshot(&interrupted_id)?;
+
+        // Run the snapshot again. The checkpoint is used as parent, so the files it contains are
+        // neither read nor uploaded again.
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        let snapshot_ids = repo.list_snapshot_ids()?;
+        assert_eq!(snapshot_ids.len(), 2);
+        assert!(snapshot_ids.contains(&first_id));
+        assert!(!snapshot_ids.contains(&checkpoint_id));
+
+        let (_, resumed) = SnapshotStreamer::new(repo.clone())?
+            .latest()
+            .expect("There should be at least one snapshot");
+        assert!(!resumed.checkpoint);
+        assert_eq!(resumed.parent, Some(first_id));
+        assert_eq!(resumed.summary.diff_counts.new_files, 0);
+        assert_eq!(resumed.summary.diff_counts.changed_files, 0);
+        assert_eq!(resumed.summary.diff_counts.unchanged_files, 2);
+        assert_eq!(resumed.summary.raw_bytes, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_large_file() -> Result<()> {
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        // A file large enough to be split in many chunks, saved in parallel batches
+        let backup_data_tmp_path = tmp_path.join("backup");
+        std::fs::create_dir_all(&backup_data_tmp_path)?;
+        let mut data = vec![0u8; 24 * 1024 * 1024];
+        rand::rng().fill_bytes(&mut data);
+        std::fs::write(backup_data_tmp_path.join("large.bin"), &data)?;
+
+        let repo = String::from("repo");
+        let repo_path = tmp_path.join(&repo);
+
+        let global = GlobalArgs {
+            repo: repo_path.to_string_lossy().to_string(),
+            password_file: Some(password_path),
+            key: None,
+            quiet: true,
+            verbosity: None,
+            ssh_pubkey: None,
+            ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
+            pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
+        };
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+
+        // Run snapshot
+        let snapshot_args = cmd_snapshot::CmdArgs {
+            paths: vec![backup_data_tmp_path.clone()],
+            files_from: Vec::new(),
+            files_from_verbatim: Vec::new(),
+            files_from_raw: Vec::new(),
+            as_root: false,
+            exclude: None,
+            exclude_caches: false,
+            exclude_if_present: Vec::new(),
+            one_file_system: false,
+            exclude_larger_than: None,
+            tags_str: String::new(),
+            description: None,
+            rescan: false,
+            parent: UseSnapshot::Latest,
+            checkpoint_interval: chrono::Duration::zero(),
+            changed_file_retries: 2,
+            change_detection: ChangeDetection::Default,
+            compression: None,
+            limit_read: None,
+            nice: Some(10),
+            ionice_class: None,
+            max_cpu_threads: Some(2),
+            host: None,
+            read_concurrency: 4,
+            write_concurrency: 5,
+            dry_run: false,
+        };
+        commands::cmd_snapshot::run(&global, &snapshot_args)
+            .with_context(|| "Failed to run cmd_snapshot")?;
+
+        // Run restore
+        let restore_path = tmp_path.join("restore");
+        let restore_args = cmd_restore::CmdArgs {
+            target: restore_path.clone(),
+            snapshot: UseSnapshot::Latest,
+            dry_run: false,
+            include: None,
+            exclude: None,
+            strip_prefix: false,
+            resolution: Resolution::Skip,
+            delta: false,
+            delete: false,
+            resume: false,
+            numeric_owner: false,
+            no_owner: false,
+            map_user: Vec::new(),
+            map_group: Vec::new(),
+            same_owner_as_target: false,
+            verify_only: false,
+            restore_concurrency: 4,
+            no_verify: false,
+        };
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore")?;
+
+        // The chunks must be restored in their original order
+        assert_eq!(
+            std::fs::read(restore_path.join("backup").join("large.bin"))?,
+            data
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_mixed_compression() -> Result<()> {
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        let backup_data_tmp_path = tmp_path.join("backup");
+        std::fs::create_dir_all(&backup_data_tmp_path)?;
+        let text = "mapache backs up your data. ".repeat(4096);
+        std::fs::write(backup_data_tmp_path.join("file_lz4.txt"), &text)?;
+
+        let repo = String::from("repo");
+        let repo_path = tmp_path.join(&repo);
+
+        let global = GlobalArgs {
+            repo: repo_path.to_string_lossy().to_string(),
+            password_file: Some(password_path),
+            key: None,
+            quiet: true,
+            verbosity: None,
+            ssh_pubkey: None,
+            ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
+            pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
+        };
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+
+        // Each snapshot uses a different compression algorithm
+        for (file_name, compression) in [
+            ("file_lz4.txt", "lz4"),
+            ("file_zstd_long.txt", "zstd-long:5"),
+            ("file_none.txt", "none"),
+        ] {
+            std::fs::write(
+                backup_data_tmp_path.join(file_name),
+                format!("{file_name}: {text}"),
+            )?;
+
+            let snapshot_args = cmd_snapshot::CmdArgs {
+                paths: vec![backup_data_tmp_path.clone()],
+                files_from: Vec::new(),
+                files_from_verbatim: Vec::new(),
+                files_from_raw: Vec::new(),
+                as_root: false,
+                exclude: None,
+                exclude_caches: false,
+                exclude_if_present: Vec::new(),
+                one_file_system: false,
+                exclude_larger_than: None,
+                tags_str: String::new(),
+                description: None,
+                rescan: false,
+                parent: UseSnapshot::Latest,
+                checkpoint_interval: chrono::Duration::zero(),
+                changed_file_retries: 2,
+                change_detection: ChangeDetection::Default,
+                compression: Some(compression.parse()?),
+                limit_read: None,
+                nice: None,
+                ionice_class: None,
+                max_cpu_threads: None,
+                host: None,
+                read_concurrency: 2,
+                write_concurrency: 5,
+                dry_run: false,
+            };
+            commands::cmd_snapshot::run(&global, &snapshot_args)
+                .with_context(|| "Failed to run cmd_snapshot")?;
+        }
+
+        // Run restore
+        let restore_path = tmp_path.join("restore");
+        let restore_args = cmd_restore::CmdArgs {
+            target: restore_path.clone(),
+            snapshot: UseSnapshot::Latest,
+            dry_run: false,
+            include: None,
+            exclude: None,
+            strip_prefix: false,
+            resolution: Resolution::Skip,
+            delta: false,
+            delete: false,
+            resume: false,
+            numeric_owner: false,
+            no_owner: false,
+            map_user: Vec::new(),
+            map_group: Vec::new(),
+            same_owner_as_target: false,
+            verify_only: false,
+            restore_concurrency: 4,
+            no_verify: false,
+        };
+        commands::cmd_restore::run(&global, &restore_args)
+            .with_context(|| "Failed to run cmd_restore")?;
+
+        for file_name in ["file_lz4.txt", "file_zstd_long.txt", "file_none.txt"] {
+            assert_eq!(
+                std::fs::read(restore_path.join("backup").join(file_name))?,
+                std::fs::read(backup_data_tmp_path.join(file_name))?
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_hosts() -> Result<()> {
+        let tmp_dir = tempdir()?;
+        let tmp_path = tmp_dir.path();
+        let password = "mapachito";
+        let password_path = tmp_path.join("password");
+        std::fs::write(&password_path, password)?;
+
+        let backup_data_tmp_path = tmp_path.join("backup");
+        std::fs::create_dir_all(&backup_data_tmp_path)?;
+        std::fs::write(backup_data_tmp_path.join("file.txt"), "mapache")?;
+
+        let repo_path = tmp_path.join(String::from("repo"));
+        let backend = Arc::new(LocalFS::new(repo_path.clone()));
+
+        let global = GlobalArgs {
+            repo: repo_path.to_string_lossy().to_string(),
+            password_file: Some(password_path),
+            key: None,
+            quiet: true,
+            verbosity: None,
+            ssh_pubkey: None,
+            ssh_privatekey: None,
+            limit_upload: None,
+            limit_download: None,
+            pack_size_mib: DEFAULT_DEFAULT_PACK_SIZE_MIB,
+        };
+        set_global_opts_with_args(&global);
+
+        // Init repo
+        init_repo(password, repo_path.clone())?;
+        let (repo, _) = Repository::try_open(
+            Some(password.to_string()),
+            None,
+            backend,
+            RepoConfig::default(),
+        )?;
+
+        // Snapshots of two hosts, alternating
+        let mut snapshot_ids = Vec::new();
+        for host in ["alpha", "beta", "alpha"] {
+            let snapshot_args = cmd_snapshot::CmdArgs {
+                paths: vec![backup_data_tmp_path.clone()],
+                files_from: Vec::new(),
+                files_from_verbatim: Vec::new(),
+                files_from_raw: Vec::new(),
+                as_root: false,
+                exclude: None,
+                exclude_caches: false,
+                exclude_if_present: Vec::new(),
+                one_file_system: false,
+                exclude_larger_than: None,
+                tags_str: String::new(),
+                description: None,
+                rescan: false,
+                parent: UseSnapshot::Latest,
+                checkpoint_interval: chrono::Duration::zero(),
+                changed_file_retries: 2,
+                change_detection: ChangeDetection::Default,
+                compression: None,
+                limit_read: None,
+                nice: None,
+                ionice_class: None,
+                max_cpu_threads: None,
+                host: Some(host.to_string()),
+                read_concurrency: 2,
+                write_concurrency: 5,
+                dry_run: false,
+            };
+            commands::cmd_snapshot::run(&global, &snapshot_args)
+                .with_context(|| "Failed to run cmd_snapshot")?;
+
+            let (id, snapshot) = SnapshotStreamer::new(repo.clone())?
+                .latest()
+                .expect("There should be at least one snapshot");
+            assert_eq!(snapshot.hostname, host);
+            assert!(!snapshot.program_version.is_empty());
+            snapshot_ids.push((id, snapshot));
+        }
+
+        // The parent is the latest snapshot of the same host
+        assert!(snapshot_ids[0].1.parent.is_none());
+        assert!(snapshot_ids[1].1.parent.is_none());
+        assert_eq!(snapshot_ids[2].1.parent, Some(snapshot_ids[0].0.clone()));
+
+        Ok(())
+    }
 }